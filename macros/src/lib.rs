@@ -127,7 +127,7 @@ pub fn register_icons(attr: TokenStream2, item: TokenStream2) -> syn::Result<Tok
                     LitStr::new(&format!("{svgs_str}/{svg_name_str}.svg"), svg_name_span);
 
                 quote!(
-                    #ident: #egui_root::include_image!(#svg_path)
+                    #ident: white(#egui_root::include_image!(#svg_path))
                 )
             });
 
@@ -147,6 +147,23 @@ pub fn register_icons(attr: TokenStream2, item: TokenStream2) -> syn::Result<Tok
                     }
                 }
             }
+
+            /// The SVGs are drawn in `currentColor`, which renders black.
+            /// White ones take their color from `Image::tint`
+            fn white(source: #image_source) -> #image_source {
+                match source {
+                    #egui_root::ImageSource::Bytes { uri, bytes } => {
+                        let svg = String::from_utf8_lossy(bytes.as_ref())
+                            .replace("currentColor", "white");
+
+                        #egui_root::ImageSource::Bytes {
+                            uri,
+                            bytes: svg.into_bytes().into(),
+                        }
+                    }
+                    source => source,
+                }
+            }
         }
     };
 
@@ -1,9 +1,13 @@
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
-use catppuccin_egui::{set_theme, Theme};
 use eframe::{
-    egui::{CentralPanel, Context, FontDefinitions, RichText, TopBottomPanel},
-    CreationContext,
+    egui::{Button, CentralPanel, Context, FontDefinitions, RichText, TopBottomPanel, Ui, Window},
+    CreationContext, Storage,
 };
 use egui_dock::{DockArea, DockState, NodeIndex};
 use egui_phosphor::{add_to_fonts, Variant};
@@ -11,23 +15,81 @@ use egui_tracing::EventCollector;
 
 use crate::{
     components::{
-        button::RSFMButtonState,
+        dnd::{self, DropAction, PendingDrop},
         dock::{
-            dir_view::DirViewState,
-            sidebar::{SidebarButtonState, SidebarState},
-            DockTab, DockTabViewer,
+            active_dir_view_mut, commander::CommanderState, dir_view::DirViewState, find_dock_tab,
+            for_each_dock_tab_mut, settings::Settings, sidebar::SidebarState, DockAction, DockTab,
+            DockTabViewer,
         },
+        toasts,
+    },
+    config::{self, Accent, Bookmark, Config, ConfigWatcher, ConnectionProfile},
+    session::{SessionExists, Sessions, DOCK_STATE_KEY, SESSIONS_KEY},
+    utils::{
+        archive,
+        color_scheme::{ColorScheme, SystemColorScheme},
+        devices::{default_backend, BlockDevice, DeviceManager, DeviceRequest, DeviceResponse},
+        fs::FileData,
+        hidden,
+        icons::PhosphorIcon,
+        jobs::{JobOutcome, Jobs},
+        journal::{self, Journal, Operation},
+        ls_colors,
+        mounts::{Mount, MountWatcher, MountsUpdate},
+        network::{NetworkManager, NetworkResponse},
+        paths::Paths,
+        sorting,
+        theme::{Theme, ThemeExt},
+        thumbnails, trash,
+        vfs::{Location, TransferMode, UnknownHostKey},
     },
-    config::Config,
-    utils::{fs::FileData, icons::PhosphorIcon, paths::Paths},
 };
 
 pub struct App {
     log_event_collector: EventCollector,
 
     config: Config,
+    /// Hides the banner about a config file that failed to load
+    config_error_dismissed: bool,
+    config_watcher: Option<ConfigWatcher>,
+    /// Theme picked in the settings tab but not saved yet
+    preview_theme: Option<(config::Theme, Accent)>,
+    /// Theme hovered in the header's theme menu, previewed over everything else
+    hover_theme: Option<(config::Theme, Accent)>,
+    /// Resolved from the config or the preview
+    theme: Theme,
+    /// Names of the user themes in the config dir
+    custom_themes: Vec<String>,
+    system_color_scheme: SystemColorScheme,
+    /// Picks the auto theme's flavour
+    color_scheme: ColorScheme,
     paths: Paths,
     dock_state: DockState<DockTab>,
+
+    sessions: Sessions,
+    new_session_name: String,
+    /// A session name that's taken, waiting for the user to confirm replacing it
+    replace_session: Option<String>,
+
+    dock_actions: Vec<DockAction>,
+
+    mounts: Vec<Mount>,
+    mount_watcher: Option<MountWatcher>,
+    /// Plugging in a device changes these before anything gets mounted
+    block_devices: Vec<String>,
+    device_manager: DeviceManager,
+    unmounted_devices: Vec<BlockDevice>,
+
+    jobs: Jobs,
+    /// File operations that can be undone
+    /// Shared with the jobs undoing and redoing
+    journal: Arc<Mutex<Journal>>,
+
+    network: NetworkManager,
+    /// A connection waiting for the user to trust the server's key
+    host_key_prompt: Option<(ConnectionProfile, UnknownHostKey)>,
+    /// Where each connected profile opened, by profile name
+    connections: HashMap<String, Location>,
 }
 
 impl App {
@@ -37,6 +99,7 @@ impl App {
         log_event_collector: EventCollector,
     ) -> Self {
         egui_extras::install_image_loaders(&cc.egui_ctx);
+        thumbnails::install(&cc.egui_ctx);
 
         let mut fonts = FontDefinitions::default();
         add_to_fonts(&mut fonts, Variant::Regular);
@@ -46,59 +109,1117 @@ impl App {
         let config = Config::load(override_config);
         let paths = Paths::load(config.overrides().paths());
 
-        set_theme(&cc.egui_ctx, (*config.theme()).into());
+        let color_scheme = cc
+            .integration_info
+            .system_theme
+            .map_or(ColorScheme::Dark, Into::into);
+        let theme = Self::resolve_theme(&cc.egui_ctx, &config, None, color_scheme);
+        let custom_themes = config.custom_themes();
+        Self::load_listing_rules(&cc.egui_ctx, &config);
+        config
+            .warnings()
+            .iter()
+            .for_each(|warning| toasts::warning(&cc.egui_ctx, warning));
+
+        let config_watcher = match ConfigWatcher::new(cc.egui_ctx.clone(), &config) {
+            Ok(config_watcher) => Some(config_watcher),
+            Err(err) => {
+                tracing::error!("Failed to watch the config file: {err}");
+                None
+            }
+        };
+
+        let dock_state = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, DOCK_STATE_KEY))
+            .unwrap_or_else(|| Self::default_dock_state(&paths, &config));
+        let sessions = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, SESSIONS_KEY))
+            .unwrap_or_default();
+
+        let mount_watcher = match MountWatcher::new(cc.egui_ctx.clone()) {
+            Ok(mount_watcher) => Some(mount_watcher),
+            Err(err) => {
+                tracing::error!("Failed to watch mounts: {err}");
+                None
+            }
+        };
+
+        let mut res = Self {
+            log_event_collector,
+
+            paths,
+            config,
+            config_error_dismissed: false,
+            config_watcher,
+            preview_theme: None,
+            hover_theme: None,
+            theme,
+            custom_themes,
+            system_color_scheme: SystemColorScheme::new(cc.egui_ctx.clone()),
+            color_scheme,
+            dock_state,
+
+            sessions,
+            new_session_name: String::new(),
+            replace_session: None,
+
+            dock_actions: vec![],
+
+            mounts: vec![],
+            mount_watcher,
+            block_devices: vec![],
+            device_manager: DeviceManager::new(default_backend(), cc.egui_ctx.clone()),
+            unmounted_devices: vec![],
+
+            jobs: Jobs::new(cc.egui_ctx.clone()),
+            journal: Arc::default(),
+
+            network: NetworkManager::new(cc.egui_ctx.clone()),
+            host_key_prompt: None,
+            connections: HashMap::new(),
+        };
+        res.sync_sidebars();
+
+        res
+    }
+
+    /// Sidebar contents come from the paths and the config rather than the session,
+    /// push them to every sidebar
+    fn sync_sidebars(&mut self) {
+        let bookmarks = self.config.bookmarks();
+        let connected = self.connections.keys().cloned().collect::<HashSet<_>>();
+
+        for_each_dock_tab_mut(&mut self.dock_state, |tab| {
+            if let DockTab::Sidebar(sidebar_state) = tab {
+                sidebar_state.set_user_dirs(&self.paths);
+                sidebar_state.set_bookmarks(bookmarks);
+                sidebar_state.set_mounts(&self.mounts);
+                sidebar_state.set_unmounted_devices(&self.unmounted_devices);
+                sidebar_state.set_connections(self.config.connections(), &connected);
+            }
+        });
+    }
+
+    fn update_mounts(&mut self) {
+        let Some(MountsUpdate {
+            mounts,
+            block_devices,
+        }) = self.mount_watcher.as_ref().and_then(MountWatcher::latest)
+        else {
+            return;
+        };
+
+        let mount_points_changed = mounts.len() != self.mounts.len()
+            || mounts
+                .iter()
+                .zip(self.mounts.iter())
+                .any(|(m1, m2)| m1.mount_point != m2.mount_point);
+        if mount_points_changed || block_devices != self.block_devices {
+            self.device_manager.request(DeviceRequest::List);
+        }
+
+        self.mounts = mounts;
+        self.block_devices = block_devices;
+
+        for_each_dock_tab_mut(&mut self.dock_state, |tab| {
+            if let DockTab::Sidebar(sidebar_state) = tab {
+                sidebar_state.set_mounts(&self.mounts);
+            }
+        });
+    }
+
+    fn sync_bookmarks(&mut self) {
+        let bookmarks = self.config.bookmarks();
+
+        for_each_dock_tab_mut(&mut self.dock_state, |tab| {
+            if let DockTab::Sidebar(sidebar_state) = tab {
+                sidebar_state.set_bookmarks(bookmarks);
+            }
+        });
+    }
+
+    fn sync_connections(&mut self) {
+        let connected = self.connections.keys().cloned().collect::<HashSet<_>>();
+
+        for_each_dock_tab_mut(&mut self.dock_state, |tab| {
+            if let DockTab::Sidebar(sidebar_state) = tab {
+                sidebar_state.set_connections(self.config.connections(), &connected);
+            }
+        });
+    }
+
+    fn add_bookmark(&mut self, path: PathBuf) {
+        if self.config.bookmarks().iter().any(|b| b.path == path) {
+            return;
+        }
+
+        let mut bookmarks = self.config.bookmarks().clone();
+        bookmarks.push(Bookmark::new(path));
+
+        self.config.set_bookmarks(bookmarks);
+        self.sync_bookmarks();
+    }
+
+    fn handle_device_responses(&mut self, ctx: &Context) {
+        self.device_manager
+            .responses()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .for_each(|response| match response {
+                DeviceResponse::Devices(devices) => {
+                    self.unmounted_devices = devices;
+
+                    for_each_dock_tab_mut(&mut self.dock_state, |tab| {
+                        if let DockTab::Sidebar(sidebar_state) = tab {
+                            sidebar_state.set_unmounted_devices(&self.unmounted_devices);
+                        }
+                    });
+                }
+                DeviceResponse::Mounted {
+                    device,
+                    mount_point,
+                } => {
+                    toasts::info(
+                        ctx,
+                        format!("Mounted {} at {}", device.display(), mount_point.display()),
+                    );
+                    self.dock_actions
+                        .push(DockAction::Navigate(mount_point.into()));
+                }
+                DeviceResponse::Unmounted(device) => {
+                    toasts::info(ctx, format!("Unmounted {}", device.display()));
+                }
+                DeviceResponse::Ejected(device) => {
+                    toasts::info(ctx, format!("{} can be safely removed", device.display()));
+                }
+                DeviceResponse::Failed { action, err } => {
+                    toasts::error(ctx, format!("Failed to {action}: {err}"));
+                }
+            });
+    }
+
+    fn handle_network_responses(&mut self, ctx: &Context) {
+        let responses = self.network.responses().collect::<Vec<_>>();
+        if responses.is_empty() {
+            return;
+        }
+
+        responses.into_iter().for_each(|response| match response {
+            NetworkResponse::Connected { name, location } => {
+                toasts::info(ctx, format!("Connected to {name}"));
+                self.dock_actions
+                    .push(DockAction::Navigate(location.clone()));
+                self.connections.insert(name, location);
+            }
+            NetworkResponse::UnknownHostKey { profile, key } => {
+                self.host_key_prompt = Some((*profile, key));
+            }
+            NetworkResponse::Failed { name, err } => {
+                toasts::error(ctx, format!("Failed to connect to {name}: {err}"));
+            }
+        });
+
+        self.sync_connections();
+    }
+
+    /// Asks whether to trust a server whose key isn't known yet, showing its fingerprint
+    fn host_key_window(&mut self, ctx: &Context) {
+        let Some((profile, key)) = &self.host_key_prompt else {
+            return;
+        };
+
+        let mut open = true;
+        let mut trust = false;
+        let mut cancel = false;
+
+        Window::new(format!("Connecting to {}", profile.name))
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{}:{} isn't a known host. Its {} key fingerprint is",
+                    key.host,
+                    key.port,
+                    key.algorithm()
+                ));
+                ui.monospace(key.fingerprint());
+                ui.label(
+                    "Only connect if you can confirm the fingerprint with the server's owner.",
+                );
+
+                ui.horizontal(|ui| {
+                    trust = ui.button("Trust and connect").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+
+        if trust {
+            match key.trust() {
+                Ok(()) => self.network.connect(profile.clone()),
+                Err(err) => toasts::error(ctx, format!("Failed to remember {}: {err}", key.host)),
+            }
+        }
+        if trust || cancel || !open {
+            self.host_key_prompt = None;
+        }
+    }
+
+    fn connect(&mut self, ctx: &Context, profile: ConnectionProfile) {
+        match self.connections.get(&profile.name) {
+            Some(location) => self
+                .dock_actions
+                .push(DockAction::Navigate(location.clone())),
+            None => {
+                toasts::info(ctx, format!("Connecting to {}", profile.name));
+                self.network.connect(profile);
+            }
+        }
+    }
+
+    fn transfer(&mut self, sources: Vec<Location>, dest_dir: Location, mode: TransferMode) {
+        let title = match sources.as_slice() {
+            [source] => format!("{} {}", mode.verb(), file_name(source.path())),
+            _ => format!("{} {} items", mode.verb(), sources.len()),
+        };
+
+        let mut dirs = vec![dest_dir.clone()];
+        if let TransferMode::Move = mode {
+            dirs.extend(sources.iter().filter_map(Location::parent));
+        }
+
+        let done = match mode {
+            TransferMode::Copy => "Copied",
+            TransferMode::Move => "Moved",
+        };
+
+        self.jobs.spawn(title, dirs, move |progress| {
+            sources.iter().try_for_each(|source| {
+                let name = source.path().file_name().unwrap_or_default();
+                let to = dest_dir.join(name);
+                mode.run(source, &to, progress)?;
+
+                progress.record(match mode {
+                    TransferMode::Copy => Operation::copied(source.clone(), to),
+                    TransferMode::Move => Operation::moved(source.clone(), to),
+                });
+                Ok::<_, std::io::Error>(())
+            })?;
+
+            Ok(format!("{done} {} items to {dest_dir}", sources.len()))
+        });
+    }
+
+    /// Like [`Self::transfer`], for paths dropped onto a directory
+    fn run_drop(&mut self, drop: PendingDrop) {
+        let count = drop.sources().count();
+        if count == 0 {
+            return;
+        }
+
+        let title = match drop.sources().next() {
+            Some(path) if count == 1 => format!("{} {}", drop.action.verb(), file_name(path)),
+            _ => format!("{} {count} items", drop.action.verb()),
+        };
+
+        let mut dirs = vec![Location::local(drop.dir.clone())];
+        if let DropAction::Move = drop.action {
+            dirs.extend(
+                drop.sources()
+                    .filter_map(|path| path.parent())
+                    .map(|parent| Location::local(parent.to_path_buf())),
+            );
+        }
+
+        let done = match drop.action {
+            DropAction::Copy => "Copied",
+            DropAction::Move => "Moved",
+        };
+
+        self.jobs.spawn(title, dirs, move |progress| {
+            drop.run(progress)?;
+            Ok(format!("{done} {count} items to {}", drop.dir.display()))
+        });
+    }
+
+    fn handle_finished_jobs(&mut self, ctx: &Context) {
+        self.jobs.finished().into_iter().for_each(|job| {
+            match job.outcome {
+                JobOutcome::Done(message) => toasts::info(ctx, message),
+                JobOutcome::Cancelled(message) => toasts::warning(ctx, message),
+                JobOutcome::Failed(message) => toasts::error(ctx, message),
+            }
+
+            if let Some(operation) = job.operation {
+                self.journal
+                    .lock()
+                    .expect("Journal poisoned")
+                    .record(operation);
+            }
+            self.reload_dirs(&job.dirs);
+        });
+    }
+
+    /// Reloads every directory view showing one of `dirs`
+    fn reload_dirs(&mut self, dirs: &[Location]) {
+        for_each_dock_tab_mut(&mut self.dock_state, |tab| {
+            tab.dir_views_mut()
+                .into_iter()
+                .filter(|dir_view_state| dirs.contains(dir_view_state.file_data().location()))
+                .for_each(|dir_view_state| dir_view_state.reload());
+        });
+    }
+
+    fn undo(&mut self, ctx: &Context, steps: usize) {
+        self.replay(ctx, steps, false);
+    }
+
+    fn redo(&mut self, ctx: &Context, steps: usize) {
+        self.replay(ctx, steps, true);
+    }
+
+    /// Undoes or redoes up to `steps` operations in the background, stopping at the first one
+    /// that fails
+    fn replay(&mut self, ctx: &Context, steps: usize, redo: bool) {
+        let (verb, done) = match redo {
+            false => ("undo", "Undid"),
+            true => ("redo", "Redid"),
+        };
+
+        // The ones the job will get to, unless something else changes the journal first
+        let dirs = {
+            let journal = self.journal.lock().expect("Journal poisoned");
+            let entries = match redo {
+                false => journal.done(),
+                true => journal.undone(),
+            };
+            if entries.is_empty() {
+                toasts::info(ctx, format!("Nothing to {verb}"));
+                return;
+            }
 
-        let mut dock_state = DockState::new(vec![DockTab::Sidebar(
-            SidebarState::default().with_my_comp_buttons(vec![SidebarButtonState::new(
-                RSFMButtonState::default().with_text("Home"),
-                paths.home_dir(),
-            )]),
-        )]);
+            entries
+                .iter()
+                .rev()
+                .take(steps)
+                .flat_map(|entry| entry.operation.dirs())
+                .fold(vec![], |mut dirs, dir| {
+                    if !dirs.contains(&dir) {
+                        dirs.push(dir);
+                    }
+                    dirs
+                })
+        };
+
+        let journal = self.journal.clone();
+        self.jobs.spawn(verb, dirs, move |progress| {
+            progress.set_total(steps as u64);
+            let mut described = vec![];
+
+            for _ in 0..steps {
+                progress.check_cancelled()?;
+
+                match journal::replay(&journal, redo) {
+                    None => break,
+                    Some(Ok(operation)) => described.push(operation.describe()),
+                    Some(Err(err)) if described.is_empty() => return Err(err),
+                    Some(Err(err)) => {
+                        return Err(io::Error::new(
+                            err.kind(),
+                            format!("{err}. {done}: {}", described.join(", ")),
+                        ))
+                    }
+                }
+                progress.advance(1);
+            }
+
+            Ok(match described.as_slice() {
+                [operation] => format!("{done}: {operation}"),
+                operations => format!("{done} {} operations", operations.len()),
+            })
+        });
+    }
+
+    /// Moves local files to the trash in the background, as one operation for the journal
+    fn trash(&mut self, ctx: &Context, locations: Vec<Location>) {
+        let (locations, remote): (Vec<_>, Vec<_>) =
+            locations.into_iter().partition(Location::is_local);
+        remote.iter().for_each(|location| {
+            toasts::error(
+                ctx,
+                format!("Only local files can go to the trash, {location} isn't"),
+            )
+        });
+
+        let title = match locations.as_slice() {
+            [] => return,
+            [location] => format!("trash {}", file_name(location.path())),
+            _ => format!("trash {} items", locations.len()),
+        };
+        let dirs = locations
+            .iter()
+            .filter_map(Location::parent)
+            .fold(vec![], |mut dirs, dir| {
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+                dirs
+            });
+
+        self.jobs.spawn(title, dirs, move |progress| {
+            progress.set_total(locations.len() as u64);
+
+            locations.iter().try_for_each(|location| {
+                progress.check_cancelled()?;
+
+                let trashed = trash::trash(location.path())
+                    .map_err(|err| io::Error::new(err.kind(), format!("{location}: {err}")))?;
+                progress.record(Operation::trashed(trashed));
+                progress.advance(1);
+                Ok::<_, io::Error>(())
+            })?;
+
+            Ok(format!("Trashed {} items", locations.len()))
+        });
+    }
+
+    fn handle_dock_actions(&mut self, ctx: &Context) {
+        std::mem::take(&mut self.dock_actions)
+            .into_iter()
+            .for_each(|action| match action {
+                DockAction::Navigate(location) => match active_dir_view_mut(&mut self.dock_state) {
+                    Some(dir_view_state) => dir_view_state.navigate_to(location),
+                    None => tracing::warn!("No directory view to navigate to {location}"),
+                },
+                DockAction::BookmarkActiveDir => {
+                    if let Some(dir_view_state) = active_dir_view_mut(&mut self.dock_state) {
+                        let path = dir_view_state.file_data().path().clone();
+                        self.add_bookmark(path);
+                    }
+                }
+                DockAction::AddBookmark(path) => self.add_bookmark(path),
+                DockAction::SetBookmarks(bookmarks) => {
+                    self.config.set_bookmarks(bookmarks);
+                    self.sync_bookmarks();
+                }
+                DockAction::Mount(device) => {
+                    self.device_manager.request(DeviceRequest::Mount(device))
+                }
+                DockAction::Unmount(device) => {
+                    self.device_manager.request(DeviceRequest::Unmount(device))
+                }
+                DockAction::Eject(device) => {
+                    self.device_manager.request(DeviceRequest::Eject(device))
+                }
+                DockAction::Compress {
+                    sources,
+                    dest,
+                    options,
+                } => {
+                    let title = format!("compress {}", file_name(&dest));
+                    let dirs = dest.parent().map(Location::from).into_iter().collect();
+
+                    self.jobs.spawn(title, dirs, move |progress| {
+                        archive::compress(&sources, &dest, &options, progress)?;
+                        Ok(format!("Created {}", dest.display()))
+                    });
+                }
+                DockAction::Extract {
+                    archive,
+                    dest_dir,
+                    password,
+                } => {
+                    let title = format!("extract {}", file_name(&archive));
+
+                    self.jobs
+                        .spawn(title, vec![dest_dir.clone().into()], move |progress| {
+                            let root = archive::extract_all(
+                                &archive,
+                                &dest_dir,
+                                password.as_deref(),
+                                progress,
+                            )?;
+                            Ok(format!("Extracted to {}", root.display()))
+                        });
+                }
+                DockAction::ExtractMembers {
+                    archive,
+                    members,
+                    dest_dir,
+                } => {
+                    let title = match members.as_slice() {
+                        [member] => format!("extract {}", file_name(member)),
+                        _ => format!("extract {} items", members.len()),
+                    };
+
+                    self.jobs
+                        .spawn(title, vec![dest_dir.clone().into()], move |progress| {
+                            archive::extract(&archive, &members, &dest_dir, progress)?;
+                            Ok(format!("Extracted to {}", dest_dir.display()))
+                        });
+                }
+                DockAction::Transfer {
+                    sources,
+                    dest_dir,
+                    mode,
+                } => self.transfer(sources, dest_dir, mode),
+                DockAction::Download(location) => {
+                    let dest_dir = self
+                        .paths
+                        .downloads_dir()
+                        .clone()
+                        .unwrap_or_else(|| self.paths.home_dir().clone());
+
+                    self.transfer(vec![location], dest_dir.into(), TransferMode::Copy);
+                }
+                DockAction::Connect(profile) => self.connect(ctx, profile),
+                DockAction::Disconnect(profile) => {
+                    self.network.disconnect(&profile);
+                    self.connections.remove(&profile.name);
+                    self.sync_connections();
+                }
+                DockAction::SetConnections(connections) => {
+                    self.config.set_connections(connections);
+                    self.sync_connections();
+                }
+                DockAction::PreviewTheme(theme) => {
+                    self.preview_theme = theme;
+                    self.refresh_theme(ctx);
+                }
+                DockAction::SaveSettings(file) => {
+                    let saved = self.config.set_file(*file);
+                    self.preview_theme = None;
+                    self.apply_config(ctx);
+                    match saved {
+                        Ok(()) => toasts::info(ctx, "Saved the settings"),
+                        Err(err) => toasts::error(
+                            ctx,
+                            format!("The settings are applied but weren't saved: {err}"),
+                        ),
+                    }
+                }
+                DockAction::DiscardSettings => {
+                    Settings::discard(ctx);
+                    self.preview_theme = None;
+                    self.refresh_theme(ctx);
+                }
+                DockAction::Record(operation) => self
+                    .journal
+                    .lock()
+                    .expect("Journal poisoned")
+                    .record(operation),
+                DockAction::Trash(locations) => self.trash(ctx, locations),
+                DockAction::Undo(steps) => self.undo(ctx, steps),
+                DockAction::Redo(steps) => self.redo(ctx, steps),
+            });
+    }
+
+    fn default_dock_state(paths: &Paths, config: &Config) -> DockState<DockTab> {
+        let mut dock_state = DockState::new(vec![DockTab::Sidebar(SidebarState::default())]);
         let surface = dock_state.main_surface_mut();
 
         let [_sidebar, dir_view] = surface.split_right(
             NodeIndex::root(),
             0.15,
-            vec![DockTab::DirView(DirViewState::new(FileData::new(
-                paths.home_dir(),
+            vec![DockTab::DirView(Box::new(DirViewState::with_view(
+                FileData::new(paths.home_dir()),
+                config.view(),
             )))],
         );
         let [dir_view, _terminal_log] =
             surface.split_below(dir_view, 0.8, vec![DockTab::Terminal, DockTab::Log]);
         let [_dir_view, _info] = surface.split_right(dir_view, 0.8, vec![DockTab::InfoSidebar]);
 
-        Self {
-            log_event_collector,
+        dock_state
+    }
 
-            paths,
-            config,
-            dock_state,
+    fn sessions_menu(&mut self, ui: &mut Ui) {
+        ui.menu_button(
+            RichText::new(format!(
+                "{} {}",
+                PhosphorIcon::FloppyDisk.symbol(),
+                self.sessions.active()
+            )),
+            |ui| {
+                let mut switch_to = None;
+                let mut remove = None;
+
+                self.sessions.names().into_iter().for_each(|name| {
+                    let active = &name == self.sessions.active();
+
+                    ui.horizontal(|ui| {
+                        if ui.selectable_label(active, &name).clicked() && !active {
+                            switch_to = Some(name.clone());
+                        }
+
+                        if !active && ui.button(PhosphorIcon::Trash.rich_text()).clicked() {
+                            remove = Some(name.clone());
+                        }
+                    });
+                });
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .text_edit_singleline(&mut self.new_session_name)
+                        .changed()
+                    {
+                        self.replace_session = None;
+                    }
+
+                    let name = self.new_session_name.trim();
+                    if ui
+                        .add_enabled(
+                            !name.is_empty(),
+                            Button::new(PhosphorIcon::Plus.rich_text()),
+                        )
+                        .on_hover_text("Save the current layout as a new session")
+                        .clicked()
+                    {
+                        match self.sessions.save_as(name, &self.dock_state) {
+                            Ok(()) => self.new_session_name.clear(),
+                            Err(SessionExists) => self.replace_session = Some(name.to_string()),
+                        }
+                    }
+                });
+
+                if let Some(name) = self.replace_session.clone() {
+                    ui.label(format!("There's already a session called {name:?}"));
+                    ui.horizontal(|ui| {
+                        if ui.button("Replace it").clicked() {
+                            self.sessions.replace(name, &self.dock_state);
+                            self.new_session_name.clear();
+                            self.replace_session = None;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.replace_session = None;
+                        }
+                    });
+                }
+
+                if let Some(name) = switch_to {
+                    self.sessions.switch(&name, &mut self.dock_state);
+                    self.sync_sidebars();
+                    ui.close_menu();
+                }
+
+                if let Some(name) = remove {
+                    self.sessions.remove(&name);
+                }
+            },
+        );
+    }
+
+    fn backups_menu(&mut self, ui: &mut Ui) {
+        ui.menu_button(PhosphorIcon::ClockCounterClockwise.rich_text(), |ui| {
+            let backups = self.config.backups();
+            if backups.is_empty() {
+                ui.label("No config backups yet");
+            }
+
+            backups.iter().for_each(|backup| {
+                if ui
+                    .button(&backup.created)
+                    .on_hover_text(backup.path.to_string_lossy())
+                    .clicked()
+                {
+                    match self.config.restore(backup) {
+                        Ok(()) => {
+                            self.apply_config(ui.ctx());
+                            toasts::info(
+                                ui.ctx(),
+                                format!("Restored the config from {}", backup.created),
+                            );
+                        }
+                        Err(err) => toasts::error(
+                            ui.ctx(),
+                            format!("Failed to restore the config backup\n{err}"),
+                        ),
+                    }
+                    ui.close_menu();
+                }
+            });
+        })
+        .response
+        .on_hover_text("Restore a config backup");
+    }
+
+    fn handle_keys(&mut self, ctx: &Context) {
+        if ctx.memory(|m| m.focus().is_some()) {
+            return;
+        }
+
+        let keymap = self.config.file().keymap;
+
+        if keymap.toggle_hidden.consume(ctx) {
+            if let Some(dir_view_state) = active_dir_view_mut(&mut self.dock_state) {
+                dir_view_state.toggle_hidden();
+            }
+        }
+
+        // Before undo, which would otherwise take a redo with an extra modifier
+        if keymap.redo.consume(ctx) {
+            self.redo(ctx, 1);
+        }
+        if keymap.undo.consume(ctx) {
+            self.undo(ctx, 1);
+        }
+
+        if keymap.trash.consume(ctx) {
+            let selected = active_dir_view_mut(&mut self.dock_state)
+                .map(|dir_view_state| {
+                    dir_view_state
+                        .selected_files()
+                        .map(|file_data| file_data.location().clone())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            self.trash(ctx, selected);
         }
     }
+
+    fn handle_config_changes(&mut self, ctx: &Context) {
+        if !self
+            .config_watcher
+            .as_mut()
+            .is_some_and(ConfigWatcher::changed)
+        {
+            return;
+        }
+
+        match self.config.reload() {
+            Ok(changes) => {
+                self.config_error_dismissed = false;
+
+                if changes.theme {
+                    self.refresh_theme(ctx);
+                }
+                if changes.colors || changes.hidden || changes.sorting || changes.thumbnails {
+                    self.refresh_listings(ctx);
+                }
+                if changes.paths {
+                    self.paths = Paths::load(self.config.overrides().paths());
+                }
+                if changes.paths || changes.bookmarks || changes.connections {
+                    self.sync_sidebars();
+                }
+
+                self.config
+                    .warnings()
+                    .iter()
+                    .for_each(|warning| toasts::warning(ctx, warning));
+                if changes.any() {
+                    toasts::info(ctx, "Reloaded the config file");
+                }
+            }
+            Err(_) => {
+                self.config_error_dismissed = false;
+                toasts::error(
+                    ctx,
+                    "The edited config file is invalid, keeping the previous one",
+                );
+            }
+        }
+    }
+
+    /// Loads and applies `preview`, or the saved theme without one.
+    /// A custom theme that fails to load falls back to the default flavour
+    fn resolve_theme(
+        ctx: &Context,
+        config: &Config,
+        preview: Option<&(config::Theme, Accent)>,
+        scheme: ColorScheme,
+    ) -> Theme {
+        let (choice, accent) = preview
+            .cloned()
+            .unwrap_or_else(|| (config.theme().clone(), *config.accent()));
+
+        let theme = config
+            .resolve_theme(&choice, accent, scheme)
+            .unwrap_or_else(|err| {
+                toasts::error(
+                    ctx,
+                    format!("Failed to load the {} theme\n{err}", choice.label()),
+                );
+                config
+                    .resolve_theme(&config::Theme::default(), accent, scheme)
+                    .expect("Flavours are built in")
+            });
+        theme.apply(ctx);
+
+        theme
+    }
+
+    /// The file colors and hide rules listings are loaded with
+    fn load_listing_rules(ctx: &Context, config: &Config) {
+        match config.ls_colors() {
+            Ok(colors) => ls_colors::set(colors),
+            Err(err) => {
+                toasts::error(ctx, err);
+                ls_colors::set(None);
+            }
+        }
+
+        if let Err(err) = hidden::set_rules(&config.file().hidden) {
+            toasts::warning(ctx, err);
+        }
+
+        sorting::set(config.file().sorting);
+        thumbnails::set(config.file().thumbnails);
+    }
+
+    /// Listings apply the rules when they're loaded, so they're all reloaded
+    fn refresh_listings(&mut self, ctx: &Context) {
+        Self::load_listing_rules(ctx, &self.config);
+
+        for_each_dock_tab_mut(&mut self.dock_state, |tab| {
+            tab.dir_views_mut()
+                .into_iter()
+                .for_each(DirViewState::reload);
+        });
+    }
+
+    fn refresh_theme(&mut self, ctx: &Context) {
+        let preview = self.hover_theme.as_ref().or(self.preview_theme.as_ref());
+        self.theme = Self::resolve_theme(ctx, &self.config, preview, self.color_scheme);
+    }
+
+    /// The portal's preference wins over winit's, which not every platform reports.
+    /// Reapplies the theme on any change, eframe resets the visuals when the system theme flips
+    fn update_color_scheme(&mut self, ctx: &Context, frame: &eframe::Frame) {
+        let scheme = self
+            .system_color_scheme
+            .poll()
+            .or(frame.info().system_theme.map(Into::into))
+            .unwrap_or(ColorScheme::Dark);
+
+        if scheme != self.color_scheme {
+            self.color_scheme = scheme;
+            self.refresh_theme(ctx);
+        }
+    }
+
+    /// Picks the theme and accent, hovering one shows it until the menu closes
+    fn theme_menu(&mut self, ui: &mut Ui) {
+        let mut hovered = None;
+
+        let response = ui.menu_button(PhosphorIcon::Palette.rich_text(), |ui| {
+            let (current, accent) = (self.config.theme().clone(), *self.config.accent());
+
+            ui.label("Theme");
+            config::Theme::all(&self.custom_themes).for_each(|theme| {
+                let response = ui.selectable_label(theme == current, theme.label());
+                if response.hovered() {
+                    hovered = Some((theme.clone(), accent));
+                }
+                if response.clicked() {
+                    self.config.set_theme(theme, accent);
+                    ui.close_menu();
+                }
+            });
+
+            ui.separator();
+            ui.label("Accent");
+            ui.horizontal_wrapped(|ui| {
+                Accent::ALL.iter().for_each(|choice| {
+                    let swatch = RichText::new("⬤").color(choice.color(&self.theme));
+                    let response = ui
+                        .add(Button::new(swatch).selected(*choice == accent))
+                        .on_hover_text(choice.label());
+                    if response.hovered() {
+                        hovered = Some((current.clone(), *choice));
+                    }
+                    if response.clicked() {
+                        self.config.set_theme(current.clone(), *choice);
+                        ui.close_menu();
+                    }
+                });
+            });
+        });
+
+        if response.response.clicked() {
+            self.custom_themes = self.config.custom_themes();
+        }
+        response.response.on_hover_text("Theme");
+
+        if hovered != self.hover_theme {
+            self.hover_theme = hovered;
+            self.refresh_theme(ui.ctx());
+        }
+    }
+
+    fn open_history(&mut self) {
+        match find_dock_tab(&self.dock_state, |tab| matches!(tab, DockTab::History)) {
+            Some(tab) => self.dock_state.set_active_tab(tab),
+            None => self.dock_state.push_to_focused_leaf(DockTab::History),
+        }
+    }
+
+    fn open_settings(&mut self) {
+        match find_dock_tab(&self.dock_state, |tab| matches!(tab, DockTab::Settings)) {
+            Some(tab) => self.dock_state.set_active_tab(tab),
+            None => self.dock_state.push_to_focused_leaf(DockTab::Settings),
+        }
+    }
+
+    /// Brings everything derived from the config in line with it after it's replaced
+    fn apply_config(&mut self, ctx: &Context) {
+        self.refresh_theme(ctx);
+        self.refresh_listings(ctx);
+        self.paths = Paths::load(self.config.overrides().paths());
+        self.config_error_dismissed = false;
+        self.sync_sidebars();
+    }
+
+    /// Banner above the dock while the config file is broken and the defaults are in use
+    fn config_error_ui(&mut self, ctx: &Context) {
+        let Some(error) = self
+            .config
+            .error()
+            .filter(|_| !self.config_error_dismissed)
+            .map(str::to_string)
+        else {
+            return;
+        };
+        let theme = self.theme;
+
+        TopBottomPanel::top("config_error").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(PhosphorIcon::Warning.rich_text().color(theme.error_color()));
+                ui.label(
+                    RichText::new(
+                        "The config file failed to load, using the last good one or the defaults. \
+                         Changes won't be saved until it's fixed or a backup is restored",
+                    )
+                    .color(theme.error_color()),
+                );
+
+                if ui.button("Dismiss").clicked() {
+                    self.config_error_dismissed = true;
+                }
+            });
+
+            ui.collapsing("Details", |ui| {
+                ui.label(RichText::new(error).monospace());
+            });
+        });
+    }
 }
 
 impl eframe::App for App {
-    fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &Context, frame: &mut eframe::Frame) {
+        self.update_color_scheme(ctx, frame);
+        self.handle_keys(ctx);
+        self.update_mounts();
+        self.handle_config_changes(ctx);
+        self.handle_device_responses(ctx);
+        self.handle_network_responses(ctx);
+        self.host_key_window(ctx);
+        self.handle_finished_jobs(ctx);
+
         TopBottomPanel::top("header").show(ctx, |ui| {
             ui.horizontal(|ui| {
+                self.theme_menu(ui);
+
+                if ui
+                    .button(PhosphorIcon::Columns.rich_text())
+                    .on_hover_text("Open a dual-pane commander tab")
+                    .clicked()
+                {
+                    self.dock_state
+                        .push_to_focused_leaf(DockTab::Commander(Box::new(CommanderState::new(
+                            FileData::new(self.paths.home_dir()),
+                            FileData::new(self.paths.home_dir()),
+                            self.config.view(),
+                        ))));
+                }
+
+                if ui
+                    .button(PhosphorIcon::Gear.rich_text())
+                    .on_hover_text("Settings")
+                    .clicked()
+                {
+                    self.open_settings();
+                }
+
+                self.sessions_menu(ui);
+                self.backups_menu(ui);
+
+                ui.separator();
+
+                if ui
+                    .add_enabled(
+                        self.journal.lock().expect("Journal poisoned").can_undo(),
+                        Button::new(PhosphorIcon::ArrowUUpLeft.rich_text()),
+                    )
+                    .on_hover_text("Undo (Ctrl+Z)")
+                    .clicked()
+                {
+                    self.undo(ctx, 1);
+                }
+
+                if ui
+                    .add_enabled(
+                        self.journal.lock().expect("Journal poisoned").can_redo(),
+                        Button::new(PhosphorIcon::ArrowUUpRight.rich_text()),
+                    )
+                    .on_hover_text("Redo (Ctrl+Shift+Z)")
+                    .clicked()
+                {
+                    self.redo(ctx, 1);
+                }
+
                 if ui
-                    .button(RichText::new(PhosphorIcon::Palette.symbol()))
+                    .button(PhosphorIcon::ListDashes.rich_text())
+                    .on_hover_text("Undo history")
                     .clicked()
                 {
-                    self.config.switch_theme();
-                    set_theme(ui.ctx(), (*self.config.theme()).into());
+                    self.open_history();
                 }
             });
         });
 
+        self.config_error_ui(ctx);
+
+        if !self.jobs.is_empty() {
+            TopBottomPanel::bottom("jobs").show(ctx, |ui| self.jobs.ui(ui));
+        }
+
         CentralPanel::default().show(ctx, |ui| {
             DockArea::new(&mut self.dock_state).show_inside(
                 ui,
                 &mut DockTabViewer::new(
-                    &Into::<Theme>::into(*self.config.theme()),
+                    self.config.file(),
+                    &self.custom_themes,
+                    &self.theme,
+                    self.paths.templates_dir().as_deref(),
                     &self.log_event_collector,
+                    &self.journal.lock().expect("Journal poisoned"),
+                    &mut self.dock_actions,
                 ),
             )
         });
+
+        self.handle_dock_actions(ctx);
+        dnd::take_drops(ctx)
+            .into_iter()
+            .for_each(|drop| self.run_drop(drop));
+        dnd::finish_frame(ctx);
+        toasts::show(ctx, &self.theme);
     }
+
+    fn save(&mut self, storage: &mut dyn Storage) {
+        eframe::set_value(storage, DOCK_STATE_KEY, &self.dock_state);
+        eframe::set_value(storage, SESSIONS_KEY, &self.sessions);
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string()
 }
@@ -1,2 +1,4 @@
 pub mod button;
+pub mod dnd;
 pub mod dock;
+pub mod toasts;
@@ -1,13 +1,14 @@
-use catppuccin_egui::Theme;
-
 use eframe::{
     egui::{
-        self, vec2, Align, Frame, InnerResponse, Layout, Margin, Rounding, Sense, TextFormat, Ui,
-        Vec2, WidgetInfo,
+        self,
+        load::TexturePoll,
+        text::{CCursor, CCursorRange},
+        vec2, Align, Frame, Image, InnerResponse, Key, Layout, Margin, Rounding, Sense, TextEdit,
+        TextFormat, Ui, Vec2, WidgetInfo,
     },
     epaint::{
         text::{LayoutJob, TextWrapping},
-        FontId, Stroke,
+        Color32, FontId, Stroke,
     },
 };
 
@@ -15,7 +16,10 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     struct_with_funcs, struct_with_into_funcs, struct_with_some_funcs,
-    utils::{icons::PhosphorIcon, theme::ThemeExt},
+    utils::{
+        icons::PhosphorIcon,
+        theme::{Theme, ThemeExt},
+    },
 };
 
 pub struct RSFMButton<'a> {
@@ -24,17 +28,26 @@ pub struct RSFMButton<'a> {
 
     editable: bool,
     selectable: bool,
+    draggable: bool,
+    /// Starts editing with the text up to its extension selected, for file names
+    select_stem: bool,
 
     layout: Layout,
     desired_size: Vec2,
     icon: Option<PhosphorIcon>,
+    /// Tints the icon, the theme's text color without one
+    icon_color: Option<Color32>,
     icon_size: Vec2,
+    /// Image URI drawn instead of the icon once it's loaded
+    thumbnail: Option<String>,
     spacing: Option<f32>,
     padding: Margin,
     rounding: Rounding,
     border_width: Option<f32>,
 
     font_size: f32,
+    text_color: Option<Color32>,
+    text_background: Option<Color32>,
     text_valign: Align,
     text_halign: Align,
     text_unselected_rows: usize,
@@ -49,17 +62,23 @@ impl<'a> RSFMButton<'a> {
 
             editable: false,
             selectable: false,
+            draggable: false,
+            select_stem: false,
 
             layout: Layout::left_to_right(Align::Center),
             desired_size: vec2(150.0, 15.0),
             icon: None,
+            icon_color: None,
             icon_size: vec2(50.0, 50.0),
+            thumbnail: None,
             spacing: None,
             padding: Margin::symmetric(5.0, 5.0),
             rounding: Rounding::ZERO,
             border_width: None,
 
             font_size: 14.0,
+            text_color: None,
+            text_background: None,
             text_valign: Align::Center,
             text_halign: Align::Min,
             text_unselected_rows: 1,
@@ -72,13 +91,13 @@ impl<'theme> RSFMButton<'theme> {
     struct_with_funcs![
         editable: bool,
         selectable: bool,
+        draggable: bool,
+        select_stem: bool,
         layout: Layout,
+        thumbnail: Option<String>,
         spacing: Option<f32>,
-        rounding: Rounding,
         font_size: f32,
-        text_valign: Align,
         text_halign: Align,
-        text_unselected_rows: usize,
         text_selected_rows: usize
     ];
     struct_with_into_funcs![
@@ -86,7 +105,12 @@ impl<'theme> RSFMButton<'theme> {
         icon_size: Vec2,
         padding: Margin
     ];
-    struct_with_some_funcs![icon: PhosphorIcon, border_width: f32];
+    struct_with_some_funcs![
+        icon: PhosphorIcon,
+        icon_color: Color32,
+        text_color: Color32,
+        text_background: Color32
+    ];
 }
 
 macro_rules! fill_rect {
@@ -115,9 +139,27 @@ impl<'theme> egui::Widget for RSFMButton<'theme> {
                 let mut text_double_clicked = false;
 
                 ui.allocate_ui_with_layout(self.desired_size, self.layout, |ui| {
+                    let thumbnail = self.thumbnail.as_ref().map(|uri| {
+                        Image::from_uri(uri.as_str())
+                            .fit_to_exact_size(self.icon_size)
+                            .sense(Sense::click())
+                    });
+                    let thumbnail = thumbnail.filter(|image| {
+                        matches!(
+                            image.load_for_size(ui.ctx(), self.icon_size),
+                            Ok(TexturePoll::Ready { .. })
+                        )
+                    });
+
                     if let Some(icon) = &self.icon {
-                        let response =
-                            ui.add(icon.image_widget(self.icon_size).sense(Sense::click()));
+                        let response = match (thumbnail, self.icon_color) {
+                            (Some(image), _) => ui.add_sized(self.icon_size, image),
+                            (None, color) => ui.add(
+                                icon.image_widget(self.icon_size)
+                                    .tint(color.unwrap_or_else(|| self.theme.text_color()))
+                                    .sense(Sense::click()),
+                            ),
+                        };
                         icon_double_clicked = response.double_clicked();
 
                         if icon_double_clicked {
@@ -136,12 +178,40 @@ impl<'theme> egui::Widget for RSFMButton<'theme> {
 
                         match self.state.editing_text {
                             true => {
-                                let response = ui.text_edit_multiline(text);
+                                let mut output = TextEdit::singleline(&mut self.state.draft)
+                                    .desired_width(f32::INFINITY)
+                                    .show(ui);
+
+                                if std::mem::take(&mut self.state.focus_editor) {
+                                    let chars = self.state.draft.chars().count();
+                                    let end = match self.select_stem {
+                                        true => self
+                                            .state
+                                            .draft
+                                            .rfind('.')
+                                            .filter(|&dot| dot > 0)
+                                            .map_or(chars, |dot| {
+                                                self.state.draft[..dot].chars().count()
+                                            }),
+                                        false => chars,
+                                    };
 
-                                if response.lost_focus()
-                                    && ui.input(|i| i.key_pressed(egui::Key::Enter))
-                                {
+                                    output.state.set_ccursor_range(Some(CCursorRange::two(
+                                        CCursor::new(0),
+                                        CCursor::new(end),
+                                    )));
+                                    output.state.store(ui.ctx(), output.response.id);
+                                    output.response.request_focus();
+                                }
+
+                                if output.response.lost_focus() {
                                     self.state.editing_text = false;
+
+                                    // Enter and clicking elsewhere keep the edit, Escape drops it
+                                    if !ui.input(|i| i.key_pressed(Key::Escape)) {
+                                        *text = self.state.draft.clone();
+                                        self.state.committed = true;
+                                    }
                                 }
                             }
                             false => {
@@ -165,7 +235,12 @@ impl<'theme> egui::Widget for RSFMButton<'theme> {
                                                 self.font_size,
                                                 egui::FontFamily::Proportional,
                                             ),
-                                            color: self.theme.text,
+                                            color: self
+                                                .text_color
+                                                .unwrap_or_else(|| self.theme.text_color()),
+                                            background: self
+                                                .text_background
+                                                .unwrap_or(Color32::TRANSPARENT),
                                             valign: self.text_valign,
                                             ..Default::default()
                                         },
@@ -188,7 +263,10 @@ impl<'theme> egui::Widget for RSFMButton<'theme> {
         let response = ui.interact(
             response.rect,
             response.id,
-            Sense::click().union(Sense::hover()),
+            match self.draggable {
+                true => Sense::click_and_drag(),
+                false => Sense::click().union(Sense::hover()),
+            },
         );
 
         let painter = ui.painter();
@@ -204,7 +282,7 @@ impl<'theme> egui::Widget for RSFMButton<'theme> {
         if icon_double_clicked {
             self.state.double_clicked = true;
         } else if text_double_clicked && self.editable {
-            self.state.editing_text = true;
+            self.state.start_editing_text();
         } else if response.clicked() {
             self.state.selected = self.selectable;
 
@@ -239,15 +317,18 @@ pub struct RSFMButtonState {
 
     editing_text: bool,
     text: Option<String>,
+    /// What's typed while editing, only written to `text` once committed
+    #[serde(skip)]
+    draft: String,
+    /// Focuses the editor and selects its text on the next frame
+    #[serde(skip)]
+    focus_editor: bool,
+    /// Set once an edit was committed, until [`Self::take_committed`]
+    #[serde(skip)]
+    committed: bool,
 }
 
 impl RSFMButtonState {
-    struct_with_funcs![
-        selected: bool,
-        double_clicked: bool,
-        editing_text: bool
-    ];
-
     pub fn with_text(mut self, text: impl Into<String>) -> Self {
         self.text = Some(text.into());
         self
@@ -265,6 +346,30 @@ impl RSFMButtonState {
         self.selected
     }
 
+    pub fn editing_text(&self) -> bool {
+        self.editing_text
+    }
+
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = Some(text.into());
+    }
+
+    pub fn start_editing_text(&mut self) {
+        self.draft = self.text.clone().unwrap_or_default();
+        self.resume_editing_text();
+    }
+
+    /// Reopens the editor with what was typed last, e.g. after the edit was rejected
+    pub fn resume_editing_text(&mut self) {
+        self.editing_text = true;
+        self.focus_editor = true;
+    }
+
+    /// Whether an edit was committed since the last call
+    pub fn take_committed(&mut self) -> bool {
+        std::mem::take(&mut self.committed)
+    }
+
     pub fn select(&mut self) {
         self.selected = true;
     }
@@ -275,7 +380,7 @@ impl RSFMButtonState {
 }
 
 pub trait RSFMButtonDerived {
-    fn to_rsfm_but(&mut self) -> RSFMButton;
+    fn to_rsfm_but(&mut self) -> RSFMButton<'_>;
 }
 
 #[macro_export]
@@ -287,7 +392,7 @@ macro_rules! button_newtype {
             $(&mut $custom_field_name_ref_mut:ident: $custom_field_ty_ref_mut:path,)*
             $($custom_field_name:ident: $custom_field_ty:path,)*
         ];)*
-        $(custom_state: $cs_name:ident {
+        $(custom_state: $(#[$cs_meta:meta])* $cs_name:ident {
             $($cs_field_name:ident: $cs_field_ty:path,)*
         };)*
         $(modifiers: [$($mod:ident{$val:expr}),+])*
@@ -296,7 +401,7 @@ macro_rules! button_newtype {
 
         pub struct $name<'a> {
             state: &'a mut button_newtype!(state_ty; $($cs_name)*),
-            theme: &'a catppuccin_egui::Theme,
+            theme: &'a $crate::utils::theme::Theme,
             $(
                 $($custom_field_name: $custom_field_ty,)*
                 $($custom_field_name_ref: &'a $custom_field_ty_ref,)*
@@ -307,7 +412,7 @@ macro_rules! button_newtype {
         impl<'a> $name<'a> {
             pub fn new(
                 state: &'a mut button_newtype!(state_ty; $($cs_name)*),
-                theme: &'a catppuccin_egui::Theme,
+                theme: &'a $crate::utils::theme::Theme,
                 $(
                     $($custom_field_name: $custom_field_ty,)*
                     $($custom_field_name_ref: &'a $custom_field_ty_ref,)*
@@ -327,7 +432,7 @@ macro_rules! button_newtype {
         }
 
         impl<'a> RSFMButtonDerived for $name<'a> {
-            fn to_rsfm_but(&mut self) -> RSFMButton {
+            fn to_rsfm_but(&mut self) -> RSFMButton<'_> {
                 paste::paste! {
                     RSFMButton::new(&mut self.state, &self.theme)$($(.[< with_ $mod >]($val))+)*
                 }
@@ -335,6 +440,7 @@ macro_rules! button_newtype {
         }
 
         $(
+            $(#[$cs_meta])*
             pub struct $cs_name {
                 rsfm: RSFMButtonState,
                 $($cs_field_name: $cs_field_ty),+
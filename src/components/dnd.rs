@@ -0,0 +1,233 @@
+//! Drag and drop of files between [`DirView`](super::dock::dir_view::DirView)s, the sidebar
+//! and other applications.
+//!
+//! egui doesn't carry drag payloads itself, so the dragged paths are kept in the context's temp data
+//! until they are released over a drop target or anywhere else.
+//! Dragging files out of the window isn't possible, winit can only receive external drops.
+//! Archive members are extracted when dropped, they can't be moved out of the archive.
+//! Drops are run as jobs, see [`take_drops`]
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use eframe::{
+    egui::{self, Context, Id, Modifiers, Rect, Response, Ui},
+    epaint::Stroke,
+};
+
+use crate::utils::{
+    archive::{self, ArchivePath},
+    fs::{copy_recursive, move_path},
+    jobs::JobProgress,
+    journal::Operation,
+    theme::{Theme, ThemeExt},
+    vfs::{Location, TransferMode},
+};
+
+const PAYLOAD_ID: &str = "rsfm_drag_payload";
+const DROPS_ID: &str = "rsfm_drops";
+
+#[derive(Clone)]
+struct DragPayload {
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy)]
+pub enum DropAction {
+    Copy,
+    Move,
+}
+
+impl DropAction {
+    /// Moving is the default, holding Ctrl copies instead
+    fn from_modifiers(modifiers: &Modifiers) -> Self {
+        match modifiers.command {
+            true => Self::Copy,
+            false => Self::Move,
+        }
+    }
+
+    pub fn verb(&self) -> &'static str {
+        match self {
+            DropAction::Copy => "copy",
+            DropAction::Move => "move",
+        }
+    }
+}
+
+impl From<DropAction> for TransferMode {
+    fn from(action: DropAction) -> Self {
+        match action {
+            DropAction::Copy => TransferMode::Copy,
+            DropAction::Move => TransferMode::Move,
+        }
+    }
+}
+
+fn payload_id() -> Id {
+    Id::new(PAYLOAD_ID)
+}
+
+fn drops_id() -> Id {
+    Id::new(DROPS_ID)
+}
+
+/// Paths released over a drop target, waiting to be copied or moved there
+#[derive(Clone)]
+pub struct PendingDrop {
+    pub paths: Vec<PathBuf>,
+    pub dir: PathBuf,
+    pub action: DropAction,
+}
+
+impl PendingDrop {
+    /// Everything that isn't in [`Self::dir`] already
+    pub fn sources(&self) -> impl Iterator<Item = &PathBuf> {
+        self.paths
+            .iter()
+            .filter(|path| path.parent() != Some(self.dir.as_path()))
+    }
+
+    /// Copies or moves every source, stopping at the first failure.
+    /// What got done is recorded in `progress`
+    pub fn run(&self, progress: &JobProgress) -> io::Result<()> {
+        self.sources().try_for_each(|path| {
+            progress.check_cancelled()?;
+
+            let Some(name) = path.file_name() else {
+                return Ok(());
+            };
+            let to = self.dir.join(name);
+
+            // Extracting leaves the archive as it is, whatever the action
+            let member = ArchivePath::parse(path).filter(|archive_path| archive_path.is_member());
+            let moved = member.is_none() && matches!(self.action, DropAction::Move);
+            let res = match (member, self.action) {
+                (Some(archive_path), _) => archive::extract(
+                    &archive_path.archive,
+                    &[archive_path.inner],
+                    &self.dir,
+                    progress,
+                ),
+                (None, DropAction::Copy) => copy_recursive(path, &to),
+                (None, DropAction::Move) => move_path(path, &to),
+            };
+            res.map_err(|err| {
+                io::Error::new(
+                    err.kind(),
+                    format!("Failed to {} {path:?} to {to:?}: {err}", self.action.verb()),
+                )
+            })?;
+
+            let (from, to) = (Location::local(path.clone()), Location::local(to));
+            progress.record(match moved {
+                true => Operation::moved(from, to),
+                false => Operation::copied(from, to),
+            });
+
+            Ok(())
+        })
+    }
+}
+
+/// The drops since the last call, the caller runs them
+pub fn take_drops(ctx: &Context) -> Vec<PendingDrop> {
+    ctx.data_mut(|d| {
+        let drops = d.get_temp::<Vec<PendingDrop>>(drops_id());
+        d.remove::<Vec<PendingDrop>>(drops_id());
+        drops.unwrap_or_default()
+    })
+}
+
+/// Start dragging `paths` when `response` starts being dragged
+pub fn drag_source(response: &Response, paths: impl FnOnce() -> Vec<PathBuf>) {
+    if response.drag_started() {
+        let paths = paths();
+
+        response
+            .ctx
+            .data_mut(|d| d.insert_temp(payload_id(), DragPayload { paths }));
+    }
+}
+
+pub fn is_dragging(ctx: &Context) -> bool {
+    ctx.data(|d| d.get_temp::<DragPayload>(payload_id()).is_some())
+}
+
+/// Show what's being dragged next to the pointer and drop the payload if it was released
+/// without landing on a drop target. Call once per frame after all the drop targets
+pub fn finish_frame(ctx: &Context) {
+    let Some(payload) = ctx.data(|d| d.get_temp::<DragPayload>(payload_id())) else {
+        return;
+    };
+
+    if ctx.input(|i| i.pointer.any_released()) {
+        ctx.data_mut(|d| d.remove::<DragPayload>(payload_id()));
+        return;
+    }
+
+    egui::show_tooltip_at_pointer(ctx, payload_id().with("tooltip"), |ui| {
+        payload.paths.iter().for_each(|path| {
+            ui.label(
+                path.file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy(),
+            );
+        });
+    });
+}
+
+/// Highlights `rect` while something is dragged over it, either from rsfm itself
+/// or from another application, and queues copying/moving it into `dir` once released there
+pub fn drop_target(ui: &Ui, rect: Rect, theme: &Theme, dir: &Path) {
+    if let Some((paths, action)) = take_dropped_paths(ui, rect, theme) {
+        let drop = PendingDrop {
+            paths,
+            dir: dir.to_path_buf(),
+            action,
+        };
+        ui.ctx().data_mut(|d| {
+            d.get_temp_mut_or_default::<Vec<PendingDrop>>(drops_id())
+                .push(drop)
+        });
+    }
+}
+
+/// Same as [`drop_target`], but leaves handling the dropped paths to the caller
+pub fn take_dropped_paths(
+    ui: &Ui,
+    rect: Rect,
+    theme: &Theme,
+) -> Option<(Vec<PathBuf>, DropAction)> {
+    if !ui.rect_contains_pointer(rect) {
+        return None;
+    }
+
+    let hovering_external = ui.input(|i| !i.raw.hovered_files.is_empty());
+
+    if is_dragging(ui.ctx()) || hovering_external {
+        ui.painter()
+            .rect_stroke(rect, 2.0, Stroke::new(2.0, theme.drop_target_color()));
+    }
+
+    let external = ui.input_mut(|i| std::mem::take(&mut i.raw.dropped_files));
+    if !external.is_empty() {
+        let paths = external.into_iter().flat_map(|file| file.path).collect();
+        return Some((paths, DropAction::Copy));
+    }
+
+    if !ui.input(|i| i.pointer.any_released()) {
+        return None;
+    }
+
+    let payload = ui.ctx().data_mut(|d| {
+        let payload = d.get_temp::<DragPayload>(payload_id());
+        d.remove::<DragPayload>(payload_id());
+        payload
+    })?;
+
+    let action = ui.input(|i| DropAction::from_modifiers(&i.modifiers));
+    Some((payload.paths, action))
+}
@@ -1,32 +1,221 @@
+pub mod commander;
 pub mod dir_view;
+pub mod history;
+pub mod settings;
 pub mod sidebar;
 
-use catppuccin_egui::Theme;
+use std::path::{Path, PathBuf};
+
 use eframe::egui::{Ui, Widget, WidgetText};
-use egui_dock::TabViewer;
+use egui_dock::{DockState, NodeIndex, SurfaceIndex, TabIndex, TabViewer};
 use egui_tracing::{ui::Logs, EventCollector};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, Accent, Bookmark, ConfigFile, ConnectionProfile},
+    utils::{
+        archive::CompressOptions,
+        journal::{Journal, Operation},
+        theme::Theme,
+        vfs::{Location, TransferMode},
+    },
+};
 
+use commander::{Commander, CommanderState};
 use dir_view::{DirView, DirViewState};
+use history::History;
+use settings::Settings;
 use sidebar::{Sidebar, SidebarState};
 
+#[derive(Clone, Serialize, Deserialize)]
 pub enum DockTab {
     Sidebar(SidebarState),
     InfoSidebar,
-    DirView(DirViewState),
+    DirView(Box<DirViewState>),
+    Commander(Box<CommanderState>),
     Log,
     Terminal,
+    Settings,
+    History,
+}
+
+impl DockTab {
+    /// The [`DirViewState`] this tab navigates, for the commander it's the active pane
+    pub fn dir_view(&self) -> Option<&DirViewState> {
+        match self {
+            DockTab::DirView(dir_view_state) => Some(dir_view_state),
+            DockTab::Commander(commander_state) => Some(commander_state.active_pane()),
+            _ => None,
+        }
+    }
+
+    /// Mutable [`Self::dir_view`]
+    pub fn dir_view_mut(&mut self) -> Option<&mut DirViewState> {
+        match self {
+            DockTab::DirView(dir_view_state) => Some(dir_view_state),
+            DockTab::Commander(commander_state) => Some(commander_state.active_pane_mut()),
+            _ => None,
+        }
+    }
+
+    /// Every [`DirViewState`] in the tab, including both commander panes
+    pub fn dir_views_mut(&mut self) -> Vec<&mut DirViewState> {
+        match self {
+            DockTab::DirView(dir_view_state) => vec![dir_view_state],
+            DockTab::Commander(commander_state) => commander_state.panes_mut().iter_mut().collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// Things tabs can't do on their own and ask the app to handle after the dock is drawn
+pub enum DockAction {
+    /// Navigate the last focused [`DirView`] to the location
+    Navigate(Location),
+    BookmarkActiveDir,
+    AddBookmark(PathBuf),
+    SetBookmarks(Vec<Bookmark>),
+    Mount(PathBuf),
+    Unmount(PathBuf),
+    Eject(PathBuf),
+    /// Pack `sources` into a new archive at `dest` in the background
+    Compress {
+        sources: Vec<PathBuf>,
+        dest: PathBuf,
+        options: CompressOptions,
+    },
+    /// Extract the whole archive into `dest_dir` in the background
+    Extract {
+        archive: PathBuf,
+        dest_dir: PathBuf,
+        password: Option<String>,
+    },
+    /// Extract some of the archive's members into `dest_dir` in the background
+    ExtractMembers {
+        archive: PathBuf,
+        members: Vec<PathBuf>,
+        dest_dir: PathBuf,
+    },
+    /// Copy or move `sources` into `dest_dir` in the background, between any two backends
+    Transfer {
+        sources: Vec<Location>,
+        dest_dir: Location,
+        mode: TransferMode,
+    },
+    /// Copy a remote file or directory into the downloads directory
+    Download(Location),
+    Connect(ConnectionProfile),
+    Disconnect(ConnectionProfile),
+    SetConnections(Vec<ConnectionProfile>),
+    /// Show the app in another theme until it's saved or reverted, `None` goes back to the saved one
+    PreviewTheme(Option<(config::Theme, Accent)>),
+    SaveSettings(Box<ConfigFile>),
+    /// The settings tab was closed without saving
+    DiscardSettings,
+    /// A file operation finished, remember it for undo
+    Record(Operation),
+    /// Move local files to the trash
+    Trash(Vec<Location>),
+    /// Undo this many operations, newest first
+    Undo(usize),
+    /// Redo this many undone operations
+    Redo(usize),
+}
+
+/// Where every tab is, floating windows included
+fn tab_indices(dock_state: &DockState<DockTab>) -> Vec<(SurfaceIndex, NodeIndex, TabIndex)> {
+    (0..)
+        .map(SurfaceIndex)
+        .map_while(|surface_index| Some((surface_index, dock_state.get_surface(surface_index)?)))
+        .filter_map(|(surface_index, surface)| Some((surface_index, surface.node_tree()?)))
+        .flat_map(|(surface_index, tree)| {
+            tree.iter().enumerate().flat_map(move |(node_index, node)| {
+                (0..node.tabs_count()).map(move |tab_index| {
+                    (surface_index, NodeIndex(node_index), TabIndex(tab_index))
+                })
+            })
+        })
+        .collect()
+}
+
+fn dock_tab(
+    dock_state: &DockState<DockTab>,
+    (surface_index, node_index, tab_index): (SurfaceIndex, NodeIndex, TabIndex),
+) -> Option<&DockTab> {
+    dock_state.get_surface(surface_index)?.node_tree()?[node_index]
+        .tabs()?
+        .get(tab_index.0)
+}
+
+fn dock_tab_mut(
+    dock_state: &mut DockState<DockTab>,
+    (surface_index, node_index, tab_index): (SurfaceIndex, NodeIndex, TabIndex),
+) -> Option<&mut DockTab> {
+    dock_state.get_surface_mut(surface_index)?.node_tree_mut()?[node_index]
+        .tabs_mut()?
+        .get_mut(tab_index.0)
+}
+
+/// Where the first tab matching `predicate` is
+pub fn find_dock_tab(
+    dock_state: &DockState<DockTab>,
+    predicate: impl Fn(&DockTab) -> bool,
+) -> Option<(SurfaceIndex, NodeIndex, TabIndex)> {
+    tab_indices(dock_state)
+        .into_iter()
+        .find(|&index| dock_tab(dock_state, index).is_some_and(&predicate))
+}
+
+/// Calls `f` with every tab, floating windows included
+pub fn for_each_dock_tab_mut(dock_state: &mut DockState<DockTab>, mut f: impl FnMut(&mut DockTab)) {
+    tab_indices(dock_state).into_iter().for_each(|index| {
+        if let Some(tab) = dock_tab_mut(dock_state, index) {
+            f(tab);
+        }
+    });
+}
+
+/// The [`DirViewState`] the user interacted with last
+pub fn active_dir_view_mut(dock_state: &mut DockState<DockTab>) -> Option<&mut DirViewState> {
+    let (index, _) = tab_indices(dock_state)
+        .into_iter()
+        .filter_map(|index| {
+            let last_focused = dock_tab(dock_state, index)?.dir_view()?.last_focused();
+            Some((index, last_focused))
+        })
+        .max_by(|(_, t1), (_, t2)| t1.total_cmp(t2))?;
+
+    dock_tab_mut(dock_state, index)?.dir_view_mut()
 }
 
 pub struct DockTabViewer<'a> {
+    config: &'a ConfigFile,
+    custom_themes: &'a [String],
     theme: &'a Theme,
+    templates_dir: Option<&'a Path>,
     log_event_collector: &'a EventCollector,
+    journal: &'a Journal,
+    actions: &'a mut Vec<DockAction>,
 }
 
 impl<'a> DockTabViewer<'a> {
-    pub fn new(theme: &'a Theme, log_event_collector: &'a EventCollector) -> Self {
+    pub fn new(
+        config: &'a ConfigFile,
+        custom_themes: &'a [String],
+        theme: &'a Theme,
+        templates_dir: Option<&'a Path>,
+        log_event_collector: &'a EventCollector,
+        journal: &'a Journal,
+        actions: &'a mut Vec<DockAction>,
+    ) -> Self {
         Self {
+            config,
+            custom_themes,
             theme,
+            templates_dir,
             log_event_collector,
+            journal,
+            actions,
         }
     }
 }
@@ -39,18 +228,48 @@ impl<'a> TabViewer for DockTabViewer<'a> {
             DockTab::Sidebar(_) => "Sidebar".into(),
             DockTab::InfoSidebar => "Info".into(),
             DockTab::DirView(dir_view_state) => dir_view_state.file_data().name().into(),
+            DockTab::Commander(commander_state) => format!(
+                "Commander: {}",
+                commander_state.active_pane().file_data().name()
+            )
+            .into(),
             DockTab::Log => "Log".into(),
             DockTab::Terminal => "Terminal".into(),
+            DockTab::Settings => "Settings".into(),
+            DockTab::History => "History".into(),
         }
     }
 
     fn ui(&mut self, ui: &mut Ui, tab: &mut Self::Tab) {
         match tab {
-            DockTab::Sidebar(sidebar_state) => Sidebar::new(sidebar_state, self.theme).ui(ui),
+            DockTab::Sidebar(sidebar_state) => {
+                Sidebar::new(sidebar_state, self.theme, self.actions).ui(ui)
+            }
             DockTab::InfoSidebar => ui.label("Info"),
-            DockTab::DirView(dir_view_state) => DirView::new(dir_view_state, self.theme).ui(ui),
+            DockTab::DirView(dir_view_state) => {
+                DirView::new(dir_view_state, self.theme, self.actions)
+                    .with_templates_dir(self.templates_dir)
+                    .ui(ui)
+            }
+            DockTab::Commander(commander_state) => {
+                Commander::new(commander_state, self.theme, self.actions)
+                    .with_templates_dir(self.templates_dir)
+                    .ui(ui)
+            }
             DockTab::Log => Logs::new(self.log_event_collector.clone()).ui(ui),
             DockTab::Terminal => ui.label("Terminal"),
+            DockTab::Settings => {
+                Settings::new(self.config, self.custom_themes, self.theme, self.actions).ui(ui)
+            }
+            DockTab::History => History::new(self.journal, self.theme, self.actions).ui(ui),
         };
     }
+
+    fn on_close(&mut self, tab: &mut Self::Tab) -> bool {
+        if let DockTab::Settings = tab {
+            self.actions.push(DockAction::DiscardSettings);
+        }
+
+        true
+    }
 }
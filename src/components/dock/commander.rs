@@ -0,0 +1,283 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use eframe::{
+    egui::{Frame, Key, Modifiers, Response, Ui, Widget},
+    epaint::Stroke,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ViewDefaults,
+    utils::{
+        fs::FileData,
+        icons::PhosphorIcon,
+        theme::{Theme, ThemeExt},
+        vfs::TransferMode,
+    },
+};
+
+use super::{
+    dir_view::{DirView, DirViewState},
+    DockAction,
+};
+
+/// Two [`DirView`]s side by side, operations go from the active pane into the other one
+pub struct Commander<'a> {
+    state: &'a mut CommanderState,
+    theme: &'a Theme,
+    actions: &'a mut Vec<DockAction>,
+
+    templates_dir: Option<&'a Path>,
+}
+
+impl<'a> Commander<'a> {
+    pub fn new(
+        state: &'a mut CommanderState,
+        theme: &'a Theme,
+        actions: &'a mut Vec<DockAction>,
+    ) -> Self {
+        Self {
+            state,
+            theme,
+            actions,
+
+            templates_dir: None,
+        }
+    }
+
+    /// See [`DirView::with_templates_dir`]
+    pub fn with_templates_dir(mut self, templates_dir: Option<&'a Path>) -> Self {
+        self.templates_dir = templates_dir;
+        self
+    }
+}
+
+impl<'a> Commander<'a> {
+    fn handle_keys(&mut self, ui: &Ui) {
+        if ui.memory(|m| m.focus().is_some()) {
+            return;
+        }
+
+        let (switch, copy, mv) = ui.input_mut(|i| {
+            (
+                i.consume_key(Modifiers::NONE, Key::Tab),
+                i.consume_key(Modifiers::NONE, Key::F5),
+                i.consume_key(Modifiers::NONE, Key::F6),
+            )
+        });
+
+        if switch {
+            self.state.switch_active();
+        }
+
+        if copy {
+            self.state.transfer(TransferMode::Copy, self.actions);
+        }
+
+        if mv {
+            self.state.transfer(TransferMode::Move, self.actions);
+        }
+    }
+}
+
+impl<'a> Widget for Commander<'a> {
+    fn ui(mut self, ui: &mut Ui) -> Response {
+        self.handle_keys(ui);
+
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .button(format!("{} Copy (F5)", PhosphorIcon::Copy.symbol()))
+                    .clicked()
+                {
+                    self.state.transfer(TransferMode::Copy, self.actions);
+                }
+
+                if ui
+                    .button(format!(
+                        "{} Move (F6)",
+                        PhosphorIcon::ArrowsLeftRight.symbol()
+                    ))
+                    .clicked()
+                {
+                    self.state.transfer(TransferMode::Move, self.actions);
+                }
+
+                ui.toggle_value(&mut self.state.compare, "Compare");
+            });
+            ui.separator();
+
+            if self.state.compare {
+                self.state.update_differences();
+            }
+
+            let CommanderState {
+                panes,
+                active,
+                compare,
+                differences,
+            } = self.state;
+            let no_differences = Default::default();
+            let differences = match (*compare, differences) {
+                (true, Some(differences)) => &differences.indices,
+                _ => &no_differences,
+            };
+
+            ui.columns(2, |columns| {
+                panes
+                    .iter_mut()
+                    .zip(columns.iter_mut())
+                    .zip(differences.iter())
+                    .enumerate()
+                    .for_each(|(i, ((pane, ui), differences))| {
+                        let stroke_color = match i == *active {
+                            true => self.theme.focus_ring_color(),
+                            false => self.theme.surface0,
+                        };
+
+                        let response = Frame::none()
+                            .stroke(Stroke::new(1.0, stroke_color))
+                            .inner_margin(2.0)
+                            .show(ui, |ui| {
+                                ui.push_id(i, |ui| {
+                                    DirView::new(pane, self.theme, self.actions)
+                                        .with_highlighted(differences)
+                                        .with_templates_dir(self.templates_dir)
+                                        .ui(ui)
+                                })
+                            })
+                            .response;
+
+                        if ui.rect_contains_pointer(response.rect)
+                            && ui.input(|i| i.pointer.any_pressed())
+                        {
+                            *active = i;
+                        }
+                    });
+            });
+        })
+        .response
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CommanderState {
+    panes: [DirViewState; 2],
+    active: usize,
+    compare: bool,
+    #[serde(skip)]
+    differences: Option<Differences>,
+}
+
+/// [`CommanderState::differences`] for the listings with the given generations
+#[derive(Clone)]
+struct Differences {
+    generations: [u64; 2],
+    indices: [HashSet<usize>; 2],
+}
+
+impl CommanderState {
+    pub fn new(left: FileData, right: FileData, view: &ViewDefaults) -> Self {
+        Self {
+            panes: [
+                DirViewState::with_view(left, view),
+                DirViewState::with_view(right, view),
+            ],
+            active: 0,
+            compare: false,
+            differences: None,
+        }
+    }
+
+    pub fn active_pane(&self) -> &DirViewState {
+        &self.panes[self.active]
+    }
+
+    pub fn active_pane_mut(&mut self) -> &mut DirViewState {
+        &mut self.panes[self.active]
+    }
+
+    pub fn panes_mut(&mut self) -> &mut [DirViewState; 2] {
+        &mut self.panes
+    }
+
+    pub fn switch_active(&mut self) {
+        self.active = 1 - self.active;
+    }
+
+    /// Runs in the background, both panes are reloaded once the job is done
+    fn transfer(&mut self, mode: TransferMode, actions: &mut Vec<DockAction>) {
+        let inactive = 1 - self.active;
+
+        let sources = self.panes[self.active]
+            .selected_files()
+            .map(|file_data| file_data.location().clone())
+            .collect::<Vec<_>>();
+        if sources.is_empty() {
+            tracing::warn!("Nothing selected to {}", mode.verb());
+            return;
+        }
+
+        actions.push(DockAction::Transfer {
+            sources,
+            dest_dir: self.panes[inactive].file_data().location().clone(),
+            mode,
+        });
+    }
+
+    /// Recomputes [`Self::differences`] if either pane's listing changed since
+    fn update_differences(&mut self) {
+        let generations = self.panes.each_ref().map(DirViewState::generation);
+        if self
+            .differences
+            .as_ref()
+            .is_some_and(|differences| differences.generations == generations)
+        {
+            return;
+        }
+
+        self.differences = Some(Differences {
+            generations,
+            indices: self.differences(),
+        });
+    }
+
+    /// Indices of the items in each pane that are missing from the other pane
+    /// or differ from their namesake in type, size or modification time
+    fn differences(&self) -> [HashSet<usize>; 2] {
+        let diff = |this: &DirViewState, other: &DirViewState| {
+            let other_files = other
+                .files()
+                .map(|file| (file.name(), file))
+                .collect::<HashMap<_, _>>();
+
+            this.files()
+                .enumerate()
+                .filter(|(_, file)| {
+                    other_files
+                        .get(&file.name())
+                        .is_none_or(|other_file| !same_file(file, other_file))
+                })
+                .map(|(i, _)| i)
+                .collect::<HashSet<_>>()
+        };
+
+        [
+            diff(&self.panes[0], &self.panes[1]),
+            diff(&self.panes[1], &self.panes[0]),
+        ]
+    }
+}
+
+fn same_file(f1: &FileData, f2: &FileData) -> bool {
+    let is_dir = |f: &FileData| f.file_ty().map(|file_ty| file_ty.is_dir());
+
+    match (is_dir(f1), is_dir(f2)) {
+        (Some(true), Some(true)) => true,
+        (Some(false), Some(false)) => f1.size() == f2.size() && f1.modified() == f2.modified(),
+        _ => false,
+    }
+}
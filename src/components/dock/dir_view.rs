@@ -1,59 +1,239 @@
-use std::{cmp::Ordering, fs::FileType, ops::Div, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, ErrorKind, Write},
+    ops::Div,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
-use catppuccin_egui::Theme;
 use eframe::{
-    egui::{Button, Layout, Margin, ScrollArea, Widget},
+    egui::{
+        Button, ComboBox, Context, Layout, Margin, Modifiers, Response, RichText, ScrollArea,
+        Slider, Spinner, TextEdit, Ui, Widget,
+    },
     emath::{Align, Rangef},
-    epaint::{vec2, Vec2},
+    epaint::{vec2, Color32, Stroke, Vec2},
 };
 use egui_extras::Size;
 use egui_grid::GridBuilder;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
     button_newtype,
-    utils::{fs::FileData, icons::PhosphorIcon},
+    components::{dnd, toasts},
+    config::{DirViewIconSize, DirViewMode, ViewDefaults},
+    utils::{
+        archive::{self, ArchiveKind, ArchivePath, CompressOptions},
+        fs::{FileData, FileKind},
+        hidden,
+        icons::PhosphorIcon,
+        journal::Operation,
+        ls_colors::{self, LsStyle},
+        sorting,
+        theme::{Theme, ThemeExt},
+        thumbnails,
+        vfs::{self, Location, VfsEntry, VfsMetadata, WatchHandle},
+    },
 };
 
+use super::DockAction;
+
+mod batch_rename;
+
+use batch_rename::BatchRename;
+
 pub struct DirView<'a> {
     state: &'a mut DirViewState,
     theme: &'a Theme,
+    actions: &'a mut Vec<DockAction>,
+
+    highlighted: Option<&'a HashSet<usize>>,
+    templates_dir: Option<&'a Path>,
 }
 
 impl<'a> DirView<'a> {
-    pub fn new(state: &'a mut DirViewState, theme: &'a Theme) -> Self {
-        Self { state, theme }
+    pub fn new(
+        state: &'a mut DirViewState,
+        theme: &'a Theme,
+        actions: &'a mut Vec<DockAction>,
+    ) -> Self {
+        Self {
+            state,
+            theme,
+            actions,
+
+            highlighted: None,
+            templates_dir: None,
+        }
+    }
+
+    /// Outline the buttons with the given indices, e.g. to mark differences between directories
+    pub fn with_highlighted(mut self, highlighted: &'a HashSet<usize>) -> Self {
+        self.highlighted = Some(highlighted);
+        self
+    }
+
+    /// Where the "New" menu's templates come from
+    pub fn with_templates_dir(mut self, templates_dir: Option<&'a Path>) -> Self {
+        self.templates_dir = templates_dir;
+        self
     }
 }
 
 impl<'a> DirView<'a> {
-    fn select_button(&mut self, i: usize) {
-        let DirViewState {
-            buttons,
-            current_selected_button,
-            ..
-        } = self.state;
+    fn new_menu(&mut self, ui: &mut Ui) {
+        let dir = self.state.file_data.location().clone();
+        let mut entry = None;
+
+        if ui
+            .button(format!("{} New Folder", PhosphorIcon::FolderPlus.symbol()))
+            .clicked()
+        {
+            entry = Some(NewEntry::Folder);
+        }
+
+        if ui
+            .button(format!("{} New File", PhosphorIcon::FilePlus.symbol()))
+            .clicked()
+        {
+            entry = Some(NewEntry::File);
+        }
 
-        buttons[i].select();
-        *current_selected_button = Some(i);
+        let link_target = self
+            .state
+            .selected_file()
+            .map(|file_data| file_data.location().clone())
+            .filter(|_| dir.is_local());
+        let new_symlink = ui
+            .add_enabled(
+                link_target.is_some(),
+                Button::new(format!("{} New Symlink", PhosphorIcon::Link.symbol())),
+            )
+            .on_hover_text("Links to the selected file")
+            .on_disabled_hover_text("Select a local file to link to first");
+        if new_symlink.clicked() {
+            entry = link_target.map(NewEntry::Symlink);
+        }
+
+        ui.add_enabled_ui(self.templates_dir.is_some(), |ui| {
+            ui.menu_button(
+                format!("{} From template", PhosphorIcon::FileDashed.symbol()),
+                |ui| {
+                    if let Some(templates_dir) = self.templates_dir {
+                        templates_menu(ui, templates_dir, &mut self.state.templates, &mut entry);
+                    }
+                },
+            )
+        })
+        .response
+        .on_disabled_hover_text("There's no Templates directory");
+
+        if let Some(entry) = entry {
+            ui.close_menu();
+
+            match self.state.create(&entry) {
+                Ok(location) => self
+                    .actions
+                    .push(DockAction::Record(Operation::created(location))),
+                Err(err) => toasts::error(
+                    ui.ctx(),
+                    format!("Failed to create {} in {dir}: {err}", entry.name()),
+                ),
+            }
+        }
     }
+}
 
-    fn deselect_button(&mut self, i: usize) {
-        let DirViewState {
-            buttons,
-            current_selected_button,
-            ..
-        } = self.state;
+/// Files in a templates directory and whether each is a directory, sorted
+type TemplateListing = Result<Vec<(PathBuf, bool)>, String>;
 
-        buttons[i].deselect();
+fn list_templates(dir: &Path) -> TemplateListing {
+    let mut templates = std::fs::read_dir(dir)
+        .map_err(|err| format!("Failed to read {dir:?}: {err}"))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            !path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        })
+        .map(|path| {
+            let is_dir = path.is_dir();
+            (path, is_dir)
+        })
+        .collect::<Vec<_>>();
+    templates.sort();
+
+    Ok(templates)
+}
 
-        if &Some(i) == current_selected_button {
-            *current_selected_button = None;
+/// Subdirectories of the templates dir become submenus, like in GNOME. Each directory is read
+/// once per opening of the menu, `listings` keeps them until it closes
+fn templates_menu(
+    ui: &mut Ui,
+    dir: &Path,
+    listings: &mut HashMap<PathBuf, TemplateListing>,
+    entry: &mut Option<NewEntry>,
+) {
+    let templates = match listings
+        .entry(dir.to_path_buf())
+        .or_insert_with(|| list_templates(dir))
+    {
+        Ok(templates) => templates.clone(),
+        Err(err) => {
+            ui.label(err.as_str());
+            return;
         }
+    };
+
+    if templates.is_empty() {
+        ui.label("No templates yet, files put in the Templates directory show up here");
     }
+
+    templates.into_iter().for_each(|(path, is_dir)| {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+
+        match is_dir {
+            true => {
+                ui.menu_button(format!("{} {name}", PhosphorIcon::Folder.symbol()), |ui| {
+                    templates_menu(ui, &path, listings, entry)
+                });
+            }
+            false => {
+                if ui
+                    .button(format!("{} {name}", PhosphorIcon::File.symbol()))
+                    .clicked()
+                {
+                    *entry = Some(NewEntry::Template(path));
+                }
+            }
+        }
+    });
 }
 
 impl<'a> Widget for DirView<'a> {
     fn ui(mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        if ui.ui_contains_pointer() && ui.input(|i| i.pointer.any_pressed()) {
+            self.state.last_focused = ui.input(|i| i.time);
+        }
+
+        self.state.reload_on_changes(ui.ctx());
+
+        let loading = self.state.poll_listing();
+        if loading {
+            ui.ctx().request_repaint_after(Duration::from_millis(100));
+        }
+
         ui.vertical(|ui| {
             ui.horizontal(|ui| {
                 if ui
@@ -82,139 +262,789 @@ impl<'a> Widget for DirView<'a> {
                 {
                     self.state.history_forward();
                 }
+
+                let file_data = &self.state.file_data;
+                let read_only = file_data.is_archive()
+                    || file_data.is_archive_member()
+                    || file_data.is_read_only();
+                let new_menu = ui.add_enabled_ui(!read_only, |ui| {
+                    ui.menu_button(PhosphorIcon::Plus.rich_text(), |ui| self.new_menu(ui))
+                });
+                // Templates are listed afresh the next time it opens
+                if new_menu.inner.inner.is_none() {
+                    self.state.templates.clear();
+                }
+                new_menu.response.on_hover_text("New");
+
+                if ui
+                    .add_enabled(
+                        !read_only && self.state.selected_files().next().is_some(),
+                        Button::new(PhosphorIcon::Textbox.rich_text()),
+                    )
+                    .on_hover_text("Rename the selected files")
+                    .on_disabled_hover_text(
+                        "Select the files to rename first, Ctrl and Shift add to the selection",
+                    )
+                    .clicked()
+                {
+                    if let Err(err) = self.state.open_batch_rename() {
+                        toasts::error(
+                            ui.ctx(),
+                            format!("Failed to read {}: {err}", self.state.file_data.location()),
+                        );
+                    }
+                }
+
+                if loading {
+                    ui.add(Spinner::new()).on_hover_text("Listing…");
+                }
+
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    if ui
+                        .selectable_label(self.state.show_hidden, PhosphorIcon::Eye.rich_text())
+                        .on_hover_text("Show hidden files (Ctrl+H)")
+                        .clicked()
+                    {
+                        self.state.toggle_hidden();
+                    }
+
+                    if ui
+                        .button(switch_icon(self.state.view_mode).rich_text())
+                        .clicked()
+                    {
+                        self.state.view_mode.switch();
+                    }
+
+                    if ui
+                        .button(PhosphorIcon::MagnifyingGlassPlus.rich_text())
+                        .clicked()
+                    {
+                        self.state.icon_size.switch();
+                    }
+                });
             });
             ui.separator();
 
-            ScrollArea::vertical()
-                .auto_shrink([false, false])
-                .show(ui, |ui| {
-                    const MARGIN: f32 = 10.0;
-                    const SPACING: f32 = 20.0;
-
-                    let available_rect = ui.available_rect_before_wrap();
-                    let available_size = available_rect.size();
-
-                    let grid_size = available_size - vec2(MARGIN, MARGIN);
-                    let icon_size_vec2: Vec2 = self.state.icon_size.into();
-                    let button_size = icon_size_vec2 + vec2(20.0, 35.0);
-                    let num_per_row: usize =
-                        (grid_size.x + SPACING).div(button_size.x + SPACING).floor() as usize;
-                    let rows =
-                        (self.state.buttons.len() as f32 / num_per_row as f32).ceil() as usize;
-
-                    let mut new_history_path: Option<PathBuf> = None;
-
-                    let mut new_selected_button = None;
-
-                    (0..rows)
-                        .fold(
-                            GridBuilder::new()
-                                .with_margin(Margin::same(MARGIN))
-                                .spacing(SPACING, SPACING),
-                            |grid, _row| {
-                                grid.new_row(Size::Absolute {
-                                    initial: button_size.y,
-                                    range: Rangef::new(button_size.y, button_size.y),
-                                })
-                                .cells(
-                                    Size::Absolute {
-                                        initial: button_size.x,
-                                        range: Rangef::new(button_size.x, button_size.x),
-                                    },
-                                    num_per_row as i32,
-                                )
-                            },
-                        )
-                        .show(ui, |mut ui| {
-                            self.state.buttons.iter_mut().enumerate().for_each(
-                                |(i, button_state)| {
-                                    ui.cell(|ui| {
-                                        let response = ui.add(DirViewButton::new(
-                                            button_state,
-                                            self.theme,
-                                            self.state.icon_size,
-                                            button_size,
-                                        ));
-
-                                        if response.clicked() {
-                                            new_selected_button = Some(i);
-                                        }
-
-                                        if button_state.rsfm.double_clicked() {
-                                            tracing::warn!("Button double clicked");
-                                            new_history_path =
-                                                Some(button_state.file_data.path().clone());
-                                        }
-
-                                        if let Some(text) = button_state.rsfm.text().clone() {
-                                            if &text != button_state.file_data.name() {
-                                                button_state.file_data.rename(text);
-                                            }
-                                        }
-                                    });
+            if let Some(batch_rename) = &mut self.state.batch_rename {
+                let id = ui.id().with("batch_rename");
+                if !batch_rename.show(ui.ctx(), id, self.theme, self.actions) {
+                    self.state.batch_rename = None;
+                    self.state.reload();
+                }
+            }
+
+            if let Some(err) = &self.state.listing_error {
+                ui.label(RichText::new(err).color(self.theme.error_color()));
+            }
+
+            let mut scroll_area = ScrollArea::vertical().auto_shrink([false, false]);
+            if self.state.restore_scroll_offset {
+                scroll_area = scroll_area.scroll_offset(self.state.scroll_offset);
+                self.state.restore_scroll_offset = false;
+            }
+
+            let highlighted = self.highlighted;
+            let selection = self
+                .state
+                .selected_files()
+                .map(|file_data| file_data.path().clone())
+                .collect::<Vec<_>>();
+
+            let scroll_output = scroll_area.show(ui, |ui| {
+                let mut new_history_path: Option<Location> = None;
+
+                let mut clicked_button = None;
+
+                match self.state.view_mode {
+                    DirViewMode::Grid => {
+                        const MARGIN: f32 = 10.0;
+                        const SPACING: f32 = 20.0;
+
+                        let available_rect = ui.available_rect_before_wrap();
+                        let available_size = available_rect.size();
+
+                        let grid_size = available_size - vec2(MARGIN, MARGIN);
+                        let icon_size_vec2: Vec2 = self.state.icon_size.into();
+                        let button_size = icon_size_vec2 + vec2(20.0, 35.0);
+                        let num_per_row: usize =
+                            (grid_size.x + SPACING).div(button_size.x + SPACING).floor() as usize;
+                        let rows =
+                            (self.state.buttons.len() as f32 / num_per_row as f32).ceil() as usize;
+
+                        (0..rows)
+                            .fold(
+                                GridBuilder::new()
+                                    .with_margin(Margin::same(MARGIN))
+                                    .spacing(SPACING, SPACING),
+                                |grid, _row| {
+                                    grid.new_row(Size::Absolute {
+                                        initial: button_size.y,
+                                        range: Rangef::new(button_size.y, button_size.y),
+                                    })
+                                    .cells(
+                                        Size::Absolute {
+                                            initial: button_size.x,
+                                            range: Rangef::new(button_size.x, button_size.x),
+                                        },
+                                        num_per_row as i32,
+                                    )
                                 },
-                            );
-                        });
-
-                    if new_selected_button != self.state.current_selected_button {
-                        match (self.state.current_selected_button, new_selected_button) {
-                            (None, None) | (Some(_), None) => {}
-                            (None, Some(new_button)) => self.select_button(new_button),
-                            (Some(old_button), Some(new_button)) => {
-                                self.deselect_button(old_button);
-                                self.select_button(new_button);
-                            }
-                        }
+                            )
+                            .show(ui, |mut ui| {
+                                self.state.buttons.iter_mut().enumerate().for_each(
+                                    |(i, button_state)| {
+                                        ui.cell(|ui| {
+                                            let was_selected = button_state.selected();
+                                            let response = ui.add(DirViewButton::new(
+                                                button_state,
+                                                self.theme,
+                                                self.state.icon_size,
+                                                button_size,
+                                                DirViewMode::Grid,
+                                            ));
+
+                                            paint_highlight(
+                                                ui,
+                                                &response,
+                                                self.theme,
+                                                highlighted,
+                                                i,
+                                            );
+                                            handle_button_dnd(
+                                                ui,
+                                                &response,
+                                                self.theme,
+                                                button_state,
+                                            );
+                                            handle_button_response(
+                                                i,
+                                                was_selected,
+                                                &response,
+                                                button_state,
+                                                &selection,
+                                                self.actions,
+                                                &mut clicked_button,
+                                                &mut new_history_path,
+                                            );
+                                        });
+                                    },
+                                );
+                            });
                     }
+                    DirViewMode::List => {
+                        let button_size = vec2(ui.available_width(), LIST_ROW_HEIGHT);
 
-                    if let Some(new_history_path) = new_history_path {
-                        if self.state.current_history_ind < self.state.history.len() - 1 {
-                            if self.state.history[self.state.current_history_ind + 1]
-                                == new_history_path
-                            {
-                                self.state.history_forward();
-                                return;
-                            }
-
-                            self.state
-                                .history
-                                .drain(self.state.current_history_ind + 1..);
-                        }
+                        self.state
+                            .buttons
+                            .iter_mut()
+                            .enumerate()
+                            .for_each(|(i, button_state)| {
+                                let was_selected = button_state.selected();
+                                let response = ui.add(DirViewButton::new(
+                                    button_state,
+                                    self.theme,
+                                    self.state.icon_size,
+                                    button_size,
+                                    DirViewMode::List,
+                                ));
 
-                        self.state.history.push(new_history_path);
-                        self.state.history_forward();
+                                paint_highlight(ui, &response, self.theme, highlighted, i);
+                                handle_button_dnd(ui, &response, self.theme, button_state);
+                                handle_button_response(
+                                    i,
+                                    was_selected,
+                                    &response,
+                                    button_state,
+                                    &selection,
+                                    self.actions,
+                                    &mut clicked_button,
+                                    &mut new_history_path,
+                                );
+                            });
                     }
-                })
+                }
+
+                if let Some((i, was_selected)) = clicked_button {
+                    let modifiers = ui.input(|i| i.modifiers);
+                    self.state.click_button(i, was_selected, modifiers);
+                }
+
+                if let Some(new_history_path) = new_history_path {
+                    self.state.navigate_to(new_history_path);
+                }
+            });
+
+            self.state.scroll_offset = scroll_output.state.offset;
+
+            // Archives are read-only, remote directories upload in the background
+            let file_data = &self.state.file_data;
+            if file_data.is_dir() && !file_data.is_archive_member() {
+                match file_data.location().is_local() {
+                    true => {
+                        dnd::drop_target(ui, scroll_output.inner_rect, self.theme, file_data.path())
+                    }
+                    false => {
+                        if let Some((paths, action)) =
+                            dnd::take_dropped_paths(ui, scroll_output.inner_rect, self.theme)
+                        {
+                            self.actions.push(DockAction::Transfer {
+                                sources: paths.into_iter().map(Location::local).collect(),
+                                dest_dir: file_data.location().clone(),
+                                mode: action.into(),
+                            });
+                        }
+                    }
+                }
+            }
         })
         .response
     }
 }
 
+fn paint_highlight(
+    ui: &Ui,
+    response: &Response,
+    theme: &Theme,
+    highlighted: Option<&HashSet<usize>>,
+    i: usize,
+) {
+    if highlighted.is_some_and(|highlighted| highlighted.contains(&i)) {
+        ui.painter().rect_stroke(
+            response.rect,
+            0.0,
+            Stroke::new(2.0, theme.difference_color()),
+        );
+    }
+}
+
+/// Every local button can be dragged, directories outside of archives also accept drops
+fn handle_button_dnd(
+    ui: &Ui,
+    response: &Response,
+    theme: &Theme,
+    button_state: &DirViewButtonState,
+) {
+    let file_data = &button_state.file_data;
+    let path = file_data.path();
+
+    if !file_data.location().is_local() {
+        return;
+    }
+
+    dnd::drag_source(response, || vec![path.clone()]);
+
+    if file_data.is_dir() && !file_data.is_archive_member() {
+        dnd::drop_target(ui, response.rect, theme, path);
+    }
+}
+
+/// `clicked_button` gets the button's index and whether it was selected before the click.
+/// `selection` holds the paths of every selected button
+#[allow(clippy::too_many_arguments)]
+fn handle_button_response(
+    i: usize,
+    was_selected: bool,
+    response: &Response,
+    button_state: &mut DirViewButtonState,
+    selection: &[PathBuf],
+    actions: &mut Vec<DockAction>,
+    clicked_button: &mut Option<(usize, bool)>,
+    new_history_path: &mut Option<Location>,
+) {
+    if response.clicked() {
+        *clicked_button = Some((i, was_selected));
+    }
+
+    let file_data = &button_state.file_data;
+    let is_member = file_data.is_archive_member();
+    let is_local = file_data.location().is_local();
+
+    response.clone().context_menu(|ui| {
+        // Right clicking one of the selected files acts on all of them
+        let targets = match button_state.rsfm.selected() {
+            true => selection.to_vec(),
+            false => vec![file_data.path().clone()],
+        };
+
+        if !is_member && ui.button("Rename").clicked() {
+            button_state.rsfm.start_editing_text();
+            ui.close_menu();
+        }
+
+        if file_data.is_dir() && is_local && !is_member && ui.button("Add to bookmarks").clicked() {
+            actions.push(DockAction::AddBookmark(file_data.path().clone()));
+            ui.close_menu();
+        }
+
+        if is_member {
+            if !file_data.is_dir()
+                && ui
+                    .button(format!("{} Open", PhosphorIcon::ArrowSquareOut.symbol()))
+                    .clicked()
+            {
+                preview_archive_member(ui.ctx(), file_data);
+                ui.close_menu();
+            }
+
+            ui.menu_button(
+                format!("{} Extract to", PhosphorIcon::Export.symbol()),
+                |ui| {
+                    extract_menu(ui, file_data, &targets, actions);
+                },
+            );
+        } else if !is_local {
+            if ui
+                .button(format!("{} Download", PhosphorIcon::Download.symbol()))
+                .clicked()
+            {
+                actions.push(DockAction::Download(file_data.location().clone()));
+                ui.close_menu();
+            }
+        } else {
+            if file_data.is_archive() {
+                if ui
+                    .button(format!("{} Extract here", PhosphorIcon::Export.symbol()))
+                    .clicked()
+                {
+                    actions.push(DockAction::Extract {
+                        archive: file_data.path().clone(),
+                        dest_dir: parent_dir(file_data.path()).to_path_buf(),
+                        password: None,
+                    });
+                    ui.close_menu();
+                }
+
+                ui.menu_button(
+                    format!("{} Extract to", PhosphorIcon::Export.symbol()),
+                    |ui| {
+                        extract_archive_menu(ui, file_data, actions);
+                    },
+                );
+            }
+
+            ui.menu_button(
+                format!("{} Compress", PhosphorIcon::FileZip.symbol()),
+                |ui| {
+                    compress_menu(ui, file_data, targets.clone(), actions);
+                },
+            );
+
+            if ui
+                .button(format!("{} Move to Trash", PhosphorIcon::Trash.symbol()))
+                .on_hover_text("Delete")
+                .clicked()
+            {
+                let locations = targets.iter().cloned().map(Location::local).collect();
+                actions.push(DockAction::Trash(locations));
+                ui.close_menu();
+            }
+        }
+    });
+
+    if button_state.rsfm.double_clicked() {
+        match is_member && !file_data.is_dir() {
+            true => preview_archive_member(&response.ctx, file_data),
+            false => *new_history_path = Some(file_data.location().clone()),
+        }
+    }
+
+    if button_state.rsfm.take_committed() {
+        commit_rename(&response.ctx, button_state, actions);
+    }
+}
+
+/// Renames the file to its edited label, a rejected name reopens the editor
+fn commit_rename(
+    ctx: &Context,
+    button_state: &mut DirViewButtonState,
+    actions: &mut Vec<DockAction>,
+) {
+    let Some(new_name) = button_state.rsfm.text().clone() else {
+        return;
+    };
+    let file_data = &mut button_state.file_data;
+    if &new_name == file_data.name() {
+        return;
+    }
+
+    let from = file_data.location().clone();
+    match file_data.rename(&new_name) {
+        Ok(()) => actions.push(DockAction::Record(Operation::moved(
+            from,
+            file_data.location().clone(),
+        ))),
+        Err(err) => {
+            toasts::error(ctx, format!("Failed to rename {}: {err}", file_data.name()));
+            let name = file_data.name().clone();
+            button_state.rsfm.set_text(name);
+            button_state.rsfm.resume_editing_text();
+        }
+    }
+}
+
+/// Extracts the member into the cache and opens it with the default application
+fn preview_archive_member(ctx: &Context, file_data: &FileData) {
+    let Some(archive_path) = ArchivePath::parse(file_data.path()) else {
+        return;
+    };
+
+    let res = archive::extract_for_preview(&archive_path)
+        .and_then(|path| opener::open(&path).map_err(std::io::Error::other));
+    if let Err(err) = res {
+        toasts::error(ctx, format!("Failed to open {}: {err}", file_data.name()));
+    }
+}
+
+/// Destination picker for extracting the targeted archive members, defaults to their archive's dir
+fn extract_menu(
+    ui: &mut Ui,
+    file_data: &FileData,
+    targets: &[PathBuf],
+    actions: &mut Vec<DockAction>,
+) {
+    let Some(archive_path) = ArchivePath::parse(file_data.path()) else {
+        return;
+    };
+
+    let id = ui.id().with("extract_dest");
+    let mut dest = ui.data_mut(|d| {
+        d.get_temp_mut_or_insert_with(id, || {
+            parent_dir(&archive_path.archive)
+                .to_string_lossy()
+                .to_string()
+        })
+        .clone()
+    });
+
+    ui.text_edit_singleline(&mut dest);
+    if ui.button("Extract").clicked() {
+        let members = targets
+            .iter()
+            .filter_map(|path| ArchivePath::parse(path))
+            .filter(|member| member.archive == archive_path.archive)
+            .map(|member| member.inner)
+            .collect();
+        actions.push(DockAction::ExtractMembers {
+            archive: archive_path.archive,
+            members,
+            dest_dir: PathBuf::from(&dest),
+        });
+        ui.close_menu();
+    }
+
+    ui.data_mut(|d| d.insert_temp(id, dest));
+}
+
+/// Destination and password for extracting a whole archive, defaults to the archive's directory
+fn extract_archive_menu(ui: &mut Ui, file_data: &FileData, actions: &mut Vec<DockAction>) {
+    let id = ui.id().with("extract_archive_form");
+    let (mut dest, mut password) = ui.data_mut(|d| {
+        d.get_temp_mut_or_insert_with(id, || {
+            (
+                parent_dir(file_data.path()).to_string_lossy().to_string(),
+                String::new(),
+            )
+        })
+        .clone()
+    });
+
+    ui.text_edit_singleline(&mut dest);
+    ui.horizontal(|ui| {
+        ui.label("Password");
+        ui.add(TextEdit::singleline(&mut password).password(true));
+    });
+
+    if ui.button("Extract").clicked() {
+        actions.push(DockAction::Extract {
+            archive: file_data.path().clone(),
+            dest_dir: PathBuf::from(&dest),
+            password: (!password.is_empty()).then(|| password.clone()),
+        });
+        ui.close_menu();
+    }
+
+    ui.data_mut(|d| d.insert_temp(id, (dest, password)));
+}
+
+#[derive(Clone)]
+struct CompressForm {
+    name: String,
+    options: CompressOptions,
+    password: String,
+}
+
+/// Name, format, level and password of a new archive with `sources` in it,
+/// next to `file_data`, the file that was right clicked
+fn compress_menu(
+    ui: &mut Ui,
+    file_data: &FileData,
+    sources: Vec<PathBuf>,
+    actions: &mut Vec<DockAction>,
+) {
+    let id = ui.id().with("compress_form");
+    let mut form = ui.data_mut(|d| {
+        d.get_temp_mut_or_insert_with(id, || CompressForm {
+            name: match file_data.is_dir() {
+                true => file_data.name().clone(),
+                false => archive::archive_stem(Path::new(file_data.name())),
+            },
+            options: CompressOptions::default(),
+            password: String::new(),
+        })
+        .clone()
+    });
+
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut form.name);
+
+        ComboBox::from_id_source(id.with("kind"))
+            .selected_text(form.options.kind.extension())
+            .show_ui(ui, |ui| {
+                ArchiveKind::COMPRESSIBLE.into_iter().for_each(|kind| {
+                    ui.selectable_value(&mut form.options.kind, kind, kind.extension());
+                });
+            });
+    });
+    ui.add(Slider::new(&mut form.options.level, 0..=9).text("Level"));
+
+    if form.options.kind.supports_password() {
+        ui.horizontal(|ui| {
+            ui.label("Password");
+            ui.add(TextEdit::singleline(&mut form.password).password(true));
+        });
+    }
+
+    let name = form.name.trim();
+    if ui
+        .add_enabled(!name.is_empty(), Button::new("Create"))
+        .clicked()
+    {
+        let dest =
+            parent_dir(file_data.path()).join(format!("{name}{}", form.options.kind.extension()));
+
+        actions.push(DockAction::Compress {
+            sources,
+            dest,
+            options: CompressOptions {
+                password: (form.options.kind.supports_password() && !form.password.is_empty())
+                    .then(|| form.password.clone()),
+                ..form.options
+            },
+        });
+        ui.close_menu();
+        ui.data_mut(|d| d.remove::<CompressForm>(id));
+        return;
+    }
+
+    ui.data_mut(|d| d.insert_temp(id, form));
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    path.parent().unwrap_or(path)
+}
+
+#[derive(Clone)]
 pub struct DirViewState {
     file_data: FileData,
     buttons: Vec<DirViewButtonState>,
+    /// Bumped whenever [`Self::buttons`] are replaced or extended
+    generation: u64,
     current_selected_button: Option<usize>,
     icon_size: DirViewIconSize,
+    view_mode: DirViewMode,
+    show_hidden: bool,
+    /// Names listed in the directory's `.hidden` file
+    dot_hidden: HashSet<String>,
+
+    scroll_offset: Vec2,
+    restore_scroll_offset: bool,
+    /// [`eframe::egui::InputState::time`] of the last click inside the view
+    last_focused: f64,
 
-    history: Vec<PathBuf>,
+    history: Vec<Location>,
     current_history_ind: usize,
+
+    /// Remote directories are listed in the background, pages are added as they arrive
+    listing: Option<PendingListing>,
+    /// Why the directory couldn't be listed
+    listing_error: Option<String>,
+    /// Something that was just created, renamed as soon as it's listed
+    pending_rename: Option<Location>,
+    batch_rename: Option<BatchRename>,
+    /// Templates directories listed while the "New" menu is open
+    templates: HashMap<PathBuf, TemplateListing>,
+    /// Keeps the backend watching the directory, `None` where it can't
+    watch: Option<Arc<WatchHandle>>,
+    changes: Arc<DirChanges>,
+    /// [`eframe::egui::InputState::time`] of the last reload the watcher caused
+    last_watch_reload: f64,
 }
 
+/// Seconds at least between the reloads the watcher causes
+const WATCH_RELOAD_INTERVAL: f64 = 1.0;
+
+/// Set from the backend's watcher when the directory changes
+#[derive(Default)]
+struct DirChanges {
+    changed: AtomicBool,
+    /// The view's, to show the change without waiting for input
+    ctx: Mutex<Option<Context>>,
+}
+
+impl DirChanges {
+    fn notify(&self) {
+        self.changed.store(true, Ordering::Relaxed);
+        if let Some(ctx) = &*self.ctx.lock().expect("Directory changes poisoned") {
+            ctx.request_repaint();
+        }
+    }
+}
+
+/// What the "New" menu creates
+pub enum NewEntry {
+    Folder,
+    File,
+    /// A link to the location
+    Symlink(Location),
+    /// A copy of a file in the templates dir
+    Template(PathBuf),
+}
+
+impl NewEntry {
+    fn name(&self) -> String {
+        match self {
+            NewEntry::Folder => "New Folder".into(),
+            NewEntry::File => "New File".into(),
+            NewEntry::Symlink(target) => format!(
+                "Link to {}",
+                target
+                    .path()
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+            ),
+            NewEntry::Template(path) => path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+
+    /// Creates it in `dir` under the first free name
+    fn create(&self, dir: &Location) -> io::Result<Location> {
+        let backend = dir.backend()?;
+
+        vfs::create_unique(&dir.join(self.name()), |location| match self {
+            NewEntry::Folder => backend.create_dir(location.path()),
+            NewEntry::File => backend.create_new(location.path())?.flush(),
+            NewEntry::Symlink(target) => {
+                // Relative when it's in the same directory, so the pair can be moved together
+                let target = target
+                    .path()
+                    .strip_prefix(dir.path())
+                    .unwrap_or(target.path());
+                std::os::unix::fs::symlink(target, location.path())
+            }
+            NewEntry::Template(path) => {
+                let mut writer = backend.create_new(location.path())?;
+                io::copy(&mut File::open(path)?, &mut writer)?;
+                writer.flush()
+            }
+        })
+    }
+}
+
+/// What the background listing sends, the directory's own metadata and the `.hidden` names
+/// come before the first page
+enum ListingMsg {
+    Meta(VfsMetadata),
+    DotHidden(HashSet<String>),
+    Page(Vec<VfsEntry>),
+}
+
+type PendingListing = Arc<Mutex<Receiver<io::Result<ListingMsg>>>>;
+
 impl DirViewState {
-    pub fn new(file_data: FileData) -> Self {
-        let files = Self::get_files(&file_data);
+    /// A new tab set up like `view` says
+    pub fn with_view(file_data: FileData, view: &ViewDefaults) -> Self {
+        let mut res = Self::unloaded(file_data);
+        res.view_mode = view.mode;
+        res.icon_size = view.icon_size;
+        res.show_hidden = view.show_hidden;
+        res.load();
+
+        res
+    }
 
-        let history = vec![file_data.path().clone()];
-        let buttons = Self::files_to_buttons(files);
+    /// Everything but the listing, so settings that change it are in place for the first load
+    fn unloaded(file_data: FileData) -> Self {
+        let history = vec![file_data.location().clone()];
 
         Self {
             file_data,
-            buttons,
+            buttons: vec![],
+            generation: 0,
             current_selected_button: None,
             icon_size: DirViewIconSize::Small,
+            view_mode: DirViewMode::Grid,
+            show_hidden: false,
+            dot_hidden: HashSet::new(),
+
+            scroll_offset: Vec2::ZERO,
+            restore_scroll_offset: false,
+            last_focused: 0.0,
 
             history,
             current_history_ind: 0,
+
+            listing: None,
+            listing_error: None,
+            pending_rename: None,
+            batch_rename: None,
+            templates: HashMap::new(),
+            watch: None,
+            changes: Arc::default(),
+            last_watch_reload: f64::NEG_INFINITY,
+        }
+    }
+
+    fn from_snapshot(snapshot: DirViewStateSnapshot) -> Self {
+        let DirViewStateSnapshot {
+            mut history,
+            current_history_ind,
+            icon_size,
+            view_mode,
+            show_hidden,
+            scroll_offset,
+        } = snapshot;
+
+        if history.is_empty() {
+            history.push(Location::local(dirs::home_dir().unwrap_or_default()));
+        }
+        let current_history_ind = current_history_ind.min(history.len() - 1);
+
+        let mut res = Self::unloaded(FileData::at(history[current_history_ind].clone()));
+        res.history = history;
+        res.current_history_ind = current_history_ind;
+        res.icon_size = icon_size;
+        res.view_mode = view_mode;
+        res.show_hidden = show_hidden;
+        res.scroll_offset = scroll_offset;
+        res.restore_scroll_offset = true;
+        res.load();
+
+        res
+    }
+
+    fn snapshot(&self) -> DirViewStateSnapshot {
+        DirViewStateSnapshot {
+            history: self.history.clone(),
+            current_history_ind: self.current_history_ind,
+            icon_size: self.icon_size,
+            view_mode: self.view_mode,
+            show_hidden: self.show_hidden,
+            scroll_offset: self.scroll_offset,
         }
     }
 
@@ -222,90 +1052,167 @@ impl DirViewState {
         &self.file_data
     }
 
-    fn get_files(file_data: &FileData) -> Vec<FileData> {
-        let mut files = walkdir::WalkDir::new(file_data.path())
-            .min_depth(1)
-            .max_depth(1)
-            .into_iter()
-            .flat_map(|e| e.map(|e| FileData::new(e.path())))
-            .collect::<Vec<_>>();
+    /// Changes whenever the listing does, for caching things computed from [`Self::files`]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
 
-        files.sort_by(|f1, f2| {
-            let f1_t: Option<FileType> = f1.file_ty();
-            let f2_t: Option<FileType> = f2.file_ty();
-
-            let cmp_nonsym_fts = |f1_t: FileType, f2_t: FileType| {
-                match (f1_t.is_dir(), f2_t.is_dir()) {
-                    (true, true) // isdir & isdir
-                    | (false, false) // isfile & isfile
-                    => f1.name().cmp(f2.name()),
-                    // isdir & isfile
-                    (true, false) => Ordering::Less,
-                    // isfile & isdir
-                    (false, true) => Ordering::Greater,
-                }
-            };
-
-            match (f1_t, f2_t) {
-                (None, None) => f1.name().cmp(f2.name()),
-                (Some(_f1_t), None) => Ordering::Less,
-                (None, Some(_f2_t)) => Ordering::Greater,
-                (Some(f1_t), Some(f2_t)) => {
-                    match (f1_t.is_symlink(), f2_t.is_symlink()) {
-                        // sym & sym
-                        (true, true) => match (f1.rec_linked_file_ty(), f2.rec_linked_file_ty()) {
-                            (None, None) => f1.name().cmp(f2.name()),
-                            (None, Some(_f2_t)) => Ordering::Greater,
-                            (Some(_f1_t), None) => Ordering::Less,
-                            (Some(f1_t), Some(f2_t)) => cmp_nonsym_fts(f1_t, f2_t),
-                        },
-                        // sym & nonsym
-                        (true, false) => {
-                            match f1.rec_linked_file_ty() {
-                                Some(f1_t) => match (f1_t.is_dir(), f2_t.is_dir()) {
-                                    (true, true) // symdir & isdir
-                                    | (false, true) // symfile & isdir
-                                    => Ordering::Greater,
-
-                                    (true, false) // symdir & isfile
-                                    | (false, false) // symfile & isfile
-                                    => Ordering::Less,
-                                },
-                                None => Ordering::Greater,
-                            }
-                        }
-                        // nonsym & sym
-                        (false, true) => {
-                            match f2.rec_linked_file_ty() {
-                                Some(f2_t) => match (f1_t.is_dir(), f2_t.is_dir()) {
-                                    (true, true) // symdir & isdir
-                                    | (false, true) // symfile & isdir
-                                    => Ordering::Less,
-
-                                    (true, false) // symdir & isfile
-                                    | (false, false) // symfile & isfile
-                                    => Ordering::Greater,
-                                },
-                                None => Ordering::Less,
-                            }
-                        }
-                        // nonsym & nonsym
-                        (false, false) => cmp_nonsym_fts(f1_t, f2_t),
-                    }
+    pub fn last_focused(&self) -> f64 {
+        self.last_focused
+    }
+
+    fn set_show_hidden(&mut self, show_hidden: bool) {
+        if self.show_hidden != show_hidden {
+            self.show_hidden = show_hidden;
+            self.load();
+        }
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.set_show_hidden(!self.show_hidden);
+    }
+
+    /// Creates the entry in the current directory and starts renaming it
+    pub fn create(&mut self, entry: &NewEntry) -> io::Result<Location> {
+        let location = entry.create(self.file_data.location())?;
+
+        self.pending_rename = Some(location.clone());
+        self.load();
+        self.start_pending_rename();
+
+        Ok(location)
+    }
+
+    fn start_pending_rename(&mut self) {
+        let Some(location) = &self.pending_rename else {
+            return;
+        };
+        let Some(i) = self
+            .buttons
+            .iter()
+            .position(|button| button.file_data.location() == location)
+        else {
+            return;
+        };
+
+        self.pending_rename = None;
+        self.buttons[i].select();
+        self.buttons[i].start_editing_text();
+        self.current_selected_button = Some(i);
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &FileData> {
+        self.buttons.iter().map(|button| &button.file_data)
+    }
+
+    pub fn selected_file(&self) -> Option<&FileData> {
+        self.current_selected_button
+            .map(|i| &self.buttons[i].file_data)
+    }
+
+    /// Opens the batch rename dialog for the selected files
+    fn open_batch_rename(&mut self) -> io::Result<()> {
+        let files = self.selected_files().cloned().collect();
+        self.batch_rename = Some(BatchRename::new(self.file_data.location(), files)?);
+        Ok(())
+    }
+
+    pub fn selected_files(&self) -> impl Iterator<Item = &FileData> {
+        self.buttons
+            .iter()
+            .filter(|button| button.selected())
+            .map(|button| &button.file_data)
+    }
+
+    /// Plain clicks select just the button, Ctrl toggles it and Shift selects the range from the
+    /// button clicked before
+    fn click_button(&mut self, i: usize, was_selected: bool, modifiers: Modifiers) {
+        if let Some(anchor) = self.current_selected_button.filter(|_| modifiers.shift) {
+            let range = anchor.min(i)..=anchor.max(i);
+            self.buttons
+                .iter_mut()
+                .enumerate()
+                .for_each(|(j, button)| match range.contains(&j) {
+                    true => button.select(),
+                    false if !modifiers.command => button.deselect(),
+                    false => {}
+                });
+            return;
+        }
+
+        match modifiers.command {
+            true if was_selected => {
+                self.buttons[i].deselect();
+                if self.current_selected_button == Some(i) {
+                    self.current_selected_button =
+                        self.buttons.iter().position(|button| button.selected());
                 }
             }
-        });
+            true => self.current_selected_button = Some(i),
+            false => {
+                self.buttons.iter_mut().for_each(|button| button.deselect());
+                self.buttons[i].select();
+                self.current_selected_button = Some(i);
+            }
+        }
+    }
 
-        files
+    fn get_files(file_data: &FileData) -> io::Result<Vec<FileData>> {
+        let location = file_data.location();
+
+        let mut files = location
+            .backend()?
+            .list(location.path())?
+            .into_iter()
+            .map(|entry| FileData::from_entry(location.vfs(), entry))
+            .collect::<Vec<_>>();
+
+        files.sort_by(sorting::cmp_files);
+
+        Ok(files)
     }
 
-    fn files_to_buttons(files: Vec<FileData>) -> Vec<DirViewButtonState> {
+    /// Leaves out hidden files unless they're shown
+    fn files_to_buttons(&self, files: Vec<FileData>) -> Vec<DirViewButtonState> {
         files
             .into_iter()
-            .map(|fd| DirViewButtonState::new(RSFMButtonState::default().with_text(fd.name()), fd))
+            .filter_map(|fd| {
+                let hidden = hidden::is_hidden(fd.name(), &self.dot_hidden);
+                if hidden && !self.show_hidden {
+                    return None;
+                }
+
+                let kind = fd.kind();
+                let style = ls_colors::style(&fd, kind);
+                let thumbnail = thumbnails::uri(&fd);
+                Some(DirViewButtonState::new(
+                    RSFMButtonState::default().with_text(fd.name()),
+                    fd,
+                    kind,
+                    style,
+                    hidden,
+                    thumbnail,
+                ))
+            })
             .collect()
     }
 
+    pub fn navigate_to(&mut self, location: impl Into<Location>) {
+        let location = location.into();
+
+        if self.current_history_ind < self.history.len() - 1 {
+            if self.history[self.current_history_ind + 1] == location {
+                self.history_forward();
+                return;
+            }
+
+            self.history.drain(self.current_history_ind + 1..);
+        }
+
+        self.history.push(location);
+        self.history_forward();
+    }
+
     pub fn history_backward(&mut self) {
         if self.current_history_ind == 0 {
             return;
@@ -328,25 +1235,231 @@ impl DirViewState {
 
     pub fn set_current_history(&mut self, ind: usize) {
         self.current_history_ind = ind;
-        self.file_data = FileData::new(self.history[ind].clone());
-        self.buttons = Self::files_to_buttons(Self::get_files(&self.file_data));
+        self.file_data = FileData::at(self.history[ind].clone());
+        self.load();
+    }
+
+    fn load(&mut self) {
+        self.current_selected_button = None;
+        self.generation += 1;
+        self.listing_error = None;
+
+        let location = self.file_data.location().clone();
+        self.watch(&location);
+        // Archives are indexed on first listing, which can take as long as a remote directory
+        let in_archive = location.is_local() && ArchivePath::parse(location.path()).is_some();
+        match location.is_local() && !in_archive {
+            true => {
+                self.dot_hidden = hidden::read_dot_hidden(&location);
+                let files = Self::get_files(&self.file_data).unwrap_or_else(|err| {
+                    self.listing_error = Some(format!("Failed to list {location}: {err}"));
+                    vec![]
+                });
+                self.buttons = self.files_to_buttons(files);
+                self.listing = None;
+            }
+            false => {
+                self.dot_hidden.clear();
+                self.buttons.clear();
+                self.listing = Some(Self::list_in_background(location));
+            }
+        }
+    }
+
+    /// Replaces the watcher with one for `location`, changes seen by the old one are dropped
+    fn watch(&mut self, location: &Location) {
+        let ctx = self
+            .changes
+            .ctx
+            .lock()
+            .expect("Directory changes poisoned")
+            .clone();
+        self.changes = Arc::new(DirChanges {
+            changed: AtomicBool::new(false),
+            ctx: Mutex::new(ctx),
+        });
+
+        let changes = self.changes.clone();
+        let watch = location
+            .backend()
+            .and_then(|vfs| vfs.watch(location.path(), Box::new(move || changes.notify())));
+        self.watch = match watch {
+            Ok(handle) => Some(Arc::new(handle)),
+            Err(err) if err.kind() == ErrorKind::Unsupported => None,
+            Err(err) => {
+                tracing::warn!("Failed to watch {location}: {err}");
+                None
+            }
+        };
+    }
+
+    /// Whether the directory changed since the last call, so the view should reload
+    fn take_changes(&self, ctx: &Context) -> bool {
+        self.changes
+            .ctx
+            .lock()
+            .expect("Directory changes poisoned")
+            .get_or_insert_with(|| ctx.clone());
+
+        self.changes.changed.swap(false, Ordering::Relaxed)
+    }
+
+    /// Reloads what the watcher saw change, at most every [`WATCH_RELOAD_INTERVAL`] as a file
+    /// being written changes all the time. The selection stays
+    fn reload_on_changes(&mut self, ctx: &Context) {
+        let now = ctx.input(|i| i.time);
+        let wait = self.last_watch_reload + WATCH_RELOAD_INTERVAL - now;
+        if wait > 0.0 {
+            if self.changes.changed.load(Ordering::Relaxed) {
+                ctx.request_repaint_after(Duration::from_secs_f64(wait));
+            }
+            return;
+        }
+        if !self.take_changes(ctx) {
+            return;
+        }
+        self.last_watch_reload = now;
+
+        let selected = self
+            .selected_files()
+            .map(|file_data| file_data.location().clone())
+            .collect::<HashSet<_>>();
+        self.reload();
+        self.buttons
+            .iter_mut()
+            .filter(|button| selected.contains(button.file_data.location()))
+            .for_each(|button| button.select());
+    }
+
+    /// The listing stops once the receiver is dropped, e.g. after navigating elsewhere
+    fn list_in_background(location: Location) -> PendingListing {
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            let meta = location.backend().and_then(|vfs| vfs.stat(location.path()));
+            match meta {
+                Ok(meta) => {
+                    if sender.send(Ok(ListingMsg::Meta(meta))).is_err() {
+                        return;
+                    }
+                }
+                Err(err) => {
+                    let _ = sender.send(Err(err));
+                    return;
+                }
+            }
+
+            let dot_hidden = hidden::read_dot_hidden(&location);
+            if sender.send(Ok(ListingMsg::DotHidden(dot_hidden))).is_err() {
+                return;
+            }
+
+            let res = location.backend().and_then(|vfs| {
+                vfs.list_pages(location.path(), &mut |page| {
+                    sender.send(Ok(ListingMsg::Page(page))).is_ok()
+                })
+            });
+
+            if let Err(err) = res {
+                let _ = sender.send(Err(err));
+            }
+        });
+
+        Arc::new(Mutex::new(receiver))
+    }
+
+    /// Adds the pages that arrived since the last frame, returns whether more are coming
+    fn poll_listing(&mut self) -> bool {
+        let Some(listing) = &self.listing else {
+            return false;
+        };
+
+        let location = self.file_data.location().clone();
+        let mut received = false;
+        let mut done = false;
+
+        let receiver = listing.lock().expect("Listing receiver poisoned");
+        loop {
+            match receiver.try_recv() {
+                Ok(Ok(ListingMsg::Meta(meta))) => self.file_data.set_meta(meta),
+                Ok(Ok(ListingMsg::DotHidden(names))) => self.dot_hidden = names,
+                Ok(Ok(ListingMsg::Page(page))) => {
+                    let files = page
+                        .into_iter()
+                        .map(|entry| FileData::from_entry(location.vfs(), entry))
+                        .collect();
+                    let buttons = self.files_to_buttons(files);
+                    self.buttons.extend(buttons);
+                    self.generation += 1;
+                    received = true;
+                }
+                Ok(Err(err)) => {
+                    self.listing_error = Some(format!("Failed to list {location}: {err}"))
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    done = true;
+                    break;
+                }
+            }
+        }
+        drop(receiver);
+
+        if received {
+            let selected = self
+                .selected_file()
+                .map(|file_data| file_data.location().clone());
+
+            self.buttons
+                .sort_by(|b1, b2| sorting::cmp_files(&b1.file_data, &b2.file_data));
+            self.current_selected_button = selected.and_then(|selected| {
+                self.buttons
+                    .iter()
+                    .position(|button| button.file_data.location() == &selected)
+            });
+            self.start_pending_rename();
+        }
+
+        if done {
+            self.listing = None;
+        }
+
+        !done
     }
 }
 
-#[derive(Clone, Copy)]
-pub enum DirViewIconSize {
-    Small,
-    Medium,
-    Large,
+/// The part of [`DirViewState`] that survives restarts, everything else is re-read from disk
+#[derive(Serialize, Deserialize)]
+struct DirViewStateSnapshot {
+    history: Vec<Location>,
+    current_history_ind: usize,
+    icon_size: DirViewIconSize,
+    view_mode: DirViewMode,
+    #[serde(default)]
+    show_hidden: bool,
+    scroll_offset: Vec2,
 }
 
-impl DirViewIconSize {
-    fn as_f32(&self) -> f32 {
-        match self {
-            DirViewIconSize::Small => 50.0,
-            DirViewIconSize::Medium => 75.0,
-            DirViewIconSize::Large => 100.0,
-        }
+impl Serialize for DirViewState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.snapshot().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DirViewState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        DirViewStateSnapshot::deserialize(deserializer).map(Self::from_snapshot)
+    }
+}
+
+/// Height of a row in [`DirViewMode::List`]
+const LIST_ROW_HEIGHT: f32 = 20.0;
+
+/// Icon of the mode [`DirViewMode::switch`] would switch to
+fn switch_icon(mode: DirViewMode) -> PhosphorIcon {
+    match mode {
+        DirViewMode::Grid => PhosphorIcon::List,
+        DirViewMode::List => PhosphorIcon::SquaresFour,
     }
 }
 
@@ -374,13 +1487,19 @@ button_newtype!(DirViewButton {
     custom_fields: [
         icon_size: DirViewIconSize,
         button_size: Vec2,
+        view_mode: DirViewMode,
     ];
-    custom_state: DirViewButtonState {
+    custom_state: #[derive(Clone)] DirViewButtonState {
         file_data: FileData,
+        kind: FileKind,
+        style: Option<LsStyle>,
+        hidden: bool,
+        thumbnail: Option<String>,
     };
     modifiers: [
         editable{true},
         selectable{true},
+        draggable{true},
 
         layout{Layout::top_down(Align::Center)}
     ]
@@ -390,16 +1509,303 @@ impl<'a> Widget for DirViewButton<'a> {
     fn ui(mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
         let icon = self.state.file_data.icon();
         let icon_size = self.icon_size;
+        let (icon_color, text_color, text_background) = {
+            let theme = self.theme;
+            let style = self.state.style.unwrap_or_default();
+
+            let icon_color = style
+                .icon_color(theme)
+                .unwrap_or_else(|| theme.file_kind_color(self.state.kind));
+            let text_color = style
+                .text_color(theme)
+                .unwrap_or_else(|| theme.text_color());
+            let text_background = style.background(theme).unwrap_or(Color32::TRANSPARENT);
+
+            match self.state.hidden {
+                true => (
+                    theme.dim_hidden(icon_color),
+                    theme.dim_hidden(text_color),
+                    theme.dim_hidden(text_background),
+                ),
+                false => (icon_color, text_color, text_background),
+            }
+        };
+        // Archives are read-only
+        let editable = !self.state.file_data.is_archive_member();
+        // Keeps the extension when renaming
+        let select_stem = !self.state.file_data.is_dir();
 
         let button_size = self.button_size;
+        let thumbnail = self.state.thumbnail.clone();
+
+        match self.view_mode {
+            DirViewMode::Grid => self
+                .to_rsfm_but()
+                .with_editable(editable)
+                .with_select_stem(select_stem)
+                .with_icon_size(icon_size)
+                .with_icon(icon)
+                .with_icon_color(icon_color)
+                .with_thumbnail(thumbnail)
+                .with_text_color(text_color)
+                .with_text_background(text_background)
+                .with_desired_size(button_size)
+                .with_font_size(12.0)
+                .with_text_halign(Align::Center)
+                .with_text_selected_rows(3)
+                .ui(ui),
+            DirViewMode::List => self
+                .to_rsfm_but()
+                .with_editable(editable)
+                .with_select_stem(select_stem)
+                .with_layout(Layout::left_to_right(Align::Center))
+                .with_icon_size(Vec2::splat(LIST_ROW_HEIGHT - 4.0))
+                .with_icon(icon)
+                .with_icon_color(icon_color)
+                .with_text_color(text_color)
+                .with_text_background(text_background)
+                .with_spacing(Some(5.0))
+                .with_desired_size(button_size)
+                .with_padding(Margin::symmetric(5.0, 2.0))
+                .with_font_size(12.0)
+                .ui(ui),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        config::HiddenFiles,
+        utils::{
+            fs::TestDir,
+            jobs::JobProgress,
+            vfs::{self, MemoryVfs, Vfs},
+        },
+    };
+
+    use super::*;
+
+    /// Registers a memory filesystem with a couple of files under `id`
+    fn memory_vfs(id: &str) -> MemoryVfs {
+        let memory = MemoryVfs::default();
+        memory.create_dir(Path::new("/docs")).unwrap();
+        memory.create_dir(Path::new("/docs/old")).unwrap();
+        ["/b.txt", "/a.txt", "/.hidden_file", "/docs/report.txt"]
+            .into_iter()
+            .for_each(|path| {
+                memory
+                    .write(Path::new(path))
+                    .unwrap()
+                    .write_all(b"contents")
+                    .unwrap();
+            });
+
+        vfs::register(id, Arc::new(memory.clone()));
+        memory
+    }
+
+    /// A tab with the default view
+    fn tab(file_data: FileData) -> DirViewState {
+        DirViewState::with_view(file_data, &ViewDefaults::default())
+    }
+
+    /// Waits for the background listing to finish
+    fn listed(mut state: DirViewState) -> DirViewState {
+        let start = Instant::now();
+        while state.poll_listing() {
+            assert!(start.elapsed() < Duration::from_secs(5), "Listing hung");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        state
+    }
+
+    fn names(state: &DirViewState) -> Vec<String> {
+        state.files().map(|file| file.name().clone()).collect()
+    }
+
+    #[test]
+    fn lists_directories_first() {
+        memory_vfs("dir-view-test-list");
+        let state = listed(tab(FileData::at(Location::new("dir-view-test-list", "/"))));
+
+        assert_eq!(names(&state), ["docs", "a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn shows_hidden_files_when_asked() {
+        let memory = memory_vfs("dir-view-test-hidden");
+        memory
+            .write(Path::new("/.hidden"))
+            .unwrap()
+            .write_all(b"b.txt")
+            .unwrap();
+        hidden::set_rules(&HiddenFiles::default()).unwrap();
+
+        let mut state = listed(tab(FileData::at(Location::new(
+            "dir-view-test-hidden",
+            "/",
+        ))));
+        assert_eq!(names(&state), ["docs", "a.txt"]);
+
+        state.set_show_hidden(true);
+        let state = listed(state);
+
+        assert_eq!(
+            names(&state),
+            ["docs", ".hidden", ".hidden_file", "a.txt", "b.txt"]
+        );
+    }
+
+    #[test]
+    fn starts_with_the_view_defaults() {
+        memory_vfs("dir-view-test-defaults");
+        let view = ViewDefaults {
+            show_hidden: true,
+            ..ViewDefaults::default()
+        };
+        let state = listed(DirViewState::with_view(
+            FileData::at(Location::new("dir-view-test-defaults", "/")),
+            &view,
+        ));
+
+        assert_eq!(names(&state), ["docs", ".hidden_file", "a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn creates_entries_under_free_names() {
+        let memory = memory_vfs("dir-view-test-create");
+        let dir = Location::new("dir-view-test-create", "/");
+        memory
+            .write(Path::new("/New File"))
+            .unwrap()
+            .write_all(b"keep")
+            .unwrap();
+
+        let created = NewEntry::File.create(&dir).unwrap();
+        assert_eq!(created.path(), Path::new("/New File (2)"));
+        let created = NewEntry::Folder.create(&dir).unwrap();
+        assert_eq!(created.path(), Path::new("/New Folder"));
+        let created = NewEntry::Folder.create(&dir).unwrap();
+        assert_eq!(created.path(), Path::new("/New Folder (2)"));
+
+        let mut contents = String::new();
+        memory
+            .read(Path::new("/New File"))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "keep");
+    }
+
+    #[test]
+    fn navigates_through_history() {
+        memory_vfs("dir-view-test-history");
+        let root = Location::new("dir-view-test-history", "/");
+        let docs = Location::new("dir-view-test-history", "/docs");
+        let mut state = listed(tab(FileData::at(root.clone())));
+
+        state.navigate_to(docs.clone());
+        state = listed(state);
+        assert_eq!(state.file_data().location(), &docs);
+        assert_eq!(names(&state), ["old", "report.txt"]);
+
+        state.history_backward();
+        state = listed(state);
+        assert_eq!(state.file_data().location(), &root);
+
+        state.history_forward();
+        state = listed(state);
+        assert_eq!(state.file_data().location(), &docs);
+
+        // Going somewhere new drops the forward history
+        state.history_backward();
+        state.navigate_to(Location::new("dir-view-test-history", "/docs/old"));
+        state.history_backward();
+        state.history_forward();
+        assert_eq!(state.history.len(), 2);
+    }
+
+    #[test]
+    fn reload_picks_up_changes() {
+        let memory = memory_vfs("dir-view-test-reload");
+        let mut state = listed(tab(FileData::at(Location::new(
+            "dir-view-test-reload",
+            "/docs",
+        ))));
+        let generation = state.generation();
+
+        memory.remove(Path::new("/docs/report.txt")).unwrap();
+        state.reload();
+        let state = listed(state);
+
+        assert_eq!(names(&state), ["old"]);
+        assert_ne!(state.generation(), generation);
+    }
+
+    #[test]
+    fn notices_changes_through_the_watcher() {
+        let memory = memory_vfs("dir-view-test-watch");
+        let state = listed(tab(FileData::at(Location::new(
+            "dir-view-test-watch",
+            "/docs",
+        ))));
+        let ctx = Context::default();
+        assert!(!state.take_changes(&ctx));
+
+        memory.remove(Path::new("/docs/report.txt")).unwrap();
+        assert!(state.take_changes(&ctx));
+        assert!(!state.take_changes(&ctx));
+
+        memory.remove(Path::new("/a.txt")).unwrap();
+        assert!(!state.take_changes(&ctx));
+    }
+
+    #[test]
+    fn shows_listing_errors_and_fills_in_metadata() {
+        memory_vfs("dir-view-test-errors");
+        let state = listed(tab(FileData::at(Location::new(
+            "dir-view-test-errors",
+            "/docs",
+        ))));
+        assert!(state.listing_error.is_none());
+        assert!(state.file_data().is_dir());
+
+        let state = listed(tab(FileData::at(Location::new(
+            "dir-view-test-errors",
+            "/missing",
+        ))));
+        assert!(state.listing_error.is_some());
+        assert!(names(&state).is_empty());
+    }
+
+    #[test]
+    fn lists_local_archives_in_the_background() {
+        let dir = TestDir::new("dir-view-archive");
+        std::fs::create_dir(dir.0.join("docs")).unwrap();
+        std::fs::write(dir.0.join("docs/report.txt"), b"contents").unwrap();
+        let zip = dir.0.join("docs.zip");
+        archive::compress(
+            &[dir.0.join("docs")],
+            &zip,
+            &CompressOptions::default(),
+            &JobProgress::default(),
+        )
+        .unwrap();
+
+        let state = tab(FileData::new(zip.join("docs")));
+        assert!(state.listing.is_some());
+        assert!(!state.file_data().is_dir());
 
-        self.to_rsfm_but()
-            .with_icon_size(icon_size)
-            .with_icon(icon)
-            .with_desired_size(button_size)
-            .with_font_size(12.0)
-            .with_text_halign(Align::Center)
-            .with_text_selected_rows(3)
-            .ui(ui)
+        let state = listed(state);
+        assert!(state.listing_error.is_none());
+        assert!(state.file_data().is_dir());
+        assert_eq!(names(&state), ["report.txt"]);
     }
 }
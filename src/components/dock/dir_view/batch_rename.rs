@@ -0,0 +1,284 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    sync::{Arc, Mutex},
+};
+
+use chrono::NaiveDateTime;
+use eframe::egui::{Button, ComboBox, Context, DragValue, Grid, Id, RichText, Spinner, Window};
+use egui_extras::{Column, TableBuilder};
+
+use crate::{
+    components::{dock::DockAction, toasts},
+    utils::{
+        batch_rename::{self, Case, DateSource, RenameRules},
+        fs::FileData,
+        theme::{Theme, ThemeExt},
+        vfs::Location,
+    },
+};
+
+const TEMPLATE_HELP: &str = "{name}: the old name without its extension, after find/replace\n\
+    {ext}: the extension, with its dot\n\
+    {n}: the counter\n\
+    {date} or {date:%Y%m%d}: the date, see strftime for the format";
+
+/// The batch rename dialog for a directory view's selection
+#[derive(Clone)]
+pub struct BatchRename {
+    files: Vec<FileData>,
+    /// Everything in the directory, hidden files included
+    existing: HashSet<String>,
+    rules: RenameRules,
+    /// Filled in by a background thread, each EXIF date means reading the file
+    exif_dates: Arc<Mutex<HashMap<Location, Option<NaiveDateTime>>>>,
+    exif_requested: bool,
+}
+
+impl BatchRename {
+    pub fn new(dir: &Location, files: Vec<FileData>) -> io::Result<Self> {
+        let existing = dir
+            .backend()?
+            .list(dir.path())?
+            .into_iter()
+            .filter_map(|entry| {
+                entry
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .collect();
+
+        Ok(Self {
+            files,
+            existing,
+            rules: RenameRules::default(),
+            exif_dates: Arc::default(),
+            exif_requested: false,
+        })
+    }
+
+    /// The dates lined up with the files, `None` for EXIF ones still being read. Returns
+    /// whether any are
+    fn dates(&mut self, ctx: &Context) -> (Vec<Option<NaiveDateTime>>, bool) {
+        if !self.rules.uses_dates() {
+            return (vec![], false);
+        }
+
+        match self.rules.date_source {
+            DateSource::Modified => (
+                self.files
+                    .iter()
+                    .map(|file| DateSource::Modified.date(file))
+                    .collect(),
+                false,
+            ),
+            DateSource::Exif => {
+                if !self.exif_requested {
+                    self.exif_requested = true;
+                    self.read_exif_dates(ctx);
+                }
+
+                let exif_dates = self.exif_dates.lock().expect("EXIF dates poisoned");
+                let dates = self
+                    .files
+                    .iter()
+                    .map(|file| exif_dates.get(file.location()).copied().flatten())
+                    .collect();
+                (dates, exif_dates.len() < self.files.len())
+            }
+        }
+    }
+
+    fn read_exif_dates(&self, ctx: &Context) {
+        let (files, exif_dates, ctx) = (self.files.clone(), self.exif_dates.clone(), ctx.clone());
+
+        std::thread::spawn(move || {
+            files.iter().for_each(|file| {
+                let date = DateSource::Exif.date(file);
+                exif_dates
+                    .lock()
+                    .expect("EXIF dates poisoned")
+                    .insert(file.location().clone(), date);
+                ctx.request_repaint();
+            });
+        });
+    }
+
+    /// Returns whether the dialog is still open
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        id: Id,
+        theme: &Theme,
+        actions: &mut Vec<DockAction>,
+    ) -> bool {
+        let mut open = true;
+        let mut done = false;
+
+        Window::new(format!("Rename {} files", self.files.len()))
+            .id(id)
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+                self.rules_ui(ui);
+                ui.separator();
+
+                let (dates, reading_dates) = self.dates(ui.ctx());
+
+                let plan = match self.rules.plan(&self.files, &dates, &self.existing) {
+                    Ok(plan) => plan,
+                    Err(err) => {
+                        ui.colored_label(theme.error_color(), err);
+                        return;
+                    }
+                };
+
+                TableBuilder::new(ui)
+                    .striped(true)
+                    .max_scroll_height(300.0)
+                    .column(Column::initial(220.0).resizable(true).clip(true))
+                    .column(Column::remainder().clip(true))
+                    .header(20.0, |mut header| {
+                        header.col(|ui| {
+                            ui.strong("Old name");
+                        });
+                        header.col(|ui| {
+                            ui.strong("New name");
+                        });
+                    })
+                    .body(|body| {
+                        body.rows(18.0, plan.len(), |i, mut row| {
+                            let rename = &plan[i];
+                            row.col(|ui| {
+                                ui.label(&rename.old_name);
+                            });
+                            row.col(|ui| {
+                                let text = RichText::new(&rename.new_name);
+                                match (&rename.problem, rename.changed()) {
+                                    (Some(problem), _) => {
+                                        ui.label(text.color(theme.error_color()))
+                                            .on_hover_text(problem);
+                                    }
+                                    (None, true) => {
+                                        ui.label(text);
+                                    }
+                                    (None, false) => {
+                                        ui.label(text.color(theme.muted_text_color()));
+                                    }
+                                }
+                            });
+                        });
+                    });
+
+                ui.separator();
+
+                let changed = plan.iter().filter(|rename| rename.changed()).count();
+                let problems = plan
+                    .iter()
+                    .filter(|rename| rename.problem.is_some())
+                    .count();
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            changed > 0 && problems == 0 && !reading_dates,
+                            Button::new(format!("Rename {changed} files")),
+                        )
+                        .clicked()
+                    {
+                        match batch_rename::apply(&plan) {
+                            Ok(operation) => {
+                                toasts::info(ui.ctx(), format!("Renamed {changed} files"));
+                                actions.push(DockAction::Record(operation));
+                                done = true;
+                            }
+                            Err(err) => toasts::error(ui.ctx(), format!("Failed to rename: {err}")),
+                        }
+                    }
+
+                    if reading_dates {
+                        ui.add(Spinner::new());
+                        ui.label("Reading dates");
+                    }
+
+                    if ui.button("Cancel").clicked() {
+                        done = true;
+                    }
+
+                    if problems > 0 {
+                        ui.colored_label(
+                            theme.error_color(),
+                            format!("{problems} names collide or aren't valid"),
+                        );
+                    }
+                });
+            });
+
+        open && !done
+    }
+
+    fn rules_ui(&mut self, ui: &mut eframe::egui::Ui) {
+        let rules = &mut self.rules;
+
+        Grid::new(ui.id().with("rules"))
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Find");
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut rules.find);
+                    ui.checkbox(&mut rules.regex, "Regex");
+                });
+                ui.end_row();
+
+                ui.label("Replace with");
+                ui.text_edit_singleline(&mut rules.replace)
+                    .on_hover_text("$1 or ${name} insert the regex's capture groups");
+                ui.end_row();
+
+                ui.label("New name");
+                ui.text_edit_singleline(&mut rules.template)
+                    .on_hover_text(TEMPLATE_HELP);
+                ui.end_row();
+
+                ui.label("Counter");
+                ui.horizontal(|ui| {
+                    ui.add(DragValue::new(&mut rules.counter_start).prefix("Start "));
+                    ui.add(
+                        DragValue::new(&mut rules.counter_step)
+                            .prefix("Step ")
+                            .clamp_range(1..=u32::MAX),
+                    );
+                    ui.add(
+                        DragValue::new(&mut rules.counter_padding)
+                            .prefix("Digits ")
+                            .clamp_range(0..=10),
+                    );
+                });
+                ui.end_row();
+
+                ui.label("Case");
+                ComboBox::from_id_source(ui.id().with("case"))
+                    .selected_text(rules.case.label())
+                    .show_ui(ui, |ui| {
+                        Case::ALL.into_iter().for_each(|case| {
+                            ui.selectable_value(&mut rules.case, case, case.label());
+                        });
+                    });
+                ui.end_row();
+
+                ui.label("Dates from");
+                ComboBox::from_id_source(ui.id().with("date_source"))
+                    .selected_text(rules.date_source.label())
+                    .show_ui(ui, |ui| {
+                        DateSource::ALL.into_iter().for_each(|source| {
+                            ui.selectable_value(&mut rules.date_source, source, source.label());
+                        });
+                    })
+                    .response
+                    .on_hover_text("Files without EXIF use when they were modified");
+                ui.end_row();
+            });
+    }
+}
@@ -0,0 +1,87 @@
+use eframe::egui::{Button, RichText, ScrollArea, Ui, Widget};
+
+use crate::utils::{
+    journal::{Entry, Journal},
+    theme::{Theme, ThemeExt},
+};
+
+use super::DockAction;
+
+/// The journal as a timeline, newest on top. Clicking an entry undoes or redoes up to it
+pub struct History<'a> {
+    journal: &'a Journal,
+    theme: &'a Theme,
+    actions: &'a mut Vec<DockAction>,
+}
+
+impl<'a> History<'a> {
+    pub fn new(journal: &'a Journal, theme: &'a Theme, actions: &'a mut Vec<DockAction>) -> Self {
+        Self {
+            journal,
+            theme,
+            actions,
+        }
+    }
+}
+
+impl<'a> Widget for History<'a> {
+    fn ui(self, ui: &mut Ui) -> eframe::egui::Response {
+        let (done, undone) = (self.journal.done(), self.journal.undone());
+
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(self.journal.can_undo(), Button::new("Undo"))
+                    .clicked()
+                {
+                    self.actions.push(DockAction::Undo(1));
+                }
+                if ui
+                    .add_enabled(self.journal.can_redo(), Button::new("Redo"))
+                    .clicked()
+                {
+                    self.actions.push(DockAction::Redo(1));
+                }
+            });
+            ui.separator();
+
+            if done.is_empty() && undone.is_empty() {
+                ui.label(RichText::new("Nothing to undo yet").color(self.theme.muted_text_color()));
+                return;
+            }
+
+            ScrollArea::vertical().show(ui, |ui| {
+                // The next one to redo is the last undone, right above what's done
+                undone.iter().enumerate().for_each(|(i, entry)| {
+                    let text = entry_text(entry).color(self.theme.muted_text_color());
+                    if ui
+                        .selectable_label(false, text)
+                        .on_hover_text("Redo up to here")
+                        .clicked()
+                    {
+                        self.actions.push(DockAction::Redo(undone.len() - i));
+                    }
+                });
+
+                done.iter().enumerate().rev().for_each(|(i, entry)| {
+                    if ui
+                        .selectable_label(i + 1 == done.len(), entry_text(entry))
+                        .on_hover_text("Undo back to before this")
+                        .clicked()
+                    {
+                        self.actions.push(DockAction::Undo(done.len() - i));
+                    }
+                });
+            });
+        })
+        .response
+    }
+}
+
+fn entry_text(entry: &Entry) -> RichText {
+    RichText::new(format!(
+        "{}  {}",
+        entry.at.format("%H:%M:%S"),
+        entry.operation.describe()
+    ))
+}
@@ -0,0 +1,561 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use eframe::egui::{
+    Button, ComboBox, Context, DragValue, Grid, Id, Response, RichText, ScrollArea, TextEdit, Ui,
+    Widget,
+};
+
+use crate::{
+    config::{self, Accent, ConfigFile, DirViewIconSize, DirViewMode, SortBy},
+    utils::{
+        icons::PhosphorIcon,
+        keymap::Shortcut,
+        theme::{Theme, ThemeExt},
+    },
+};
+
+use super::DockAction;
+
+/// Every option of `config.toml`, edited on a draft that's only written out on save
+pub struct Settings<'a> {
+    config: &'a ConfigFile,
+    custom_themes: &'a [String],
+    theme: &'a Theme,
+    actions: &'a mut Vec<DockAction>,
+}
+
+impl<'a> Settings<'a> {
+    pub fn new(
+        config: &'a ConfigFile,
+        custom_themes: &'a [String],
+        theme: &'a Theme,
+        actions: &'a mut Vec<DockAction>,
+    ) -> Self {
+        Self {
+            config,
+            custom_themes,
+            theme,
+            actions,
+        }
+    }
+
+    fn form_id() -> Id {
+        Id::new("settings_form")
+    }
+
+    /// Forgets the draft, e.g. once the tab is closed
+    pub fn discard(ctx: &Context) {
+        ctx.data_mut(|d| d.remove::<Arc<Mutex<SettingsForm>>>(Self::form_id()));
+    }
+}
+
+/// Kept in temp memory while the tab is open, shared so it's edited in place
+struct SettingsForm {
+    /// The config the draft started from
+    base: ConfigFile,
+    draft: ConfigFile,
+    /// Path overrides as typed, empty ones fall back to the XDG dirs
+    paths: [String; 8],
+    /// The `dircolors` file as typed, empty uses `LS_COLORS`
+    dircolors: String,
+    /// Hide patterns as typed, one per line
+    hide_patterns: String,
+    /// Keymap shortcuts as typed
+    shortcuts: [String; 4],
+}
+
+impl SettingsForm {
+    fn new(config: &ConfigFile) -> Self {
+        let mut draft = config.clone();
+        let paths = draft.overrides.paths_mut().entries_mut().map(|(_, path)| {
+            path.as_ref()
+                .map(|path| path.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+
+        let dircolors = draft
+            .colors
+            .dircolors
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let hide_patterns = draft.hidden.patterns.join("\n");
+
+        let shortcuts = draft
+            .keymap
+            .entries_mut()
+            .map(|(_, shortcut)| shortcut.to_string());
+
+        Self {
+            base: config.clone(),
+            draft,
+            paths,
+            dircolors,
+            hide_patterns,
+            shortcuts,
+        }
+    }
+
+    /// `Err` explains what's wrong with the typed path
+    fn parse_path(text: &str) -> Result<Option<PathBuf>, &'static str> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(None);
+        }
+
+        let path = PathBuf::from(text);
+        match (path.is_absolute(), path.is_dir()) {
+            (false, _) => Err("Must be an absolute path"),
+            (true, false) => Err("Not a directory"),
+            (true, true) => Ok(Some(path)),
+        }
+    }
+
+    /// `Err` names the first pattern that isn't a valid glob
+    fn parse_hide_patterns(&self) -> Result<Vec<String>, String> {
+        self.hide_patterns
+            .lines()
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| match glob::Pattern::new(pattern) {
+                Ok(_) => Ok(pattern.to_string()),
+                Err(err) => Err(format!("{pattern:?}: {err}")),
+            })
+            .collect()
+    }
+
+    /// `Err` explains what's wrong with the `i`th typed shortcut
+    fn parse_shortcut(&self, i: usize) -> Result<Shortcut, String> {
+        let shortcut = self.shortcuts[i].parse::<Shortcut>()?;
+        let taken = self.shortcuts[..i]
+            .iter()
+            .any(|other| other.parse() == Ok(shortcut));
+
+        match taken {
+            true => Err("Already used above".into()),
+            false => Ok(shortcut),
+        }
+    }
+
+    /// Copies the typed fields that parse into the draft, `false` while any of them is invalid
+    fn sync_typed(&mut self) -> bool {
+        let mut valid = true;
+        for ((_, path), text) in self
+            .draft
+            .overrides
+            .paths_mut()
+            .entries_mut()
+            .into_iter()
+            .zip(&self.paths)
+        {
+            match Self::parse_path(text) {
+                Ok(parsed) => *path = parsed,
+                Err(_) => valid = false,
+            }
+        }
+
+        let dircolors = self.dircolors.trim();
+        self.draft.colors.dircolors = (!dircolors.is_empty()).then(|| PathBuf::from(dircolors));
+
+        match self.parse_hide_patterns() {
+            Ok(patterns) => self.draft.hidden.patterns = patterns,
+            Err(_) => valid = false,
+        }
+
+        let shortcuts: [_; 4] = std::array::from_fn(|i| self.parse_shortcut(i));
+        for ((_, shortcut), parsed) in self.draft.keymap.entries_mut().into_iter().zip(shortcuts) {
+            match parsed {
+                Ok(parsed) => *shortcut = parsed,
+                Err(_) => valid = false,
+            }
+        }
+
+        valid
+            && self
+                .draft
+                .bookmarks
+                .iter()
+                .all(|bookmark| !bookmark.label.trim().is_empty())
+    }
+}
+
+impl<'a> Settings<'a> {
+    fn appearance_ui(&mut self, ui: &mut Ui, draft: &mut ConfigFile) {
+        ui.heading("Appearance");
+
+        Grid::new("settings_appearance")
+            .num_columns(2)
+            .spacing([20.0, 5.0])
+            .show(ui, |ui| {
+                ui.label("Theme");
+                ComboBox::from_id_source("settings_theme")
+                    .selected_text(draft.theme.label())
+                    .show_ui(ui, |ui| {
+                        config::Theme::all(self.custom_themes).for_each(|theme| {
+                            let label = theme.label().to_string();
+                            ui.selectable_value(&mut draft.theme, theme, label);
+                        });
+                    });
+                ui.end_row();
+
+                let auto_theme = &mut draft.auto_theme;
+                [
+                    ("Auto theme when dark", &mut auto_theme.dark),
+                    ("Auto theme when light", &mut auto_theme.light),
+                ]
+                .into_iter()
+                .for_each(|(label, picked)| {
+                    ui.label(label);
+                    ComboBox::from_id_source(label)
+                        .selected_text(picked.label())
+                        .show_ui(ui, |ui| {
+                            config::Theme::all(self.custom_themes)
+                                .filter(|theme| *theme != config::Theme::Auto)
+                                .for_each(|theme| {
+                                    let label = theme.label().to_string();
+                                    ui.selectable_value(picked, theme, label);
+                                });
+                        });
+                    ui.end_row();
+                });
+
+                ui.label("Accent");
+                ComboBox::from_id_source("settings_accent")
+                    .selected_text(
+                        RichText::new(draft.accent.label()).color(draft.accent.color(self.theme)),
+                    )
+                    .show_ui(ui, |ui| {
+                        Accent::ALL.iter().for_each(|accent| {
+                            ui.selectable_value(
+                                &mut draft.accent,
+                                *accent,
+                                RichText::new(accent.label()).color(accent.color(self.theme)),
+                            );
+                        });
+                    });
+                ui.end_row();
+
+                ui.label("View of new tabs");
+                ComboBox::from_id_source("settings_view_mode")
+                    .selected_text(draft.view.mode.label())
+                    .show_ui(ui, |ui| {
+                        DirViewMode::ALL.iter().for_each(|mode| {
+                            ui.selectable_value(&mut draft.view.mode, *mode, mode.label());
+                        });
+                    });
+                ui.end_row();
+
+                ui.label("Hidden files in new tabs");
+                ui.checkbox(&mut draft.view.show_hidden, "Show");
+                ui.end_row();
+
+                ui.label("Icon size of new tabs");
+                ComboBox::from_id_source("settings_icon_size")
+                    .selected_text(draft.view.icon_size.label())
+                    .show_ui(ui, |ui| {
+                        DirViewIconSize::ALL.iter().for_each(|size| {
+                            ui.selectable_value(&mut draft.view.icon_size, *size, size.label());
+                        });
+                    });
+                ui.end_row();
+            });
+    }
+
+    fn sorting_ui(&mut self, ui: &mut Ui, draft: &mut ConfigFile) {
+        ui.heading("Sorting");
+
+        let sorting = &mut draft.sorting;
+        ui.horizontal(|ui| {
+            ui.label("Sort by");
+            ComboBox::from_id_source("settings_sort_by")
+                .selected_text(sorting.by.label())
+                .show_ui(ui, |ui| {
+                    SortBy::ALL.iter().for_each(|by| {
+                        ui.selectable_value(&mut sorting.by, *by, by.label());
+                    });
+                });
+            ui.checkbox(&mut sorting.descending, "Descending");
+        });
+        ui.checkbox(&mut sorting.directories_first, "Directories first");
+    }
+
+    fn keymap_ui(&mut self, ui: &mut Ui, form: &mut SettingsForm) {
+        ui.heading("Keyboard shortcuts");
+
+        Grid::new("settings_keymap")
+            .num_columns(3)
+            .spacing([20.0, 5.0])
+            .show(ui, |ui| {
+                let names = form.draft.keymap.entries_mut().map(|(name, _)| name);
+
+                (0..names.len()).for_each(|i| {
+                    ui.label(names[i]);
+                    ui.add(TextEdit::singleline(&mut form.shortcuts[i]).hint_text("Ctrl+Shift+Z"));
+                    match form.parse_shortcut(i) {
+                        Ok(_) => ui.label(""),
+                        Err(err) => ui.label(RichText::new(err).color(self.theme.error_color())),
+                    };
+                    ui.end_row();
+                });
+            });
+    }
+
+    fn thumbnails_ui(&mut self, ui: &mut Ui, draft: &mut ConfigFile) {
+        ui.heading("Thumbnails");
+
+        let thumbnails = &mut draft.thumbnails;
+        ui.checkbox(&mut thumbnails.enabled, "Show images by their contents");
+        ui.add_enabled_ui(thumbnails.enabled, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Up to");
+                ui.add(
+                    DragValue::new(&mut thumbnails.max_file_size)
+                        .clamp_range(1..=1024)
+                        .suffix(" MiB"),
+                );
+            });
+            ui.checkbox(
+                &mut thumbnails.remote,
+                "Also on network connections, which downloads every image",
+            );
+        });
+    }
+
+    fn colors_ui(&mut self, ui: &mut Ui, form: &mut SettingsForm) {
+        ui.heading("File colors");
+
+        Grid::new("settings_colors")
+            .num_columns(2)
+            .spacing([20.0, 5.0])
+            .show(ui, |ui| {
+                ui.label("LS_COLORS");
+                ui.checkbox(&mut form.draft.colors.ls_colors, "Color files like ls does");
+                ui.end_row();
+
+                ui.label("dircolors file");
+                ui.add(
+                    TextEdit::singleline(&mut form.dircolors)
+                        .hint_text("Relative to the config dir, used instead of LS_COLORS"),
+                );
+                ui.end_row();
+            });
+    }
+
+    fn hidden_ui(&mut self, ui: &mut Ui, form: &mut SettingsForm) {
+        ui.heading("Hidden files");
+
+        ui.checkbox(
+            &mut form.draft.hidden.dot_hidden,
+            "Hide the files listed in a directory's .hidden file",
+        );
+        ui.label("Also hide names matching these globs, one per line:");
+        ui.add(
+            TextEdit::multiline(&mut form.hide_patterns)
+                .hint_text("*.pyc\n__pycache__")
+                .desired_rows(3),
+        );
+        if let Err(err) = form.parse_hide_patterns() {
+            ui.label(RichText::new(err).color(self.theme.error_color()));
+        }
+    }
+
+    fn paths_ui(&mut self, ui: &mut Ui, form: &mut SettingsForm) {
+        ui.heading("Paths");
+
+        Grid::new("settings_paths")
+            .num_columns(3)
+            .spacing([20.0, 5.0])
+            .show(ui, |ui| {
+                let names = form
+                    .draft
+                    .overrides
+                    .paths_mut()
+                    .entries_mut()
+                    .map(|(name, _)| name);
+
+                names.iter().zip(&mut form.paths).for_each(|(name, text)| {
+                    ui.label(*name);
+                    ui.add(TextEdit::singleline(text).hint_text("XDG default"));
+                    match SettingsForm::parse_path(text) {
+                        Ok(_) => ui.label(""),
+                        Err(err) => ui.label(RichText::new(err).color(self.theme.error_color())),
+                    };
+                    ui.end_row();
+                });
+            });
+    }
+
+    fn bookmarks_ui(&mut self, ui: &mut Ui, draft: &mut ConfigFile) {
+        ui.heading("Bookmarks");
+
+        if draft.bookmarks.is_empty() {
+            ui.label("Bookmark directories from their context menu");
+            return;
+        }
+
+        let mut remove = None;
+        Grid::new("settings_bookmarks")
+            .num_columns(3)
+            .spacing([20.0, 5.0])
+            .show(ui, |ui| {
+                draft
+                    .bookmarks
+                    .iter_mut()
+                    .enumerate()
+                    .for_each(|(i, bookmark)| {
+                        let label = ui.text_edit_singleline(&mut bookmark.label);
+                        if bookmark.label.trim().is_empty() {
+                            label.on_hover_text("Can't be empty");
+                        }
+                        ui.label(bookmark.path.to_string_lossy());
+                        if ui.button(PhosphorIcon::X.rich_text()).clicked() {
+                            remove = Some(i);
+                        }
+                        ui.end_row();
+                    });
+            });
+
+        if let Some(i) = remove {
+            draft.bookmarks.remove(i);
+        }
+    }
+
+    fn connections_ui(&mut self, ui: &mut Ui, draft: &mut ConfigFile) {
+        ui.heading("Connections");
+
+        if draft.connections.is_empty() {
+            ui.label("Add connections from the sidebar's Network section");
+            return;
+        }
+
+        let mut remove = None;
+        Grid::new("settings_connections")
+            .num_columns(4)
+            .spacing([20.0, 5.0])
+            .show(ui, |ui| {
+                draft
+                    .connections
+                    .iter()
+                    .enumerate()
+                    .for_each(|(i, profile)| {
+                        ui.label(&profile.name);
+                        ui.label(profile.kind.label());
+                        ui.label(&profile.host);
+                        if ui.button(PhosphorIcon::X.rich_text()).clicked() {
+                            remove = Some(i);
+                        }
+                        ui.end_row();
+                    });
+            });
+
+        if let Some(i) = remove {
+            draft.connections.remove(i);
+        }
+    }
+
+    fn backups_ui(&mut self, ui: &mut Ui, draft: &mut ConfigFile) {
+        ui.heading("Backups");
+
+        ui.horizontal(|ui| {
+            ui.label("Keep");
+            ui.add(DragValue::new(&mut draft.backups.count).clamp_range(0..=100));
+            ui.label("previous versions of the config file");
+        });
+    }
+}
+
+impl<'a> Widget for Settings<'a> {
+    fn ui(mut self, ui: &mut Ui) -> Response {
+        let form = ui.data_mut(|d| {
+            d.get_temp_mut_or_insert_with(Self::form_id(), || {
+                Arc::new(Mutex::new(SettingsForm::new(self.config)))
+            })
+            .clone()
+        });
+        let mut form = form.lock().expect("Settings form poisoned");
+
+        // Edited elsewhere, e.g. by hand or from the sidebar
+        if form.base != *self.config {
+            match form.sync_typed() && form.draft == form.base {
+                true => *form = SettingsForm::new(self.config),
+                false => form.base = self.config.clone(),
+            }
+        }
+
+        let previewed = (form.draft.theme.clone(), form.draft.accent);
+
+        let response = ScrollArea::vertical()
+            .show(ui, |ui| {
+                self.appearance_ui(ui, &mut form.draft);
+                ui.separator();
+
+                self.sorting_ui(ui, &mut form.draft);
+                ui.separator();
+
+                self.thumbnails_ui(ui, &mut form.draft);
+                ui.separator();
+
+                self.keymap_ui(ui, &mut form);
+                ui.separator();
+
+                self.colors_ui(ui, &mut form);
+                ui.separator();
+
+                self.hidden_ui(ui, &mut form);
+                ui.separator();
+
+                self.paths_ui(ui, &mut form);
+                ui.separator();
+
+                self.bookmarks_ui(ui, &mut form.draft);
+                ui.separator();
+                self.connections_ui(ui, &mut form.draft);
+                ui.separator();
+                self.backups_ui(ui, &mut form.draft);
+                ui.separator();
+
+                let valid = form.sync_typed();
+                let modified = form.draft != *self.config;
+
+                ui.horizontal(|ui| {
+                    let save = ui.add_enabled(
+                        valid && modified,
+                        Button::new(format!("{} Save", PhosphorIcon::FloppyDisk.symbol())),
+                    );
+                    if save.clicked() {
+                        form.base = form.draft.clone();
+                        self.actions
+                            .push(DockAction::SaveSettings(Box::new(form.draft.clone())));
+                    }
+
+                    if ui.add_enabled(modified, Button::new("Revert")).clicked() {
+                        *form = SettingsForm::new(self.config);
+                        self.actions.push(DockAction::PreviewTheme(None));
+                    }
+
+                    if !valid {
+                        ui.label(
+                            RichText::new("Fix the marked fields to save")
+                                .color(self.theme.error_color()),
+                        );
+                    }
+                })
+                .response
+            })
+            .inner;
+
+        if (&form.draft.theme, form.draft.accent) != (&previewed.0, previewed.1) {
+            let picked = (form.draft.theme.clone(), form.draft.accent);
+            let saved = (self.config.theme.clone(), self.config.accent);
+            self.actions.push(DockAction::PreviewTheme(
+                (picked != saved).then_some(picked),
+            ));
+        }
+
+        response
+    }
+}
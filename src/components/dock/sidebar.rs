@@ -1,56 +1,253 @@
-use std::path::PathBuf;
+use std::{collections::HashSet, path::PathBuf};
 
-use catppuccin_egui::Theme;
-use eframe::egui::{RichText, Widget};
+use eframe::egui::{vec2, Align, Layout, RichText, Widget};
+use serde::{Deserialize, Serialize};
 
-use crate::{button_newtype, utils::icons::PhosphorIcon};
+use crate::{
+    button_newtype,
+    components::dnd,
+    config::{Bookmark, ConnectionProfile},
+    utils::{
+        bookmarks::gtk_bookmarks,
+        devices::BlockDevice,
+        icons::PhosphorIcon,
+        mounts::Mount,
+        paths::{Paths, UserDir},
+        theme::Theme,
+    },
+};
+
+use super::DockAction;
+
+mod bookmarks;
+mod devices;
+mod network;
+
+use bookmarks::BookmarkButtonState;
+use devices::DeviceButtonState;
+use network::NetworkButtonState;
 
 pub struct Sidebar<'a> {
     state: &'a mut SidebarState,
     theme: &'a Theme,
+    actions: &'a mut Vec<DockAction>,
 }
 
 impl<'a> Sidebar<'a> {
-    pub fn new(state: &'a mut SidebarState, theme: &'a Theme) -> Self {
-        Self { state, theme }
+    pub fn new(
+        state: &'a mut SidebarState,
+        theme: &'a Theme,
+        actions: &'a mut Vec<DockAction>,
+    ) -> Self {
+        Self {
+            state,
+            theme,
+            actions,
+        }
     }
 }
 
 impl<'a> Widget for Sidebar<'a> {
-    fn ui(self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+    fn ui(mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
         ui.vertical(|ui| {
             ui.collapsing(
                 RichText::new(format!("{} My Computer", PhosphorIcon::Desktop.symbol())),
                 |ui| {
                     self.state.my_comp_buttons.iter_mut().for_each(|comp_but| {
-                        ui.add(SidebarButton::new(comp_but, self.theme));
+                        let response = ui.add(SidebarButton::new(comp_but, self.theme));
+                        dnd::drop_target(ui, response.rect, self.theme, &comp_but.path);
+
+                        if response.clicked() {
+                            self.actions
+                                .push(DockAction::Navigate(comp_but.path.clone().into()));
+                        }
                     })
                 },
-            )
+            );
+
+            let bookmarks = ui.collapsing(
+                RichText::new(format!(
+                    "{} Bookmarks",
+                    PhosphorIcon::BookmarkSimple.symbol()
+                )),
+                |ui| {
+                    self.bookmarks_ui(ui);
+
+                    ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+                        if ui
+                            .button(PhosphorIcon::Plus.rich_text())
+                            .on_hover_text("Bookmark the current directory")
+                            .clicked()
+                        {
+                            self.actions.push(DockAction::BookmarkActiveDir);
+                        }
+
+                        if ui.button("Import GTK").clicked() {
+                            let mut bookmarks = self.state.bookmarks();
+                            gtk_bookmarks().into_iter().for_each(|bookmark| {
+                                if !bookmarks.iter().any(|b| b.path == bookmark.path) {
+                                    bookmarks.push(bookmark);
+                                }
+                            });
+
+                            self.actions.push(DockAction::SetBookmarks(bookmarks));
+                        }
+                    });
+                },
+            );
+
+            let bookmarks_rect = bookmarks
+                .body_response
+                .map(|body| body.rect.union(bookmarks.header_response.rect))
+                .unwrap_or(bookmarks.header_response.rect);
+
+            if let Some((paths, _action)) = dnd::take_dropped_paths(ui, bookmarks_rect, self.theme)
+            {
+                let mut bookmarks = self.state.bookmarks();
+                paths
+                    .into_iter()
+                    .filter(|path| path.is_dir())
+                    .for_each(|path| {
+                        if !bookmarks.iter().any(|bookmark| bookmark.path == path) {
+                            bookmarks.push(Bookmark::new(path));
+                        }
+                    });
+
+                self.actions.push(DockAction::SetBookmarks(bookmarks));
+            }
+
+            ui.collapsing(
+                RichText::new(format!("{} Devices", PhosphorIcon::HardDrives.symbol())),
+                |ui| self.devices_ui(ui),
+            );
+
+            ui.collapsing(
+                RichText::new(format!("{} Network", PhosphorIcon::Globe.symbol())),
+                |ui| self.network_ui(ui),
+            );
         })
         .response
     }
 }
 
-#[derive(Default)]
+/// Every section is rebuilt from [`Paths`], the config and the mount table on startup,
+/// only the view settings are persisted
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct SidebarState {
+    #[serde(skip)]
     my_comp_buttons: Vec<SidebarButtonState>,
+
+    #[serde(skip)]
+    bookmark_buttons: Vec<BookmarkButtonState>,
+    #[serde(skip)]
+    dragged_bookmark: Option<usize>,
+
+    #[serde(skip)]
+    device_buttons: Vec<DeviceButtonState>,
+    #[serde(skip)]
+    unmounted_devices: Vec<BlockDevice>,
+    show_pseudo_mounts: bool,
+
+    #[serde(skip)]
+    connection_buttons: Vec<NetworkButtonState>,
 }
 
 impl SidebarState {
-    pub fn with_my_comp_buttons(mut self, my_comp_buttons: Vec<SidebarButtonState>) -> Self {
-        self.my_comp_buttons = my_comp_buttons;
-        self
+    pub fn set_user_dirs(&mut self, paths: &Paths) {
+        self.my_comp_buttons = paths
+            .user_dirs()
+            .into_iter()
+            .map(|(user_dir, path)| {
+                SidebarButtonState::new(
+                    RSFMButtonState::default().with_text(user_dir.label()),
+                    path,
+                    Some(user_dir),
+                )
+            })
+            .collect();
+    }
+
+    pub fn set_bookmarks(&mut self, bookmarks: &[Bookmark]) {
+        self.bookmark_buttons = bookmarks
+            .iter()
+            .map(|bookmark| {
+                BookmarkButtonState::new(
+                    RSFMButtonState::default().with_text(&bookmark.label),
+                    bookmark.clone(),
+                )
+            })
+            .collect();
+        self.dragged_bookmark = None;
+    }
+
+    pub fn set_mounts(&mut self, mounts: &[Mount]) {
+        self.device_buttons = mounts
+            .iter()
+            .map(|mount| {
+                DeviceButtonState::new(
+                    RSFMButtonState::default().with_text(&mount.label),
+                    mount.clone(),
+                )
+            })
+            .collect();
+    }
+
+    pub fn set_unmounted_devices(&mut self, devices: &[BlockDevice]) {
+        self.unmounted_devices = devices.to_vec();
     }
 
-    pub fn append_comp_button(&mut self, comp_button: SidebarButtonState) {
-        self.my_comp_buttons.push(comp_button);
+    pub fn set_connections(
+        &mut self,
+        connections: &[ConnectionProfile],
+        connected: &HashSet<String>,
+    ) {
+        self.connection_buttons = connections
+            .iter()
+            .map(|profile| {
+                NetworkButtonState::new(
+                    RSFMButtonState::default().with_text(&profile.name),
+                    profile.clone(),
+                    connected.contains(&profile.name),
+                )
+            })
+            .collect();
+    }
+
+    fn bookmarks(&self) -> Vec<Bookmark> {
+        self.bookmark_buttons
+            .iter()
+            .map(|button| button.bookmark().clone())
+            .collect()
+    }
+
+    fn connections(&self) -> Vec<ConnectionProfile> {
+        self.connection_buttons
+            .iter()
+            .map(|button| button.profile().clone())
+            .collect()
     }
 }
 
 button_newtype!(SidebarButton {
-    wimpl: default;
-    custom_state: SidebarButtonState {
+    wimpl: custom;
+    custom_state: #[derive(Clone, Serialize, Deserialize)] SidebarButtonState {
         path: PathBuf,
+        user_dir: Option<UserDir>,
     };
 });
+
+impl<'a> Widget for SidebarButton<'a> {
+    fn ui(mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let icon = self
+            .state
+            .user_dir
+            .map(|user_dir| user_dir.icon())
+            .unwrap_or(PhosphorIcon::Folder);
+
+        self.to_rsfm_but()
+            .with_icon(icon)
+            .with_icon_size(vec2(16.0, 16.0))
+            .with_spacing(Some(5.0))
+            .ui(ui)
+    }
+}
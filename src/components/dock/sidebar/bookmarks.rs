@@ -0,0 +1,110 @@
+use eframe::egui::{vec2, Sense, Ui};
+
+use crate::{
+    button_newtype,
+    components::dnd,
+    config::Bookmark,
+    utils::{icons::PhosphorIcon, theme::ThemeExt},
+};
+
+use super::{DockAction, Sidebar};
+
+impl<'a> Sidebar<'a> {
+    pub(super) fn bookmarks_ui(&mut self, ui: &mut Ui) {
+        let mut changed = false;
+        let mut remove = None;
+        let mut hovered_row = None;
+
+        self.state
+            .bookmark_buttons
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, bookmark)| {
+                let row_response = ui
+                    .horizontal(|ui| {
+                        let handle = ui.add(
+                            PhosphorIcon::DotsSixVertical
+                                .image_widget(vec2(12.0, 12.0))
+                                .tint(self.theme.muted_text_color())
+                                .sense(Sense::drag()),
+                        );
+                        if handle.drag_started() {
+                            self.state.dragged_bookmark = Some(i);
+                        }
+
+                        let response = ui.add(BookmarkButton::new(bookmark, self.theme));
+                        dnd::drop_target(ui, response.rect, self.theme, &bookmark.bookmark.path);
+
+                        if response.clicked() {
+                            self.actions
+                                .push(DockAction::Navigate(bookmark.bookmark.path.clone().into()));
+                        }
+
+                        response.context_menu(|ui| {
+                            if ui.button("Rename").clicked() {
+                                bookmark.start_editing_text();
+                                ui.close_menu();
+                            }
+
+                            if ui.button("Remove").clicked() {
+                                remove = Some(i);
+                                ui.close_menu();
+                            }
+                        });
+
+                        if !bookmark.editing_text() {
+                            if let Some(text) = bookmark.text().clone() {
+                                if text != bookmark.bookmark.label {
+                                    bookmark.bookmark.label = text;
+                                    changed = true;
+                                }
+                            }
+                        }
+                    })
+                    .response;
+
+                if ui.rect_contains_pointer(row_response.rect) {
+                    hovered_row = Some(i);
+                }
+            });
+
+        if let Some(dragged) = self.state.dragged_bookmark {
+            if let Some(hovered) = hovered_row.filter(|hovered| *hovered != dragged) {
+                let bookmark = self.state.bookmark_buttons.remove(dragged);
+                self.state.bookmark_buttons.insert(hovered, bookmark);
+                self.state.dragged_bookmark = Some(hovered);
+            }
+
+            if ui.input(|i| i.pointer.any_released()) {
+                self.state.dragged_bookmark = None;
+                changed = true;
+            }
+        }
+
+        if let Some(i) = remove {
+            self.state.bookmark_buttons.remove(i);
+            changed = true;
+        }
+
+        if changed {
+            self.actions
+                .push(DockAction::SetBookmarks(self.state.bookmarks()));
+        }
+    }
+}
+
+impl BookmarkButtonState {
+    pub fn bookmark(&self) -> &Bookmark {
+        &self.bookmark
+    }
+}
+
+button_newtype!(BookmarkButton {
+    wimpl: default;
+    custom_state: #[derive(Clone)] BookmarkButtonState {
+        bookmark: Bookmark,
+    };
+    modifiers: [
+        editable{true}
+    ]
+});
@@ -0,0 +1,147 @@
+use eframe::egui::{vec2, ProgressBar, RichText, Ui, Widget};
+
+use std::path::PathBuf;
+
+use crate::{
+    button_newtype,
+    components::dnd,
+    utils::{fs::format_size, icons::PhosphorIcon, mounts::Mount, theme::ThemeExt},
+};
+
+use super::{DockAction, Sidebar};
+
+impl<'a> Sidebar<'a> {
+    pub(super) fn devices_ui(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.state.show_pseudo_mounts, "Show system mounts");
+
+        let show_pseudo_mounts = self.state.show_pseudo_mounts;
+
+        self.state
+            .device_buttons
+            .iter_mut()
+            .filter(|device| show_pseudo_mounts || !device.mount.is_pseudo())
+            .for_each(|device| {
+                let response = ui.add(DeviceButton::new(device, self.theme));
+                dnd::drop_target(ui, response.rect, self.theme, &device.mount.mount_point);
+
+                if response.clicked() {
+                    self.actions.push(DockAction::Navigate(
+                        device.mount.mount_point.clone().into(),
+                    ));
+                }
+
+                let mount = &device.mount;
+                let source = PathBuf::from(&mount.source);
+                let device_backed = mount.source.starts_with("/dev/");
+
+                response
+                    .on_hover_text(format!(
+                        "{} on {}\n{}",
+                        mount.source,
+                        mount.mount_point.display(),
+                        mount.fs_type
+                    ))
+                    .context_menu(|ui| {
+                        if device_backed && ui.button("Unmount").clicked() {
+                            self.actions.push(DockAction::Unmount(source.clone()));
+                            ui.close_menu();
+                        }
+
+                        if mount.removable && ui.button("Eject").clicked() {
+                            self.actions.push(DockAction::Eject(source.clone()));
+                            ui.close_menu();
+                        }
+                    });
+
+                ui.horizontal(|ui| {
+                    if let Some(stats) = mount.stats {
+                        ui.add(
+                            ProgressBar::new(stats.used_fraction())
+                                .desired_width(ui.available_width() - 30.0)
+                                .text(format!(
+                                    "{} free of {}",
+                                    format_size(stats.available),
+                                    format_size(stats.total)
+                                )),
+                        );
+                    }
+
+                    if mount.read_only {
+                        ui.label(
+                            RichText::new("RO")
+                                .small()
+                                .color(self.theme.warning_color()),
+                        )
+                        .on_hover_text("Mounted read-only");
+                    }
+
+                    if mount.removable
+                        && ui
+                            .small_button(PhosphorIcon::Eject.rich_text())
+                            .on_hover_text("Eject")
+                            .clicked()
+                    {
+                        self.actions.push(DockAction::Eject(source.clone()));
+                    }
+                });
+            });
+
+        if self.state.unmounted_devices.is_empty() {
+            return;
+        }
+
+        ui.separator();
+
+        self.state.unmounted_devices.iter().for_each(|device| {
+            let label = match device.label.is_empty() {
+                true => device.device.display().to_string(),
+                false => device.label.clone(),
+            };
+
+            ui.horizontal(|ui| {
+                ui.label(
+                    PhosphorIcon::HardDrive
+                        .rich_text()
+                        .color(self.theme.muted_text_color()),
+                );
+                ui.label(RichText::new(label).color(self.theme.muted_text_color()))
+                    .on_hover_text(format!(
+                        "{}\n{}, {}",
+                        device.device.display(),
+                        device.fs_type,
+                        format_size(device.size)
+                    ));
+
+                if ui
+                    .small_button(PhosphorIcon::Plus.rich_text())
+                    .on_hover_text("Mount")
+                    .clicked()
+                {
+                    self.actions.push(DockAction::Mount(device.device.clone()));
+                }
+            });
+        });
+    }
+}
+
+button_newtype!(DeviceButton {
+    wimpl: custom;
+    custom_state: #[derive(Clone)] DeviceButtonState {
+        mount: Mount,
+    };
+});
+
+impl<'a> Widget for DeviceButton<'a> {
+    fn ui(mut self, ui: &mut Ui) -> eframe::egui::Response {
+        let icon = match self.state.mount.removable {
+            true => PhosphorIcon::Usb,
+            false => PhosphorIcon::HardDrive,
+        };
+
+        self.to_rsfm_but()
+            .with_icon(icon)
+            .with_icon_size(vec2(16.0, 16.0))
+            .with_spacing(Some(5.0))
+            .ui(ui)
+    }
+}
@@ -0,0 +1,279 @@
+use eframe::egui::{vec2, Button, ComboBox, DragValue, Id, RichText, TextEdit, Ui, Widget};
+
+use crate::{
+    button_newtype,
+    config::{ConnectionKind, ConnectionProfile, SecretRef},
+    utils::{icons::PhosphorIcon, theme::ThemeExt},
+};
+
+use super::{DockAction, Sidebar};
+
+/// Fields of the "add connection" form, kept in temp memory while it's open
+#[derive(Clone)]
+struct ConnectionForm {
+    name: String,
+    kind: ConnectionKind,
+    host: String,
+    port: u16,
+    user: String,
+    key: String,
+    passphrase: String,
+    secret: String,
+    path: String,
+    bucket: String,
+    region: String,
+}
+
+impl Default for ConnectionForm {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            kind: ConnectionKind::Sftp,
+            host: String::new(),
+            port: 22,
+            user: std::env::var("USER").unwrap_or_default(),
+            key: String::new(),
+            passphrase: String::new(),
+            secret: String::new(),
+            path: String::new(),
+            bucket: String::new(),
+            region: String::new(),
+        }
+    }
+}
+
+impl ConnectionForm {
+    /// `Err` explains what's missing or wrong
+    fn to_profile(&self) -> Result<ConnectionProfile, String> {
+        let host = self.host.trim();
+        if host.is_empty() {
+            return Err("Host is required".into());
+        }
+        if self.kind == ConnectionKind::Sftp && self.user.trim().is_empty() {
+            return Err("User is required".into());
+        }
+        if self.kind == ConnectionKind::S3 && self.bucket.trim().is_empty() {
+            return Err("Bucket is required".into());
+        }
+
+        let optional = |s: &str| (!s.trim().is_empty()).then(|| s.trim().to_string());
+        let secret = |s: &str| optional(s).map(|s| SecretRef::parse(&s)).transpose();
+
+        Ok(ConnectionProfile {
+            name: match self.name.trim() {
+                "" => host.to_string(),
+                name => name.to_string(),
+            },
+            kind: self.kind,
+            host: host.to_string(),
+            port: self.port,
+            user: self.user.trim().to_string(),
+            key: optional(&self.key).map(Into::into),
+            passphrase: secret(&self.passphrase)?,
+            secret: secret(&self.secret)?,
+            path: optional(&self.path).map(Into::into),
+            bucket: optional(&self.bucket),
+            region: optional(&self.region),
+        })
+    }
+}
+
+impl<'a> Sidebar<'a> {
+    pub(super) fn network_ui(&mut self, ui: &mut Ui) {
+        let mut remove = None;
+
+        self.state
+            .connection_buttons
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, connection)| {
+                let response = ui.add(NetworkButton::new(connection, self.theme));
+                let profile = &connection.profile;
+
+                if response.clicked() {
+                    self.actions.push(DockAction::Connect(profile.clone()));
+                }
+
+                response
+                    .on_hover_text(match profile.kind {
+                        ConnectionKind::Sftp => {
+                            format!("{}@{}:{}", profile.user, profile.host, profile.port)
+                        }
+                        ConnectionKind::WebDav => profile.host.clone(),
+                        ConnectionKind::S3 => format!(
+                            "{}/{}",
+                            profile.host,
+                            profile.bucket.as_deref().unwrap_or_default()
+                        ),
+                    })
+                    .context_menu(|ui| {
+                        if connection.connected && ui.button("Disconnect").clicked() {
+                            self.actions.push(DockAction::Disconnect(profile.clone()));
+                            ui.close_menu();
+                        }
+
+                        if ui.button("Remove").clicked() {
+                            remove = Some(i);
+                            ui.close_menu();
+                        }
+                    });
+            });
+
+        if let Some(i) = remove {
+            let removed = self.state.connection_buttons.remove(i);
+            if removed.connected {
+                self.actions.push(DockAction::Disconnect(removed.profile));
+            }
+
+            self.actions
+                .push(DockAction::SetConnections(self.state.connections()));
+        }
+
+        ui.menu_button(PhosphorIcon::Plus.rich_text(), |ui| {
+            self.add_connection_ui(ui)
+        })
+        .response
+        .on_hover_text("Add a connection");
+    }
+
+    fn add_connection_ui(&mut self, ui: &mut Ui) {
+        let id = Id::new("add_connection_form");
+        let mut form = ui.data_mut(|d| {
+            d.get_temp_mut_or_insert_with(id, ConnectionForm::default)
+                .clone()
+        });
+
+        ComboBox::from_id_source(id.with("kind"))
+            .selected_text(form.kind.label())
+            .show_ui(ui, |ui| {
+                ConnectionKind::ALL.iter().for_each(|kind| {
+                    ui.selectable_value(&mut form.kind, *kind, kind.label());
+                });
+            });
+
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.add(TextEdit::singleline(&mut form.name).hint_text("Same as the host"));
+        });
+        ui.horizontal(|ui| match form.kind {
+            ConnectionKind::Sftp => {
+                ui.label("Host");
+                ui.text_edit_singleline(&mut form.host);
+                ui.add(DragValue::new(&mut form.port).clamp_range(1..=u16::MAX));
+            }
+            ConnectionKind::WebDav | ConnectionKind::S3 => {
+                ui.label("URL");
+                ui.add(TextEdit::singleline(&mut form.host).hint_text("https://"));
+            }
+        });
+        ui.horizontal(|ui| {
+            match form.kind {
+                ConnectionKind::S3 => ui.label("Access key"),
+                ConnectionKind::Sftp | ConnectionKind::WebDav => ui.label("User"),
+            };
+            ui.text_edit_singleline(&mut form.user);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Secret");
+            ui.add(TextEdit::singleline(&mut form.secret).hint_text("env:, file: or cmd:"))
+                .on_hover_text("Where to read the password from, it's never stored in the config");
+        });
+        match form.kind {
+            ConnectionKind::Sftp => {
+                ui.horizontal(|ui| {
+                    ui.label("Key");
+                    ui.add(TextEdit::singleline(&mut form.key).hint_text("SSH agent and ~/.ssh"));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Passphrase");
+                    ui.add(
+                        TextEdit::singleline(&mut form.passphrase).hint_text("env:, file: or cmd:"),
+                    )
+                    .on_hover_text("Where to read the key's passphrase from, if it has one");
+                });
+            }
+            ConnectionKind::S3 => {
+                ui.horizontal(|ui| {
+                    ui.label("Bucket");
+                    ui.text_edit_singleline(&mut form.bucket);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Region");
+                    ui.add(TextEdit::singleline(&mut form.region).hint_text("us-east-1"));
+                });
+            }
+            ConnectionKind::WebDav => {}
+        }
+        ui.horizontal(|ui| {
+            ui.label("Path");
+            ui.add(TextEdit::singleline(&mut form.path).hint_text("Remote home"));
+        });
+
+        let profile = form.to_profile().and_then(|profile| {
+            let duplicate = self
+                .state
+                .connection_buttons
+                .iter()
+                .any(|button| button.profile.name == profile.name);
+
+            match duplicate {
+                true => Err("A connection with this name exists".to_string()),
+                false => Ok(profile),
+            }
+        });
+        if let Err(err) = &profile {
+            ui.label(RichText::new(err).color(self.theme.error_color()));
+        }
+
+        let add = ui.add_enabled(profile.is_ok(), Button::new("Add"));
+        match (add.clicked(), profile) {
+            (true, Ok(profile)) => {
+                let mut connections = self.state.connections();
+                connections.push(profile);
+                self.actions.push(DockAction::SetConnections(connections));
+
+                ui.data_mut(|d| d.remove::<ConnectionForm>(id));
+                ui.close_menu();
+            }
+            _ => ui.data_mut(|d| d.insert_temp(id, form)),
+        }
+    }
+}
+
+impl NetworkButtonState {
+    pub fn profile(&self) -> &ConnectionProfile {
+        &self.profile
+    }
+}
+
+button_newtype!(NetworkButton {
+    wimpl: custom;
+    custom_state: #[derive(Clone)] NetworkButtonState {
+        profile: ConnectionProfile,
+        connected: bool,
+    };
+});
+
+impl<'a> Widget for NetworkButton<'a> {
+    fn ui(mut self, ui: &mut Ui) -> eframe::egui::Response {
+        let color = match self.state.connected {
+            true => self.theme.success_color(),
+            false => self.theme.muted_text_color(),
+        };
+        let icon = match self.state.profile.kind {
+            ConnectionKind::S3 => PhosphorIcon::Cloud,
+            ConnectionKind::Sftp | ConnectionKind::WebDav => PhosphorIcon::Globe,
+        };
+
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("●").small().color(color));
+
+            self.to_rsfm_but()
+                .with_icon(icon)
+                .with_icon_size(vec2(16.0, 16.0))
+                .with_spacing(Some(5.0))
+                .ui(ui)
+        })
+        .inner
+    }
+}
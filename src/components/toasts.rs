@@ -0,0 +1,129 @@
+//! Short-lived notifications in the bottom right corner.
+//!
+//! Toasts are queued in the context's temp data, so anything holding a [`Context`]
+//! can report to the user without threading extra state through the widgets
+
+use std::time::Duration;
+
+use eframe::{
+    egui::{Align2, Area, Context, Frame, Id, Order, RichText},
+    epaint::{vec2, Color32},
+};
+
+use crate::utils::{
+    icons::PhosphorIcon,
+    theme::{Theme, ThemeExt},
+};
+
+const TOASTS_ID: &str = "rsfm_toasts";
+const TOAST_DURATION: Duration = Duration::from_secs(6);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ToastLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Clone)]
+struct Toast {
+    level: ToastLevel,
+    text: String,
+    /// [`eframe::egui::InputState::time`] the toast was shown at
+    created: Option<f64>,
+}
+
+fn toasts_id() -> Id {
+    Id::new(TOASTS_ID)
+}
+
+pub fn push(ctx: &Context, level: ToastLevel, text: impl Into<String>) {
+    let text = text.into();
+
+    match level {
+        ToastLevel::Info => tracing::info!("{text}"),
+        ToastLevel::Warning => tracing::warn!("{text}"),
+        ToastLevel::Error => tracing::error!("{text}"),
+    }
+
+    ctx.data_mut(|d| {
+        d.get_temp_mut_or_default::<Vec<Toast>>(toasts_id())
+            .push(Toast {
+                level,
+                text,
+                created: None,
+            })
+    });
+    ctx.request_repaint();
+}
+
+pub fn info(ctx: &Context, text: impl Into<String>) {
+    push(ctx, ToastLevel::Info, text)
+}
+
+pub fn warning(ctx: &Context, text: impl Into<String>) {
+    push(ctx, ToastLevel::Warning, text)
+}
+
+pub fn error(ctx: &Context, text: impl Into<String>) {
+    push(ctx, ToastLevel::Error, text)
+}
+
+/// Draws the queued toasts and drops the expired or dismissed ones. Call once per frame
+pub fn show(ctx: &Context, theme: &Theme) {
+    let now = ctx.input(|i| i.time);
+
+    let mut toasts = ctx.data(|d| d.get_temp::<Vec<Toast>>(toasts_id()).unwrap_or_default());
+    if toasts.is_empty() {
+        return;
+    }
+
+    let mut dismissed = None;
+
+    Area::new(toasts_id())
+        .order(Order::Foreground)
+        .anchor(Align2::RIGHT_BOTTOM, vec2(-10.0, -10.0))
+        .show(ctx, |ui| {
+            toasts.iter_mut().enumerate().for_each(|(i, toast)| {
+                toast.created.get_or_insert(now);
+
+                let (icon, color) = toast_style(toast.level, theme);
+
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.set_max_width(350.0);
+
+                    ui.horizontal(|ui| {
+                        ui.label(icon.rich_text().color(color));
+                        ui.label(RichText::new(&toast.text).color(theme.text_color()));
+
+                        if ui.small_button(PhosphorIcon::X.rich_text()).clicked() {
+                            dismissed = Some(i);
+                        }
+                    });
+                });
+            });
+        });
+
+    if let Some(i) = dismissed {
+        toasts.remove(i);
+    }
+    toasts.retain(|toast| {
+        toast
+            .created
+            .is_none_or(|created| now - created < TOAST_DURATION.as_secs_f64())
+    });
+
+    if !toasts.is_empty() {
+        ctx.request_repaint_after(Duration::from_millis(500));
+    }
+
+    ctx.data_mut(|d| d.insert_temp(toasts_id(), toasts));
+}
+
+fn toast_style(level: ToastLevel, theme: &Theme) -> (PhosphorIcon, Color32) {
+    match level {
+        ToastLevel::Info => (PhosphorIcon::Info, theme.info_color()),
+        ToastLevel::Warning => (PhosphorIcon::Warning, theme.warning_color()),
+        ToastLevel::Error => (PhosphorIcon::SealWarning, theme.error_color()),
+    }
+}
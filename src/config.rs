@@ -1,11 +1,28 @@
-use std::{io::Write, path::PathBuf};
+use std::path::PathBuf;
 
+use eframe::{
+    egui::{Key, Modifiers},
+    epaint::Color32,
+};
 use serde::{Deserialize, Serialize};
 
+use crate::utils::{color_scheme::ColorScheme, keymap::Shortcut, ls_colors::LsColors, theme};
+
+use self::parse::{ConfigError, CONFIG_VERSION};
+
+pub use self::{backup::Backup, watch::ConfigWatcher};
+
+mod backup;
+mod parse;
+mod themes;
+mod watch;
+
 pub struct Config {
     dir_path: PathBuf,
     file_path: PathBuf,
     file: ConfigFile,
+    error: Option<String>,
+    warnings: Vec<String>,
 }
 
 macro_rules! ccf_getters {
@@ -19,118 +36,917 @@ macro_rules! ccf_getters {
 }
 
 impl Config {
+    /// Never fails, a missing or broken file leaves the defaults in place and
+    /// [`Config::error`] explains what went wrong
     pub fn load(override_path: Option<impl Into<PathBuf>>) -> Self {
         let dir_path = override_path.map(Into::into).unwrap_or(
             dirs::config_dir()
                 .map(|config_dir| config_dir.join("rsfm"))
                 .expect("Failed to find a config dir"),
         );
+        let file_path = dir_path.join("config.toml");
+
+        let mut res = Self {
+            dir_path,
+            file_path,
+            file: ConfigFile::default(),
+            error: None,
+            warnings: vec![],
+        };
 
-        if !dir_path.exists() {
-            std::fs::create_dir_all(&dir_path).expect("Failed to create config dir");
+        if let Err(err) = std::fs::create_dir_all(&res.dir_path) {
+            tracing::error!("Failed to create the config dir: {err}");
         }
 
-        let file_path = dir_path.join("config.toml");
+        if !res.file_path.exists() {
+            res.save();
+            return res;
+        }
+
+        let parsed = std::fs::read_to_string(&res.file_path)
+            .map_err(|err| ConfigError::Io {
+                path: res.file_path.to_string_lossy().to_string(),
+                err,
+            })
+            .and_then(|src| parse::parse(&res.file_path, &src));
 
-        match file_path.exists() {
-            true => {
-                let file = toml::from_str(
-                    &std::fs::read_to_string(&file_path).expect("Failed to read the config file"),
-                )
-                .expect("Failed to deserialize config file");
-
-                Self {
-                    dir_path,
-                    file_path,
-                    file,
+        match parsed {
+            Ok(parsed) => {
+                res.file = parsed.file;
+                res.warnings = unknown_key_warnings(parsed.unknown_keys);
+
+                if let Some(version) = parsed.migrated_from {
+                    res.keep_pre_migration_copy(version);
+                    res.save();
+                    res.warnings.push(format!(
+                        "Config migrated from version {version} to {CONFIG_VERSION}"
+                    ));
                 }
             }
-            false => {
-                let file = ConfigFile::default();
+            Err(err) => {
+                let report = err.render();
+                tracing::error!("{report}");
+                res.error = Some(report);
+            }
+        }
 
-                let res = Self {
-                    dir_path,
-                    file_path,
-                    file,
-                };
-                res.save();
+        res
+    }
 
-                res
-            }
+    /// The old file stays next to the new one as `config.toml.v<version>`
+    fn keep_pre_migration_copy(&self, version: u32) {
+        let copy = self.file_path.with_extension(format!("toml.v{version}"));
+        if let Err(err) = std::fs::copy(&self.file_path, &copy) {
+            tracing::error!("Failed to keep a copy of the config before migrating it: {err}");
         }
     }
 
+    /// Rendered diagnostic of the file that failed to load, saving is off until it's fixed
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// Problems worth telling the user about that didn't stop the file from loading
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
     pub fn save(&self) {
-        // TODO: backup
+        if let Err(err) = self.try_save() {
+            tracing::error!("{err}");
+        }
+    }
+
+    /// `Err` says why nothing was written
+    pub fn try_save(&self) -> Result<(), String> {
+        if self.error.is_some() {
+            return Err("Not saving over the config file that failed to load".into());
+        }
 
-        let toml = match toml::to_string_pretty(&self.file) {
-            Ok(toml) => toml,
+        let toml = toml::to_string_pretty(&self.file)
+            .map_err(|err| format!("Failed to serialize config file: {err}"))?;
+
+        if let Err(err) = backup::create(&self.dir_path, &self.file_path, self.file.backups.count) {
+            tracing::error!("Failed to back up the config file: {err}");
+        }
+
+        backup::write_atomic(&self.file_path, toml.as_bytes())
+            .map_err(|err| format!("Failed to save config to the file: {err}"))
+    }
+
+    /// Re-reads the file after it was edited elsewhere. An invalid edit keeps the current config
+    /// but stops saving, so it can't overwrite the edit, and `Err` is the rendered diagnostic
+    pub fn reload(&mut self) -> Result<ConfigChanges, String> {
+        let parsed = std::fs::read_to_string(&self.file_path)
+            .map_err(|err| ConfigError::Io {
+                path: self.file_path.to_string_lossy().to_string(),
+                err,
+            })
+            .and_then(|src| parse::parse(&self.file_path, &src));
+
+        let parsed = match parsed {
+            Ok(parsed) => parsed,
             Err(err) => {
-                tracing::error!("Failed to serialize config file: {err}");
-                return;
+                let report = err.render();
+                tracing::error!("{report}");
+                self.error = Some(report.clone());
+                return Err(report);
             }
         };
 
-        match std::fs::File::create(&self.file_path) {
-            Ok(mut file) => {
-                if let Err(err) = file.write(toml.as_bytes()) {
-                    tracing::error!("Failed to save config to the file: {err}");
-                }
+        let changes = ConfigChanges::between(&self.file, &parsed.file);
+
+        self.file = parsed.file;
+        self.error = None;
+        self.warnings = unknown_key_warnings(parsed.unknown_keys);
+
+        Ok(changes)
+    }
+
+    /// Newest first
+    pub fn backups(&self) -> Vec<Backup> {
+        backup::list(&self.dir_path).unwrap_or_else(|err| {
+            tracing::error!("Failed to list config backups: {err}");
+            vec![]
+        })
+    }
+
+    /// Replaces the current file with a backup, which gets backed up itself first.
+    /// `Err` is the rendered diagnostic of a backup that doesn't load
+    pub fn restore(&mut self, backup: &Backup) -> Result<(), String> {
+        let parsed = std::fs::read_to_string(&backup.path)
+            .map_err(|err| ConfigError::Io {
+                path: backup.path.to_string_lossy().to_string(),
+                err,
+            })
+            .and_then(|src| parse::parse(&backup.path, &src))
+            .map_err(|err| err.render())?;
+
+        self.file = parsed.file;
+        self.error = None;
+        self.save();
+
+        Ok(())
+    }
+
+    pub fn set_theme(&mut self, theme: Theme, accent: Accent) {
+        self.file.theme = theme;
+        self.file.accent = accent;
+        self.save()
+    }
+
+    /// Names of the themes in the `themes` dir
+    pub fn custom_themes(&self) -> Vec<String> {
+        themes::list(&self.dir_path)
+    }
+
+    /// Loads custom themes from their files and picks the auto theme's flavour for `scheme`,
+    /// `Err` is the rendered diagnostic
+    pub fn resolve_theme(
+        &self,
+        theme: &Theme,
+        accent: Accent,
+        scheme: ColorScheme,
+    ) -> Result<theme::Theme, String> {
+        let theme = match theme {
+            Theme::Auto => self.file.auto_theme.pick(scheme),
+            theme => theme,
+        };
+
+        match (theme, theme.flavour()) {
+            (_, Some(palette)) => Ok(theme::Theme::new(palette, accent.color(&palette))),
+            (Theme::Custom(name), None) => {
+                themes::load(&self.dir_path, name, accent).map_err(|err| err.render())
             }
-            Err(err) => {
-                tracing::error!("Failed to create a config file: {err}")
+            // The auto theme mapped onto itself
+            _ => self.resolve_theme(&Theme::default(), accent, scheme),
+        }
+    }
+
+    /// The `dircolors` file if one is set, otherwise `LS_COLORS` if that's on.
+    /// `Err` explains why the `dircolors` file can't be used
+    pub fn ls_colors(&self) -> Result<Option<LsColors>, String> {
+        let colors = &self.file.colors;
+
+        match &colors.dircolors {
+            Some(path) => {
+                let path = self.dir_path.join(path);
+                LsColors::load_dircolors(&path)
+                    .map(Some)
+                    .map_err(|err| format!("Failed to read the dircolors file {path:?}: {err}"))
             }
+            None => Ok(colors.ls_colors.then(LsColors::from_env).flatten()),
         }
     }
 
-    pub fn switch_theme(&mut self) {
-        self.file.theme.switch();
+    pub fn set_bookmarks(&mut self, bookmarks: Vec<Bookmark>) {
+        self.file.bookmarks = bookmarks;
         self.save()
     }
 
-    ccf_getters!(theme: Theme, overrides: Overrides);
+    pub fn set_connections(&mut self, connections: Vec<ConnectionProfile>) {
+        self.file.connections = connections;
+        self.save()
+    }
+
+    pub fn file(&self) -> &ConfigFile {
+        &self.file
+    }
+
+    /// Replaces everything at once, e.g. from the settings tab. The new file is used even when
+    /// `Err` says why it couldn't be saved
+    pub fn set_file(&mut self, file: ConfigFile) -> Result<(), String> {
+        self.file = file;
+        self.try_save()
+    }
+
+    ccf_getters!(
+        theme: Theme,
+        accent: Accent,
+        view: ViewDefaults,
+        overrides: Overrides,
+        bookmarks: Vec<Bookmark>,
+        connections: Vec<ConnectionProfile>
+    );
+}
+
+fn unknown_key_warnings(keys: Vec<String>) -> Vec<String> {
+    keys.into_iter()
+        .map(|key| format!("Unknown config key `{key}` is ignored"))
+        .collect()
+}
+
+/// What a [`Config::reload`] changed
+#[derive(Clone, Copy)]
+pub struct ConfigChanges {
+    pub theme: bool,
+    /// Only new tabs pick it up
+    pub view: bool,
+    pub sorting: bool,
+    /// Read whenever a key is pressed, nothing to apply
+    pub keymap: bool,
+    pub thumbnails: bool,
+    pub colors: bool,
+    pub hidden: bool,
+    pub paths: bool,
+    pub bookmarks: bool,
+    pub connections: bool,
+}
+
+impl ConfigChanges {
+    fn between(old: &ConfigFile, new: &ConfigFile) -> Self {
+        Self {
+            theme: old.theme != new.theme
+                || old.auto_theme != new.auto_theme
+                || old.accent != new.accent,
+            view: old.view != new.view,
+            sorting: old.sorting != new.sorting,
+            keymap: old.keymap != new.keymap,
+            thumbnails: old.thumbnails != new.thumbnails,
+            colors: old.colors != new.colors,
+            hidden: old.hidden != new.hidden,
+            paths: old.overrides != new.overrides,
+            bookmarks: old.bookmarks != new.bookmarks,
+            connections: old.connections != new.connections,
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.theme
+            || self.view
+            || self.sorting
+            || self.keymap
+            || self.thumbnails
+            || self.colors
+            || self.hidden
+            || self.paths
+            || self.bookmarks
+            || self.connections
+    }
 }
 
-#[derive(Default, Serialize, Deserialize)]
+/// Keys missing from the file take their default
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ConfigFile {
-    theme: Theme,
-    overrides: Overrides,
+    /// Bumped whenever old files need migrating, see [`parse::CONFIG_VERSION`]
+    version: u32,
+    pub theme: Theme,
+    pub auto_theme: AutoTheme,
+    pub accent: Accent,
+    pub view: ViewDefaults,
+    pub sorting: Sorting,
+    pub keymap: Keymap,
+    pub thumbnails: Thumbnails,
+    pub colors: FileColors,
+    pub hidden: HiddenFiles,
+    pub overrides: Overrides,
+    pub bookmarks: Vec<Bookmark>,
+    pub connections: Vec<ConnectionProfile>,
+    pub backups: Backups,
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            theme: Theme::default(),
+            auto_theme: AutoTheme::default(),
+            accent: Accent::default(),
+            view: ViewDefaults::default(),
+            sorting: Sorting::default(),
+            keymap: Keymap::default(),
+            thumbnails: Thumbnails::default(),
+            colors: FileColors::default(),
+            hidden: HiddenFiles::default(),
+            overrides: Overrides::default(),
+            bookmarks: vec![],
+            connections: vec![],
+            backups: Backups::default(),
+        }
+    }
+}
+
+/// How new directory tabs look
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ViewDefaults {
+    pub mode: DirViewMode,
+    pub icon_size: DirViewIconSize,
+    pub show_hidden: bool,
+}
+
+impl Default for ViewDefaults {
+    fn default() -> Self {
+        Self {
+            mode: DirViewMode::Grid,
+            icon_size: DirViewIconSize::Small,
+            show_hidden: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DirViewMode {
+    Grid,
+    List,
+}
+
+impl DirViewMode {
+    pub const ALL: [DirViewMode; 2] = [DirViewMode::Grid, DirViewMode::List];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DirViewMode::Grid => "Grid",
+            DirViewMode::List => "List",
+        }
+    }
+
+    pub fn switch(&mut self) {
+        use DirViewMode::*;
+
+        *self = match self {
+            Grid => List,
+            List => Grid,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DirViewIconSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl DirViewIconSize {
+    pub const ALL: [DirViewIconSize; 3] = [
+        DirViewIconSize::Small,
+        DirViewIconSize::Medium,
+        DirViewIconSize::Large,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DirViewIconSize::Small => "Small",
+            DirViewIconSize::Medium => "Medium",
+            DirViewIconSize::Large => "Large",
+        }
+    }
+
+    pub fn switch(&mut self) {
+        use DirViewIconSize::*;
+
+        *self = match self {
+            Small => Medium,
+            Medium => Large,
+            Large => Small,
+        }
+    }
+
+    /// Edge length of the icons in points
+    pub fn as_f32(&self) -> f32 {
+        match self {
+            DirViewIconSize::Small => 50.0,
+            DirViewIconSize::Medium => 75.0,
+            DirViewIconSize::Large => 100.0,
+        }
+    }
+}
+
+/// The order listings are shown in
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sorting {
+    pub by: SortBy,
+    pub descending: bool,
+    /// Directories and links to them go first whichever way the rest is sorted
+    pub directories_first: bool,
+}
+
+impl Default for Sorting {
+    fn default() -> Self {
+        Self {
+            by: SortBy::Name,
+            descending: false,
+            directories_first: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SortBy {
+    Name,
+    Extension,
+    Size,
+    Modified,
+}
+
+impl SortBy {
+    pub const ALL: [SortBy; 4] = [
+        SortBy::Name,
+        SortBy::Extension,
+        SortBy::Size,
+        SortBy::Modified,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SortBy::Name => "Name",
+            SortBy::Extension => "Extension",
+            SortBy::Size => "Size",
+            SortBy::Modified => "Date modified",
+        }
+    }
+}
+
+/// Shortcuts of the actions that have one, written like `Ctrl+Shift+Z`
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keymap {
+    pub toggle_hidden: Shortcut,
+    pub undo: Shortcut,
+    pub redo: Shortcut,
+    pub trash: Shortcut,
 }
 
-#[derive(Default, Clone, Copy, Serialize, Deserialize)]
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            toggle_hidden: Shortcut::new(Modifiers::CTRL, Key::H),
+            undo: Shortcut::new(Modifiers::CTRL, Key::Z),
+            redo: Shortcut::new(Modifiers::CTRL.plus(Modifiers::SHIFT), Key::Z),
+            trash: Shortcut::new(Modifiers::NONE, Key::Delete),
+        }
+    }
+}
+
+impl Keymap {
+    /// Every shortcut with its action's name, for editing them all the same way
+    pub fn entries_mut(&mut self) -> [(&'static str, &mut Shortcut); 4] {
+        [
+            ("Show hidden files", &mut self.toggle_hidden),
+            ("Undo", &mut self.undo),
+            ("Redo", &mut self.redo),
+            ("Move to trash", &mut self.trash),
+        ]
+    }
+}
+
+/// Image files shown by their contents instead of an icon
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thumbnails {
+    pub enabled: bool,
+    /// Bigger files keep their icon, in MiB
+    pub max_file_size: u64,
+    /// Also for files on network connections, each one is a download
+    pub remote: bool,
+}
+
+impl Default for Thumbnails {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_file_size: 10,
+            remote: false,
+        }
+    }
+}
+
+/// Coloring files like `ls --color` does, files no entry matches take the theme's colors
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileColors {
+    /// Read `LS_COLORS` from the environment
+    pub ls_colors: bool,
+    /// A `dircolors` database used instead of `LS_COLORS`, relative to the config dir
+    pub dircolors: Option<PathBuf>,
+}
+
+impl Default for FileColors {
+    fn default() -> Self {
+        Self {
+            ls_colors: true,
+            dircolors: None,
+        }
+    }
+}
+
+/// What counts as hidden besides dotfiles
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HiddenFiles {
+    /// Globs matched against file names, e.g. `*.pyc`
+    pub patterns: Vec<String>,
+    /// Hide the names listed in a directory's `.hidden` file
+    pub dot_hidden: bool,
+}
+
+impl Default for HiddenFiles {
+    fn default() -> Self {
+        Self {
+            patterns: vec![],
+            dot_hidden: true,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Backups {
+    /// How many copies of previous saves to keep in `backups/`, 0 turns them off
+    pub count: usize,
+}
+
+impl Default for Backups {
+    fn default() -> Self {
+        Self { count: 10 }
+    }
+}
+
+/// A Catppuccin flavour, `Auto` or the name of a file in the `themes` dir.
+/// Written as the bare name, e.g. `theme = "Mocha"` or `theme = "nord"`
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
 pub enum Theme {
     Frappe,
     Latte,
     Macchiato,
     #[default]
     Mocha,
+    /// Whichever of [`AutoTheme`]'s themes matches the desktop's light/dark preference
+    Auto,
+    Custom(String),
 }
 
 impl Theme {
-    pub fn switch(&mut self) {
-        use Theme::*;
+    pub const FLAVOURS: [Theme; 4] = [Theme::Frappe, Theme::Latte, Theme::Macchiato, Theme::Mocha];
 
-        *self = match self {
-            Frappe => Latte,
-            Latte => Macchiato,
-            Macchiato => Mocha,
-            Mocha => Frappe,
+    /// Flavours, the auto theme, then the `custom` ones
+    pub fn all(custom: &[String]) -> impl Iterator<Item = Theme> + '_ {
+        Self::FLAVOURS
+            .into_iter()
+            .chain([Theme::Auto])
+            .chain(custom.iter().cloned().map(Theme::Custom))
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            Theme::Frappe => "Frappé",
+            Theme::Latte => "Latte",
+            Theme::Macchiato => "Macchiato",
+            Theme::Mocha => "Mocha",
+            Theme::Auto => "Auto",
+            Theme::Custom(name) => name,
+        }
+    }
+
+    /// `None` for custom themes, they have to be loaded first, and for the auto theme
+    pub fn flavour(&self) -> Option<catppuccin_egui::Theme> {
+        match self {
+            Theme::Frappe => Some(catppuccin_egui::FRAPPE),
+            Theme::Latte => Some(catppuccin_egui::LATTE),
+            Theme::Macchiato => Some(catppuccin_egui::MACCHIATO),
+            Theme::Mocha => Some(catppuccin_egui::MOCHA),
+            Theme::Auto | Theme::Custom(_) => None,
+        }
+    }
+}
+
+impl From<String> for Theme {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "Frappe" => Theme::Frappe,
+            "Latte" => Theme::Latte,
+            "Macchiato" => Theme::Macchiato,
+            "Mocha" => Theme::Mocha,
+            "Auto" => Theme::Auto,
+            _ => Theme::Custom(value),
         }
     }
 }
 
-impl From<Theme> for catppuccin_egui::Theme {
+impl From<Theme> for String {
     fn from(value: Theme) -> Self {
         match value {
-            Theme::Frappe => catppuccin_egui::FRAPPE,
-            Theme::Latte => catppuccin_egui::LATTE,
-            Theme::Macchiato => catppuccin_egui::MACCHIATO,
-            Theme::Mocha => catppuccin_egui::MOCHA,
+            Theme::Frappe => "Frappe".into(),
+            Theme::Latte => "Latte".into(),
+            Theme::Macchiato => "Macchiato".into(),
+            Theme::Mocha => "Mocha".into(),
+            Theme::Auto => "Auto".into(),
+            Theme::Custom(name) => name,
+        }
+    }
+}
+
+/// Themes the auto theme switches between
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoTheme {
+    pub dark: Theme,
+    pub light: Theme,
+}
+
+impl Default for AutoTheme {
+    fn default() -> Self {
+        Self {
+            dark: Theme::Mocha,
+            light: Theme::Latte,
+        }
+    }
+}
+
+impl AutoTheme {
+    pub fn pick(&self, scheme: ColorScheme) -> &Theme {
+        match scheme {
+            ColorScheme::Dark => &self.dark,
+            ColorScheme::Light => &self.light,
+        }
+    }
+}
+
+/// Highlight color for selections and links, one of the Catppuccin accents
+#[derive(Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Accent {
+    Rosewater,
+    Flamingo,
+    Pink,
+    Mauve,
+    Red,
+    Maroon,
+    Peach,
+    Yellow,
+    Green,
+    Teal,
+    Sky,
+    Sapphire,
+    #[default]
+    Blue,
+    Lavender,
+}
+
+impl Accent {
+    pub const ALL: [Accent; 14] = [
+        Accent::Rosewater,
+        Accent::Flamingo,
+        Accent::Pink,
+        Accent::Mauve,
+        Accent::Red,
+        Accent::Maroon,
+        Accent::Peach,
+        Accent::Yellow,
+        Accent::Green,
+        Accent::Teal,
+        Accent::Sky,
+        Accent::Sapphire,
+        Accent::Blue,
+        Accent::Lavender,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Accent::Rosewater => "Rosewater",
+            Accent::Flamingo => "Flamingo",
+            Accent::Pink => "Pink",
+            Accent::Mauve => "Mauve",
+            Accent::Red => "Red",
+            Accent::Maroon => "Maroon",
+            Accent::Peach => "Peach",
+            Accent::Yellow => "Yellow",
+            Accent::Green => "Green",
+            Accent::Teal => "Teal",
+            Accent::Sky => "Sky",
+            Accent::Sapphire => "Sapphire",
+            Accent::Blue => "Blue",
+            Accent::Lavender => "Lavender",
+        }
+    }
+
+    pub fn color(&self, palette: &catppuccin_egui::Theme) -> Color32 {
+        match self {
+            Accent::Rosewater => palette.rosewater,
+            Accent::Flamingo => palette.flamingo,
+            Accent::Pink => palette.pink,
+            Accent::Mauve => palette.mauve,
+            Accent::Red => palette.red,
+            Accent::Maroon => palette.maroon,
+            Accent::Peach => palette.peach,
+            Accent::Yellow => palette.yellow,
+            Accent::Green => palette.green,
+            Accent::Teal => palette.teal,
+            Accent::Sky => palette.sky,
+            Accent::Sapphire => palette.sapphire,
+            Accent::Blue => palette.blue,
+            Accent::Lavender => palette.lavender,
         }
     }
 }
 
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub label: String,
+    pub path: PathBuf,
+}
+
+impl Bookmark {
+    /// Labels the bookmark with the last component of the `path`
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let label = path
+            .file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .to_string();
+
+        Self { label, path }
+    }
+}
+
+/// A remote machine or bucket shown in the sidebar's Network section
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionProfile {
+    pub name: String,
+    #[serde(default)]
+    pub kind: ConnectionKind,
+    /// Host name for SFTP, base URL of the share for WebDAV and of the endpoint for S3
+    pub host: String,
+    #[serde(default = "ConnectionProfile::default_port")]
+    pub port: u16,
+    /// User name, the access key id for S3
+    #[serde(default)]
+    pub user: String,
+    /// Private key to authenticate with, the SSH agent and the default keys in `~/.ssh` are tried otherwise
+    #[serde(default)]
+    pub key: Option<PathBuf>,
+    /// Unlocks `key` when it's encrypted
+    #[serde(default)]
+    pub passphrase: Option<SecretRef>,
+    /// Password, or the secret key for S3
+    #[serde(default)]
+    pub secret: Option<SecretRef>,
+    /// Directory to open after connecting, the remote home if unset
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub bucket: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+impl ConnectionProfile {
+    fn default_port() -> u16 {
+        22
+    }
+
+    /// Id the connection's filesystem is [registered](crate::utils::vfs::register) under
+    pub fn vfs_id(&self) -> String {
+        format!("{}:{}", self.kind.scheme(), self.name)
+    }
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionKind {
+    #[default]
+    Sftp,
+    WebDav,
+    S3,
+}
+
+impl ConnectionKind {
+    pub const ALL: [ConnectionKind; 3] = [Self::Sftp, Self::WebDav, Self::S3];
+
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            ConnectionKind::Sftp => "sftp",
+            ConnectionKind::WebDav => "dav",
+            ConnectionKind::S3 => "s3",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConnectionKind::Sftp => "SFTP",
+            ConnectionKind::WebDav => "WebDAV",
+            ConnectionKind::S3 => "S3",
+        }
+    }
+}
+
+/// Where a password or key comes from, so it never has to be written into the config.
+/// Written as `env:VARIABLE`, `file:PATH` or `cmd:COMMAND`
+#[derive(Clone, PartialEq)]
+pub enum SecretRef {
+    Env(String),
+    /// Only the first line of the file is used
+    File(PathBuf),
+    /// Run with `sh -c`, e.g. `pass show s3/minio`
+    Command(String),
+}
+
+impl SecretRef {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.split_once(':') {
+            Some(("env", var)) if !var.is_empty() => Ok(Self::Env(var.to_string())),
+            Some(("file", path)) if !path.is_empty() => Ok(Self::File(path.into())),
+            Some(("cmd", command)) if !command.is_empty() => Ok(Self::Command(command.to_string())),
+            _ => Err(format!(
+                "{s:?} isn't a secret reference, expected env:VARIABLE, file:PATH or cmd:COMMAND"
+            )),
+        }
+    }
+
+    pub fn resolve(&self) -> std::io::Result<String> {
+        use std::io::{Error, ErrorKind};
+
+        let secret = match self {
+            SecretRef::Env(var) => std::env::var(var)
+                .map_err(|err| Error::new(ErrorKind::NotFound, format!("${var}: {err}")))?,
+            SecretRef::File(path) => std::fs::read_to_string(path)?,
+            SecretRef::Command(command) => {
+                let output = std::process::Command::new("sh")
+                    .args(["-c", command])
+                    .output()?;
+                if !output.status.success() {
+                    return Err(Error::other(format!(
+                        "`{command}` failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    )));
+                }
+
+                String::from_utf8_lossy(&output.stdout).to_string()
+            }
+        };
+
+        Ok(secret.lines().next().unwrap_or_default().to_string())
+    }
+}
+
+impl std::fmt::Display for SecretRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretRef::Env(var) => write!(f, "env:{var}"),
+            SecretRef::File(path) => write!(f, "file:{}", path.display()),
+            SecretRef::Command(command) => write!(f, "cmd:{command}"),
+        }
+    }
+}
+
+impl Serialize for SecretRef {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretRef {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Overrides {
     paths: PathsOverrides,
 }
@@ -139,13 +955,23 @@ impl Overrides {
     pub fn paths(&self) -> &PathsOverrides {
         &self.paths
     }
+
+    pub fn paths_mut(&mut self) -> &mut PathsOverrides {
+        &mut self.paths
+    }
 }
 
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PathsOverrides {
     downloads: Option<PathBuf>,
     desktop: Option<PathBuf>,
     documents: Option<PathBuf>,
+    music: Option<PathBuf>,
+    pictures: Option<PathBuf>,
+    videos: Option<PathBuf>,
+    templates: Option<PathBuf>,
+    public: Option<PathBuf>,
 }
 
 macro_rules! po_getters {
@@ -159,5 +985,52 @@ macro_rules! po_getters {
 }
 
 impl PathsOverrides {
-    po_getters!(downloads, desktop, documents);
+    po_getters!(downloads, desktop, documents, music, pictures, videos, templates, public);
+
+    /// Every override with its name, for editing them all the same way
+    pub fn entries_mut(&mut self) -> [(&'static str, &mut Option<PathBuf>); 8] {
+        [
+            ("Downloads", &mut self.downloads),
+            ("Desktop", &mut self.desktop),
+            ("Documents", &mut self.documents),
+            ("Music", &mut self.music),
+            ("Pictures", &mut self.pictures),
+            ("Videos", &mut self.videos),
+            ("Templates", &mut self.templates),
+            ("Public", &mut self.public),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_no_changes_between_equal_files() {
+        let file = ConfigFile::default();
+        assert!(!ConfigChanges::between(&file, &file.clone()).any());
+    }
+
+    #[test]
+    fn flags_only_the_sections_that_changed() {
+        let old = ConfigFile::default();
+
+        let mut new = old.clone();
+        new.theme = Theme::Latte;
+        new.bookmarks.push(Bookmark::new("/tmp"));
+        let changes = ConfigChanges::between(&old, &new);
+        assert!(changes.theme && changes.bookmarks && changes.any());
+        assert!(!changes.paths && !changes.connections && !changes.hidden);
+
+        let mut new = old.clone();
+        new.overrides.paths_mut().downloads = Some("/tmp".into());
+        let changes = ConfigChanges::between(&old, &new);
+        assert!(changes.paths && changes.any());
+        assert!(!changes.theme && !changes.bookmarks);
+
+        let mut new = old.clone();
+        new.hidden.patterns.push("*.pyc".into());
+        assert!(ConfigChanges::between(&old, &new).hidden);
+    }
 }
@@ -0,0 +1,182 @@
+//! Crash-safe writes of `config.toml` and the rolling backups kept before each one
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+const BACKUPS_DIR: &str = "backups";
+const BACKUP_PREFIX: &str = "config-";
+const BACKUP_EXTENSION: &str = "toml";
+
+/// Replaces `path` with `contents` so that a crash leaves either the old or the new file,
+/// never a truncated one
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp_path = path.with_extension("toml.tmp");
+
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(contents)?;
+    tmp.sync_all()?;
+    drop(tmp);
+
+    fs::rename(&tmp_path, path)?;
+
+    // The rename itself only sticks once the directory is synced
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+pub struct Backup {
+    pub path: PathBuf,
+    /// Timestamp from the file name, as written
+    pub created: String,
+}
+
+pub fn backups_dir(config_dir: &Path) -> PathBuf {
+    config_dir.join(BACKUPS_DIR)
+}
+
+/// Newest first
+pub fn list(config_dir: &Path) -> io::Result<Vec<Backup>> {
+    let mut backups = match fs::read_dir(backups_dir(config_dir)) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let path = entry.path();
+                let created = path
+                    .file_stem()?
+                    .to_str()?
+                    .strip_prefix(BACKUP_PREFIX)?
+                    .to_string();
+
+                (path.extension()? == BACKUP_EXTENSION).then_some(Backup { path, created })
+            })
+            .collect::<Vec<_>>(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+        Err(err) => return Err(err),
+    };
+
+    // RFC 3339 timestamps sort chronologically as strings
+    backups.sort_by(|a, b| b.created.cmp(&a.created));
+    Ok(backups)
+}
+
+/// Copies the current file into the backups unless the newest one already has the same contents,
+/// then drops the oldest ones past `keep`
+pub fn create(config_dir: &Path, file_path: &Path, keep: usize) -> io::Result<()> {
+    if keep == 0 || !file_path.exists() {
+        return Ok(());
+    }
+
+    let contents = fs::read(file_path)?;
+    let backups = list(config_dir)?;
+
+    let unchanged = backups
+        .first()
+        .and_then(|newest| fs::read(&newest.path).ok())
+        .is_some_and(|newest| newest == contents);
+
+    if !unchanged {
+        let dir = backups_dir(config_dir);
+        fs::create_dir_all(&dir)?;
+
+        // `:` isn't allowed in file names everywhere
+        let created = humantime::format_rfc3339_millis(SystemTime::now())
+            .to_string()
+            .replace(':', "-");
+        write_atomic(
+            &dir.join(format!("{BACKUP_PREFIX}{created}.{BACKUP_EXTENSION}")),
+            &contents,
+        )?;
+    }
+
+    list(config_dir)?
+        .into_iter()
+        .skip(keep)
+        .try_for_each(|old| fs::remove_file(old.path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use crate::utils::fs::TestDir;
+
+    use super::*;
+
+    /// Saves `contents` and backs up what was there before, a millisecond apart so every backup
+    /// gets its own timestamp
+    fn save(dir: &TestDir, contents: &str, keep: usize) {
+        let file_path = dir.0.join("config.toml");
+        create(&dir.0, &file_path, keep).unwrap();
+        write_atomic(&file_path, contents.as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(2));
+    }
+
+    fn listed(dir: &TestDir) -> Vec<String> {
+        list(&dir.0)
+            .unwrap()
+            .into_iter()
+            .map(|backup| fs::read_to_string(backup.path).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn writes_atomically() {
+        let dir = TestDir::new("backup-write");
+        let path = dir.0.join("config.toml");
+
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!path.with_extension("toml.tmp").exists());
+    }
+
+    #[test]
+    fn backs_up_the_previous_file_newest_first() {
+        let dir = TestDir::new("backup-create");
+        assert!(listed(&dir).is_empty());
+
+        save(&dir, "a", 5);
+        assert!(listed(&dir).is_empty());
+
+        save(&dir, "b", 5);
+        save(&dir, "c", 5);
+        assert_eq!(listed(&dir), ["b", "a"]);
+
+        save(&dir, "c", 5);
+        assert_eq!(listed(&dir), ["c", "b", "a"]);
+        // The file is still the newest backup, so it isn't backed up twice
+        save(&dir, "d", 5);
+        assert_eq!(listed(&dir), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn keeps_only_the_newest_backups() {
+        let dir = TestDir::new("backup-prune");
+        ["a", "b", "c", "d", "e"]
+            .into_iter()
+            .for_each(|contents| save(&dir, contents, 2));
+        assert_eq!(listed(&dir), ["d", "c"]);
+
+        save(&dir, "f", 0);
+        assert_eq!(listed(&dir), ["d", "c"]);
+    }
+
+    #[test]
+    fn ignores_other_files_in_the_backups_dir() {
+        let dir = TestDir::new("backup-list");
+        save(&dir, "a", 5);
+        save(&dir, "b", 5);
+
+        let backups_dir = backups_dir(&dir.0);
+        fs::write(backups_dir.join("notes.txt"), "").unwrap();
+        fs::write(backups_dir.join("config-old.txt"), "").unwrap();
+        assert_eq!(listed(&dir), ["a"]);
+    }
+}
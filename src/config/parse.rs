@@ -0,0 +1,202 @@
+//! Reading `config.toml` without trusting it.
+//!
+//! Syntax and type errors become [`ConfigError`] diagnostics pointing into the file, unknown keys
+//! are collected as warnings, and files written by older versions are migrated before they're
+//! deserialized
+
+use std::path::Path;
+
+use miette::{Diagnostic, GraphicalReportHandler, GraphicalTheme, NamedSource, SourceSpan};
+use serde::Deserialize;
+use thiserror::Error;
+use toml::{Spanned, Table, Value};
+
+use super::ConfigFile;
+
+/// Version written into new and migrated files
+pub const CONFIG_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` brings a table from version `n` to `n + 1`
+const MIGRATIONS: [fn(&mut Table); CONFIG_VERSION as usize] = [v0_to_v1];
+
+/// Unversioned files predate the `version` key, they only lack the key itself
+fn v0_to_v1(_table: &mut Table) {}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum ConfigError {
+    #[error("Failed to read {path}")]
+    #[diagnostic(code(rsfm::config::io))]
+    Io {
+        path: String,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error("Invalid config file")]
+    #[diagnostic(
+        code(rsfm::config::invalid),
+        help("Fix the file or delete it to start over with the defaults")
+    )]
+    Invalid {
+        #[source_code]
+        src: NamedSource,
+        #[label("{message}")]
+        span: Option<SourceSpan>,
+        message: String,
+    },
+
+    #[error("Config version {version} is newer than this build supports ({CONFIG_VERSION})")]
+    #[diagnostic(
+        code(rsfm::config::version),
+        help("Update rsfm or lower the version after checking the file by hand")
+    )]
+    TooNew { version: u32 },
+}
+
+impl ConfigError {
+    pub(super) fn invalid(name: &str, src: &str, err: toml::de::Error) -> Self {
+        Self::Invalid {
+            src: NamedSource::new(name, src.to_string()),
+            span: err.span().map(Into::into),
+            message: err.message().to_string(),
+        }
+    }
+
+    /// Plain text report with the offending lines, for logs and the in-app banner
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        match GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut out, self)
+        {
+            Ok(()) => out,
+            Err(_) => self.to_string(),
+        }
+    }
+}
+
+pub struct Parsed {
+    pub file: ConfigFile,
+    /// Dotted paths of keys nothing reads, most likely typos
+    pub unknown_keys: Vec<String>,
+    /// Version the file was migrated from, if it was
+    pub migrated_from: Option<u32>,
+}
+
+/// Just the version, with where it is to point at it
+#[derive(Deserialize)]
+struct Versioned {
+    version: Option<Spanned<i64>>,
+}
+
+pub fn parse(path: &Path, src: &str) -> Result<Parsed, ConfigError> {
+    let name = path.to_string_lossy();
+
+    let mut table: Table =
+        toml::from_str(src).map_err(|err| ConfigError::invalid(&name, src, err))?;
+
+    let version = match toml::from_str::<Versioned>(src) {
+        Ok(Versioned { version: None }) => 0,
+        Ok(Versioned {
+            version: Some(version),
+        }) => u32::try_from(*version.get_ref()).map_err(|_| ConfigError::Invalid {
+            src: NamedSource::new(name.as_ref(), src.to_string()),
+            span: Some(version.span().into()),
+            message: format!("Expected a version between 0 and {CONFIG_VERSION}"),
+        })?,
+        // Not an integer, let the typed pass below point at it
+        Err(_) => CONFIG_VERSION,
+    };
+    if version > CONFIG_VERSION {
+        return Err(ConfigError::TooNew { version });
+    }
+
+    let mut unknown_keys = vec![];
+    if version == CONFIG_VERSION {
+        let file = serde_ignored::deserialize(toml::Deserializer::new(src), |key| {
+            unknown_keys.push(key.to_string())
+        })
+        .map_err(|err| ConfigError::invalid(&name, src, err))?;
+
+        return Ok(Parsed {
+            file,
+            unknown_keys,
+            migrated_from: None,
+        });
+    }
+
+    MIGRATIONS[version as usize..]
+        .iter()
+        .for_each(|migrate| migrate(&mut table));
+    table.insert("version".into(), Value::Integer(CONFIG_VERSION.into()));
+
+    // The migrated table has no text of its own, errors have to point into the original
+    let file = serde_ignored::deserialize(Value::Table(table), |key| {
+        unknown_keys.push(key.to_string())
+    })
+    .map_err(|err| match toml::from_str::<ConfigFile>(src) {
+        Err(original) => ConfigError::invalid(&name, src, original),
+        // Only the migrated keys are wrong, there's nothing in the file to point at
+        Ok(_) => ConfigError::Invalid {
+            src: NamedSource::new(name.as_ref(), src.to_string()),
+            span: None,
+            message: format!("After migrating from version {version}: {}", err.message()),
+        },
+    })?;
+
+    Ok(Parsed {
+        file,
+        unknown_keys,
+        migrated_from: Some(version),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(src: &str) -> Result<Parsed, ConfigError> {
+        parse(Path::new("config.toml"), src)
+    }
+
+    fn span(err: ConfigError) -> Option<SourceSpan> {
+        match err {
+            ConfigError::Invalid { span, .. } => span,
+            _ => panic!("Expected an invalid file error"),
+        }
+    }
+
+    #[test]
+    fn migrates_unversioned_files() {
+        let parsed = parse_str("").unwrap();
+        assert_eq!(parsed.migrated_from, Some(0));
+        assert!(parsed.unknown_keys.is_empty());
+
+        let parsed = parse_str(&format!("version = {CONFIG_VERSION}")).unwrap();
+        assert_eq!(parsed.migrated_from, None);
+    }
+
+    #[test]
+    fn rejects_out_of_range_versions() {
+        let src = "version = -1";
+        assert_eq!(span(parse_str(src).err().unwrap()), Some((10, 2).into()));
+
+        let src = "version = 4294967296";
+        assert_eq!(span(parse_str(src).err().unwrap()), Some((10, 10).into()));
+
+        assert!(matches!(
+            parse_str("version = 4294967295"),
+            Err(ConfigError::TooNew { version: u32::MAX })
+        ));
+    }
+
+    #[test]
+    fn points_into_the_original_file_after_migrating() {
+        let src = "# unversioned\nbookmarks = 1\n";
+        let span = span(parse_str(src).err().unwrap()).unwrap();
+        assert_eq!(&src[span.offset()..][..span.len()], "1");
+
+        let src = "# unversioned\nunknown_key = 1\n";
+        let parsed = parse_str(src).unwrap();
+        assert_eq!(parsed.unknown_keys, ["unknown_key"]);
+    }
+}
@@ -0,0 +1,103 @@
+//! User themes, one TOML file per theme in the `themes` dir:
+//!
+//! ```toml
+//! inherits = "Mocha"
+//!
+//! [palette]
+//! base = "#2e3440"
+//! text = "#eceff4"
+//!
+//! [ext]
+//! button_hover_color = "#4c566a80"
+//! dir_color = "#88c0d0"
+//! hidden_opacity = 0.4
+//! ```
+//!
+//! Colors left out come from the inherited flavour
+
+use std::path::Path;
+
+use miette::NamedSource;
+use serde::Deserialize;
+
+use crate::utils::theme::{self, ExtColors, HexColor};
+
+use super::{parse::ConfigError, Accent, Theme};
+
+const THEMES_DIR: &str = "themes";
+
+macro_rules! palette_overrides {
+    ($($color:ident),+) => {
+        /// Colors replacing the inherited flavour's
+        #[derive(Default, Deserialize)]
+        #[serde(default, deny_unknown_fields)]
+        struct PaletteOverrides {
+            $($color: Option<HexColor>,)+
+        }
+
+        impl PaletteOverrides {
+            fn apply(&self, palette: &mut catppuccin_egui::Theme) {
+                $(
+                    if let Some(color) = self.$color {
+                        palette.$color = color.0;
+                    }
+                )+
+            }
+        }
+    };
+}
+
+palette_overrides!(
+    rosewater, flamingo, pink, mauve, red, maroon, peach, yellow, green, teal, sky, sapphire, blue,
+    lavender, text, subtext1, subtext0, overlay2, overlay1, overlay0, surface2, surface1, surface0,
+    base, mantle, crust
+);
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ThemeFile {
+    inherits: Theme,
+    palette: PaletteOverrides,
+    ext: ExtColors,
+}
+
+/// Sorted by name
+pub fn list(config_dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(config_dir.join(THEMES_DIR)) else {
+        return vec![];
+    };
+
+    let mut names = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect::<Vec<_>>();
+    names.sort();
+
+    names
+}
+
+pub fn load(config_dir: &Path, name: &str, accent: Accent) -> Result<theme::Theme, ConfigError> {
+    let path = config_dir.join(THEMES_DIR).join(format!("{name}.toml"));
+    let path_name = path.to_string_lossy().to_string();
+
+    let src = std::fs::read_to_string(&path).map_err(|err| ConfigError::Io {
+        path: path_name.clone(),
+        err,
+    })?;
+    let file: ThemeFile =
+        toml::from_str(&src).map_err(|err| ConfigError::invalid(&path_name, &src, err))?;
+
+    let mut palette = file
+        .inherits
+        .flavour()
+        .ok_or_else(|| ConfigError::Invalid {
+            src: NamedSource::new(&path_name, src.clone()),
+            span: None,
+            message: "Themes can only inherit a Catppuccin flavour".into(),
+        })?;
+    file.palette.apply(&mut palette);
+
+    Ok(theme::Theme::new(palette, accent.color(&palette)).with_ext(file.ext))
+}
@@ -0,0 +1,71 @@
+//! Noticing edits made to `config.toml` outside of rsfm
+
+use std::{
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+use eframe::egui::Context;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use super::Config;
+
+/// Editors often write a file in several steps, only the last one is worth parsing
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+pub struct ConfigWatcher {
+    ctx: Context,
+    _watcher: RecommendedWatcher,
+    events: Receiver<()>,
+    /// When the last change came in, if it wasn't reported yet
+    pending: Option<Instant>,
+}
+
+impl ConfigWatcher {
+    /// Watches the whole directory, since editors and [`Config::save`] replace the file by renaming
+    pub fn new(ctx: Context, config: &Config) -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let file_path = config.file_path.clone();
+        let repaint_ctx = ctx.clone();
+
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event)
+                    if !event.kind.is_access()
+                        && event.paths.iter().any(|path| path == &file_path) =>
+                {
+                    let _ = sender.send(());
+                    repaint_ctx.request_repaint();
+                }
+                Ok(_) => {}
+                Err(err) => tracing::error!("Failed to watch the config file: {err}"),
+            })?;
+        watcher.watch(&config.dir_path, RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            ctx,
+            _watcher: watcher,
+            events,
+            pending: None,
+        })
+    }
+
+    /// `true` once the file settled after changing
+    pub fn changed(&mut self) -> bool {
+        if self.events.try_iter().count() > 0 {
+            self.pending = Some(Instant::now());
+        }
+
+        match self.pending {
+            Some(since) if since.elapsed() >= DEBOUNCE => {
+                self.pending = None;
+                true
+            }
+            Some(since) => {
+                self.ctx.request_repaint_after(DEBOUNCE - since.elapsed());
+                false
+            }
+            None => false,
+        }
+    }
+}
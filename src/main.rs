@@ -1,6 +1,7 @@
 mod app;
 mod components;
 mod config;
+mod session;
 mod utils;
 
 use std::path::PathBuf;
@@ -51,6 +52,7 @@ fn main() -> miette::Result<()> {
         initial_window_pos: None,
         initial_window_size: Some(MIN_WINDOW_SIZE),
         min_window_size: Some(MIN_WINDOW_SIZE),
+        persist_window: true,
         max_window_size: None,
         resizable: true,
         mouse_passthrough: false,
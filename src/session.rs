@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+
+use egui_dock::DockState;
+use serde::{Deserialize, Serialize};
+
+use crate::components::dock::DockTab;
+
+pub const DOCK_STATE_KEY: &str = "dock_state";
+pub const SESSIONS_KEY: &str = "sessions";
+
+const DEFAULT_SESSION_NAME: &str = "Default";
+
+/// [`Sessions::save_as`] won't replace a saved session, see [`Sessions::replace`]
+#[derive(Debug)]
+pub struct SessionExists;
+
+/// Named dock layouts the user can switch between.
+/// The active session lives in the app itself, only the inactive ones are kept here
+#[derive(Serialize, Deserialize)]
+pub struct Sessions {
+    active: String,
+    inactive: BTreeMap<String, DockState<DockTab>>,
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self {
+            active: DEFAULT_SESSION_NAME.into(),
+            inactive: Default::default(),
+        }
+    }
+}
+
+impl Sessions {
+    pub fn active(&self) -> &String {
+        &self.active
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names = self.inactive.keys().cloned().collect::<Vec<_>>();
+        names.push(self.active.clone());
+        names.sort();
+
+        names
+    }
+
+    pub fn switch(&mut self, name: &str, dock_state: &mut DockState<DockTab>) {
+        let Some(new_dock_state) = self.inactive.remove(name) else {
+            tracing::error!("Session {name:?} doesn't exist");
+            return;
+        };
+
+        let old_dock_state = std::mem::replace(dock_state, new_dock_state);
+        let old_name = std::mem::replace(&mut self.active, name.into());
+
+        self.inactive.insert(old_name, old_dock_state);
+    }
+
+    /// Keeps a copy of the current layout under the active session name
+    /// and continues with the live layout as the new active session
+    pub fn save_as(
+        &mut self,
+        name: impl Into<String>,
+        dock_state: &DockState<DockTab>,
+    ) -> Result<(), SessionExists> {
+        let name = name.into();
+
+        if self.inactive.contains_key(&name) {
+            return Err(SessionExists);
+        }
+
+        self.replace(name, dock_state);
+        Ok(())
+    }
+
+    /// [`Self::save_as`], dropping a saved session with the same name
+    pub fn replace(&mut self, name: impl Into<String>, dock_state: &DockState<DockTab>) {
+        let name = name.into();
+
+        if name == self.active {
+            return;
+        }
+
+        let old_name = std::mem::replace(&mut self.active, name);
+        self.inactive.remove(&self.active);
+        self.inactive.insert(old_name, dock_state.clone());
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        if self.inactive.remove(name).is_none() {
+            tracing::error!("Can't remove session {name:?}, it's either active or doesn't exist");
+        }
+    }
+}
@@ -1,7 +1,23 @@
+pub mod archive;
+pub mod batch_rename;
+pub mod bookmarks;
+pub mod color_scheme;
+pub mod devices;
 pub mod fs;
+pub mod hidden;
 pub mod icons;
+pub mod jobs;
+pub mod journal;
+pub mod keymap;
+pub mod ls_colors;
+pub mod mounts;
+pub mod network;
 pub mod paths;
+pub mod sorting;
 pub mod theme;
+pub mod thumbnails;
+pub mod trash;
+pub mod vfs;
 
 #[macro_export]
 macro_rules! struct_with_funcs {
@@ -0,0 +1,592 @@
+//! Read-only access to archive members, so archives can be browsed like directories.
+//!
+//! Members are addressed by paths that continue past the archive file itself,
+//! e.g. `/home/user/photos.zip/2023/beach.jpg`. Nothing is extracted to list an archive,
+//! compressed tarballs are decompressed as a stream every time they are read.
+//! Listings come from an index of the archive that's kept until the archive changes
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fs::{self, File},
+    io::{self, BufReader, ErrorKind, Read},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use lazy_static::lazy_static;
+
+use super::{
+    fs::unique_path,
+    jobs::{JobProgress, ProgressReader},
+};
+
+mod compress;
+
+pub use compress::{compress, CompressOptions};
+
+/// How many archive indices are kept around
+const INDEX_CACHE_SIZE: usize = 8;
+
+lazy_static! {
+    /// Most recently used last
+    static ref INDICES: Mutex<VecDeque<(PathBuf, Arc<ArchiveIndex>)>> = Mutex::new(VecDeque::new());
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+    SevenZ,
+}
+
+const EXTENSIONS: &[(&str, ArchiveKind)] = &[
+    (".zip", ArchiveKind::Zip),
+    (".tar", ArchiveKind::Tar),
+    (".tar.gz", ArchiveKind::TarGz),
+    (".tgz", ArchiveKind::TarGz),
+    (".tar.xz", ArchiveKind::TarXz),
+    (".txz", ArchiveKind::TarXz),
+    (".tar.zst", ArchiveKind::TarZst),
+    (".tzst", ArchiveKind::TarZst),
+    (".7z", ArchiveKind::SevenZ),
+];
+
+impl ArchiveKind {
+    /// Formats new archives can be created in
+    pub const COMPRESSIBLE: [ArchiveKind; 5] = [
+        ArchiveKind::Zip,
+        ArchiveKind::TarGz,
+        ArchiveKind::TarXz,
+        ArchiveKind::TarZst,
+        ArchiveKind::SevenZ,
+    ];
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+
+        EXTENSIONS
+            .iter()
+            .find(|(ext, _)| name.ends_with(ext))
+            .map(|(_, kind)| *kind)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveKind::Zip => ".zip",
+            ArchiveKind::Tar => ".tar",
+            ArchiveKind::TarGz => ".tar.gz",
+            ArchiveKind::TarXz => ".tar.xz",
+            ArchiveKind::TarZst => ".tar.zst",
+            ArchiveKind::SevenZ => ".7z",
+        }
+    }
+
+    pub fn supports_password(&self) -> bool {
+        matches!(self, ArchiveKind::Zip | ArchiveKind::SevenZ)
+    }
+}
+
+/// File name of the archive without its archive extension, e.g. `photos` for `photos.tar.gz`
+pub fn archive_stem(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string();
+    let lowercase = name.to_lowercase();
+
+    EXTENSIONS
+        .iter()
+        .find(|(ext, _)| lowercase.ends_with(ext))
+        .map(|(ext, _)| name[..name.len() - ext.len()].to_string())
+        .unwrap_or(name)
+}
+
+/// A path split into the archive file on disk and the member path inside of it
+#[derive(Clone, PartialEq, Eq)]
+pub struct ArchivePath {
+    pub archive: PathBuf,
+    pub kind: ArchiveKind,
+    /// Relative to the archive root, empty for the root itself
+    pub inner: PathBuf,
+}
+
+impl ArchivePath {
+    /// Finds the archive file among `path` and its ancestors. Nested archives aren't supported
+    pub fn parse(path: &Path) -> Option<Self> {
+        path.ancestors().find_map(|ancestor| {
+            let kind = ArchiveKind::from_path(ancestor)?;
+            if !ancestor.is_file() {
+                return None;
+            }
+
+            Some(Self {
+                archive: ancestor.to_path_buf(),
+                kind,
+                inner: path.strip_prefix(ancestor).ok()?.to_path_buf(),
+            })
+        })
+    }
+
+    /// `true` for members, `false` for the archive file itself
+    pub fn is_member(&self) -> bool {
+        !self.inner.as_os_str().is_empty()
+    }
+}
+
+#[derive(Clone)]
+pub struct ArchiveEntry {
+    /// Relative to the archive root
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl ArchiveEntry {
+    /// Directories are often only implied by the paths of their members
+    fn implicit_dir(path: PathBuf) -> Self {
+        Self {
+            path,
+            is_dir: true,
+            size: 0,
+            modified: None,
+        }
+    }
+}
+
+/// Every entry of an archive by path, implied directories included.
+/// Valid as long as the archive's size and modification time stay the same
+struct ArchiveIndex {
+    len: u64,
+    modified: Option<SystemTime>,
+    entries: BTreeMap<PathBuf, ArchiveEntry>,
+}
+
+impl ArchiveIndex {
+    fn build(
+        archive_path: &ArchivePath,
+        len: u64,
+        modified: Option<SystemTime>,
+    ) -> io::Result<Self> {
+        let mut entries = BTreeMap::new();
+
+        for_each_entry(archive_path, false, None, |entry, _| {
+            entry
+                .path
+                .ancestors()
+                .skip(1)
+                .filter(|ancestor| !ancestor.as_os_str().is_empty())
+                .for_each(|ancestor| {
+                    entries
+                        .entry(ancestor.to_path_buf())
+                        .or_insert_with(|| ArchiveEntry::implicit_dir(ancestor.to_path_buf()));
+                });
+            entries.insert(entry.path.clone(), entry);
+
+            Ok(())
+        })?;
+
+        Ok(Self {
+            len,
+            modified,
+            entries,
+        })
+    }
+}
+
+/// The cached index of the archive, re-read if the archive changed since
+fn index(archive_path: &ArchivePath) -> io::Result<Arc<ArchiveIndex>> {
+    let metadata = fs::metadata(&archive_path.archive)?;
+    let (len, modified) = (metadata.len(), metadata.modified().ok());
+
+    let cached = {
+        let mut indices = INDICES.lock().expect("Archive indices poisoned");
+        let position = indices
+            .iter()
+            .position(|(archive, _)| archive == &archive_path.archive);
+        position.and_then(|i| indices.remove(i))
+    };
+
+    let index = match cached {
+        Some((_, index)) if index.len == len && index.modified == modified => index,
+        _ => Arc::new(ArchiveIndex::build(archive_path, len, modified)?),
+    };
+
+    let mut indices = INDICES.lock().expect("Archive indices poisoned");
+    indices.retain(|(archive, _)| archive != &archive_path.archive);
+    indices.push_back((archive_path.archive.clone(), index.clone()));
+    if indices.len() > INDEX_CACHE_SIZE {
+        indices.pop_front();
+    }
+
+    Ok(index)
+}
+
+/// Direct children of the `inner` directory of the archive
+pub fn list(archive_path: &ArchivePath) -> io::Result<Vec<ArchiveEntry>> {
+    let dir = archive_path.inner.as_path();
+
+    Ok(index(archive_path)?
+        .entries
+        .values()
+        .filter(|entry| entry.path.parent() == Some(dir))
+        .cloned()
+        .collect())
+}
+
+/// Looks a single member up, implied directories included
+pub fn stat(archive_path: &ArchivePath) -> io::Result<ArchiveEntry> {
+    let index = index(archive_path)?;
+
+    let found = match archive_path.is_member() {
+        true => index.entries.get(&archive_path.inner).cloned(),
+        false => (!index.entries.is_empty())
+            .then(|| ArchiveEntry::implicit_dir(archive_path.inner.clone())),
+    };
+
+    found.ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "{:?} not found in {:?}",
+                archive_path.inner, archive_path.archive
+            ),
+        )
+    })
+}
+
+/// Contents of a single file member
+pub fn read(archive_path: &ArchivePath) -> io::Result<Vec<u8>> {
+    let mut contents = None;
+
+    for_each_entry(archive_path, true, None, |entry, reader| {
+        if contents.is_none() && !entry.is_dir && entry.path == archive_path.inner {
+            let mut buf = Vec::with_capacity(entry.size as usize);
+            reader.read_to_end(&mut buf)?;
+            contents = Some(buf);
+        }
+
+        Ok(())
+    })?;
+
+    contents.ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "{:?} not found in {:?}",
+                archive_path.inner, archive_path.archive
+            ),
+        )
+    })
+}
+
+/// Extracts the selected members, and everything below the directories among them, into `dest`.
+/// Every member ends up right inside `dest` under its own name, like a regular copy would
+pub fn extract(
+    archive: &Path,
+    members: &[PathBuf],
+    dest: &Path,
+    progress: &JobProgress,
+) -> io::Result<()> {
+    let archive_path = parse_archive(archive)?;
+
+    extract_with(&archive_path, dest, None, progress, |path| {
+        members.iter().find_map(|member| {
+            let rest = path.strip_prefix(member).ok()?;
+            Some(Path::new(member.file_name()?).join(rest))
+        })
+    })
+}
+
+/// Extracts the whole archive into `dest_dir`. Archives with a single top level entry are
+/// extracted as is, everything else is wrapped in a new directory named after the archive,
+/// so the files don't end up scattered around `dest_dir`. Returns the extracted root
+pub fn extract_all(
+    archive: &Path,
+    dest_dir: &Path,
+    password: Option<&str>,
+    progress: &JobProgress,
+) -> io::Result<PathBuf> {
+    let archive_path = parse_archive(archive)?;
+
+    let mut roots = BTreeSet::new();
+    let mut total = 0;
+    for_each_entry(&archive_path, false, None, |entry, _| {
+        roots.extend(
+            entry
+                .path
+                .components()
+                .next()
+                .map(|root| root.as_os_str().to_owned()),
+        );
+        total += entry.size;
+        Ok(())
+    })?;
+    progress.set_total(total);
+
+    let single_root = match roots.len() {
+        1 => roots
+            .first()
+            .map(|root| dest_dir.join(root))
+            .filter(|root| root.symlink_metadata().is_err()),
+        _ => None,
+    };
+
+    let (dest, root) = match single_root {
+        Some(root) => (dest_dir.to_path_buf(), root),
+        None => {
+            let wrapper = unique_path(&dest_dir.join(archive_stem(archive)));
+            fs::create_dir(&wrapper)?;
+            (wrapper.clone(), wrapper)
+        }
+    };
+
+    let res = extract_with(&archive_path, &dest, password, progress, |path| {
+        Some(path.to_path_buf())
+    });
+    if res.is_err() && root.exists() {
+        // Don't leave half of the archive behind
+        if let Err(err) = super::fs::remove_path(&root) {
+            tracing::error!("Failed to clean up {root:?}: {err}");
+        }
+    }
+
+    res.map(|_| root)
+}
+
+fn parse_archive(archive: &Path) -> io::Result<ArchivePath> {
+    ArchivePath::parse(archive).ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("{archive:?} isn't an archive"),
+        )
+    })
+}
+
+/// Extracts every entry `target` maps to a path relative to `dest`, skipping the rest
+fn extract_with(
+    archive_path: &ArchivePath,
+    dest: &Path,
+    password: Option<&str>,
+    progress: &JobProgress,
+    target: impl Fn(&Path) -> Option<PathBuf>,
+) -> io::Result<()> {
+    for_each_entry(archive_path, true, password, |entry, reader| {
+        progress.check_cancelled()?;
+
+        let Some(to) = target(&entry.path).map(|rel| dest.join(rel)) else {
+            return Ok(());
+        };
+
+        match entry.is_dir {
+            true => fs::create_dir_all(&to),
+            false => {
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                if to.symlink_metadata().is_ok() {
+                    return Err(io::Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("{to:?} already exists"),
+                    ));
+                }
+
+                let mut reader = ProgressReader::new(reader, progress);
+                io::copy(&mut reader, &mut File::create(&to)?).map(|_| ())
+            }
+        }
+    })
+}
+
+/// Extracts a single member into the cache, so it can be opened with another application
+pub fn extract_for_preview(archive_path: &ArchivePath) -> io::Result<PathBuf> {
+    let cache_dir = dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("rsfm")
+        .join("archive-preview");
+    let dest = cache_dir.join(archive_path.archive.file_name().unwrap_or_default());
+    let to = dest.join(archive_path.inner.file_name().unwrap_or_default());
+
+    if to.exists() {
+        super::fs::remove_path(&to)?;
+    }
+    fs::create_dir_all(&dest)?;
+
+    extract(
+        &archive_path.archive,
+        std::slice::from_ref(&archive_path.inner),
+        &dest,
+        &JobProgress::default(),
+    )?;
+
+    Ok(to)
+}
+
+/// Walks every entry of the archive in order, handing out a reader for its contents.
+/// Only regular files and directories are visited.
+/// Without `contents` the reader may yield raw compressed data, but encrypted members can be listed
+fn for_each_entry(
+    archive_path: &ArchivePath,
+    contents: bool,
+    password: Option<&str>,
+    mut f: impl FnMut(ArchiveEntry, &mut dyn Read) -> io::Result<()>,
+) -> io::Result<()> {
+    if archive_path.kind == ArchiveKind::SevenZ {
+        return for_each_7z_entry(&archive_path.archive, contents, password, f);
+    }
+
+    let file = BufReader::new(File::open(&archive_path.archive)?);
+
+    let reader: Box<dyn Read> = match archive_path.kind {
+        ArchiveKind::Zip => return for_each_zip_entry(file, contents, password, f),
+        ArchiveKind::Tar => Box::new(file),
+        ArchiveKind::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+        ArchiveKind::TarXz => Box::new(xz2::read::XzDecoder::new(file)),
+        ArchiveKind::TarZst => Box::new(zstd::stream::read::Decoder::with_buffer(file)?),
+        ArchiveKind::SevenZ => unreachable!(),
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let Some(path) = sanitize(&entry.path()?) else {
+            continue;
+        };
+
+        let header = entry.header();
+        let entry_type = header.entry_type();
+        if !entry_type.is_file() && !entry_type.is_dir() {
+            continue;
+        }
+
+        let archive_entry = ArchiveEntry {
+            path,
+            is_dir: entry_type.is_dir(),
+            size: header.size().unwrap_or_default(),
+            modified: header
+                .mtime()
+                .ok()
+                .map(|mtime| SystemTime::UNIX_EPOCH + Duration::from_secs(mtime)),
+        };
+
+        f(archive_entry, &mut entry)?;
+    }
+
+    Ok(())
+}
+
+fn for_each_zip_entry(
+    file: BufReader<File>,
+    contents: bool,
+    password: Option<&str>,
+    mut f: impl FnMut(ArchiveEntry, &mut dyn Read) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(file)?;
+
+    for i in 0..archive.len() {
+        let mut entry = match (contents, password) {
+            (false, _) => archive.by_index_raw(i)?,
+            (true, Some(password)) => archive.by_index_decrypt(i, password.as_bytes())?,
+            (true, None) => archive.by_index(i)?,
+        };
+        let Some(path) = entry.enclosed_name().and_then(|path| sanitize(&path)) else {
+            continue;
+        };
+        if entry.is_symlink() {
+            continue;
+        }
+
+        let archive_entry = ArchiveEntry {
+            path,
+            is_dir: entry.is_dir(),
+            size: entry.size(),
+            modified: entry.last_modified().and_then(zip_time),
+        };
+
+        f(archive_entry, &mut entry)?;
+    }
+
+    Ok(())
+}
+
+fn for_each_7z_entry(
+    archive: &Path,
+    contents: bool,
+    password: Option<&str>,
+    mut f: impl FnMut(ArchiveEntry, &mut dyn Read) -> io::Result<()>,
+) -> io::Result<()> {
+    let password = password.map_or_else(sevenz_rust::Password::empty, sevenz_rust::Password::from);
+    let mut reader =
+        sevenz_rust::SevenZReader::open(archive, password).map_err(io::Error::other)?;
+
+    let to_archive_entry = |entry: &sevenz_rust::SevenZArchiveEntry| {
+        Some(ArchiveEntry {
+            path: sanitize(Path::new(entry.name()))?,
+            is_dir: entry.is_directory(),
+            size: entry.size(),
+            modified: None,
+        })
+    };
+
+    if !contents {
+        return reader
+            .archive()
+            .files
+            .iter()
+            .filter_map(to_archive_entry)
+            .try_for_each(|entry| f(entry, &mut io::empty()));
+    }
+
+    // The closure has to return the crate's own error, keep ours aside and stop the walk instead
+    let mut res = Ok(());
+    reader
+        .for_each_entries(|entry, reader| {
+            if let Some(entry) = to_archive_entry(entry) {
+                res = f(entry, reader);
+            }
+            Ok(res.is_ok())
+        })
+        .map_err(io::Error::other)?;
+
+    res
+}
+
+/// Drops `./` and rejects anything that could escape the destination when extracting
+fn sanitize(path: &Path) -> Option<PathBuf> {
+    let mut res = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => res.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    (!res.as_os_str().is_empty()).then_some(res)
+}
+
+/// Zip timestamps are local MS-DOS dates, treated as UTC here
+fn zip_time(time: zip::DateTime) -> Option<SystemTime> {
+    let (year, month, day) = (time.year() as i64, time.month() as i64, time.day() as i64);
+
+    // Days since the epoch for a proleptic Gregorian date, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs =
+        days * 86400 + time.hour() as i64 * 3600 + time.minute() as i64 * 60 + time.second() as i64;
+
+    u64::try_from(secs)
+        .ok()
+        .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
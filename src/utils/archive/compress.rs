@@ -0,0 +1,260 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind, Write},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+
+use walkdir::WalkDir;
+
+use crate::utils::jobs::{JobProgress, ProgressReader};
+
+use super::ArchiveKind;
+
+#[derive(Clone)]
+pub struct CompressOptions {
+    pub kind: ArchiveKind,
+    /// From 0 (store/fastest) to 9 (smallest), scaled to what the format supports
+    pub level: u32,
+    /// Only used by formats that [support it](ArchiveKind::supports_password)
+    pub password: Option<String>,
+}
+
+impl Default for CompressOptions {
+    fn default() -> Self {
+        Self {
+            kind: ArchiveKind::Zip,
+            level: 6,
+            password: None,
+        }
+    }
+}
+
+/// Packs `sources` with everything below them into a new archive at `dest`.
+/// Every source ends up at the archive's root. A partially written archive is removed on failure
+pub fn compress(
+    sources: &[PathBuf],
+    dest: &Path,
+    options: &CompressOptions,
+    progress: &JobProgress,
+) -> io::Result<()> {
+    if dest.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{dest:?} already exists"),
+        ));
+    }
+
+    let entries = collect_entries(sources)?;
+    progress.set_total(
+        entries
+            .iter()
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.meta.len())
+            .sum(),
+    );
+
+    let res = write_archive(&entries, dest, options, progress);
+    if res.is_err() && dest.exists() {
+        if let Err(err) = fs::remove_file(dest) {
+            tracing::error!("Failed to clean up {dest:?}: {err}");
+        }
+    }
+
+    res
+}
+
+/// An entry on disk and its path inside the archive
+struct Entry {
+    path: PathBuf,
+    name: PathBuf,
+    meta: fs::Metadata,
+}
+
+impl Entry {
+    fn file_type(&self) -> fs::FileType {
+        self.meta.file_type()
+    }
+
+    /// Archives always use forward slashes
+    fn name_str(&self) -> String {
+        self.name.to_string_lossy().to_string()
+    }
+}
+
+fn collect_entries(sources: &[PathBuf]) -> io::Result<Vec<Entry>> {
+    let mut entries = vec![];
+
+    for source in sources {
+        let root = source.parent().unwrap_or(Path::new(""));
+
+        for entry in WalkDir::new(source).follow_links(false) {
+            let entry = entry?;
+
+            entries.push(Entry {
+                path: entry.path().to_path_buf(),
+                name: entry
+                    .path()
+                    .strip_prefix(root)
+                    .unwrap_or(entry.path())
+                    .to_path_buf(),
+                meta: entry.metadata()?,
+            });
+        }
+    }
+
+    Ok(entries)
+}
+
+fn write_archive(
+    entries: &[Entry],
+    dest: &Path,
+    options: &CompressOptions,
+    progress: &JobProgress,
+) -> io::Result<()> {
+    let level = options.level.min(9);
+
+    if options.kind == ArchiveKind::SevenZ {
+        return write_7z(entries, dest, level, options.password.as_deref(), progress);
+    }
+
+    let file = BufWriter::new(File::create(dest)?);
+
+    match options.kind {
+        ArchiveKind::Zip => write_zip(file, entries, level, options.password.as_deref(), progress),
+        ArchiveKind::Tar => write_tar(file, entries, progress)?.flush(),
+        ArchiveKind::TarGz => {
+            let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::new(level));
+            write_tar(encoder, entries, progress)?.finish()?.flush()
+        }
+        ArchiveKind::TarXz => {
+            let encoder = xz2::write::XzEncoder::new(file, level);
+            write_tar(encoder, entries, progress)?.finish()?.flush()
+        }
+        ArchiveKind::TarZst => {
+            // zstd goes up to 19 without the ultra levels
+            let encoder = zstd::stream::write::Encoder::new(file, (level * 2 + 1) as i32)?;
+            write_tar(encoder, entries, progress)?.finish()?.flush()
+        }
+        ArchiveKind::SevenZ => unreachable!(),
+    }
+}
+
+fn write_tar<W: Write>(writer: W, entries: &[Entry], progress: &JobProgress) -> io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+
+    for entry in entries {
+        progress.check_cancelled()?;
+
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&entry.meta);
+
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            builder.append_data(&mut header, &entry.name, io::empty())?;
+        } else if file_type.is_symlink() {
+            builder.append_link(&mut header, &entry.name, fs::read_link(&entry.path)?)?;
+        } else if file_type.is_file() {
+            let file = ProgressReader::new(File::open(&entry.path)?, progress);
+            builder.append_data(&mut header, &entry.name, file)?;
+        }
+    }
+
+    builder.into_inner()
+}
+
+fn write_zip(
+    writer: BufWriter<File>,
+    entries: &[Entry],
+    level: u32,
+    password: Option<&str>,
+    progress: &JobProgress,
+) -> io::Result<()> {
+    let mut zip = zip::ZipWriter::new(writer);
+
+    let file_options = |entry: &Entry| {
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(match level {
+                0 => zip::CompressionMethod::Stored,
+                _ => zip::CompressionMethod::Deflated,
+            })
+            .compression_level((level > 0).then_some(level as i64))
+            .unix_permissions(entry.meta.permissions().mode());
+
+        match password {
+            Some(password) => options.with_aes_encryption(zip::AesMode::Aes256, password),
+            None => options,
+        }
+    };
+
+    for entry in entries {
+        progress.check_cancelled()?;
+
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            zip.add_directory(entry.name_str(), file_options(entry))?;
+        } else if file_type.is_symlink() {
+            let target = fs::read_link(&entry.path)?;
+            zip.add_symlink(
+                entry.name_str(),
+                target.to_string_lossy(),
+                file_options(entry),
+            )?;
+        } else if file_type.is_file() {
+            zip.start_file(entry.name_str(), file_options(entry))?;
+            io::copy(
+                &mut ProgressReader::new(File::open(&entry.path)?, progress),
+                &mut zip,
+            )?;
+        }
+    }
+
+    zip.finish()?.flush()
+}
+
+fn write_7z(
+    entries: &[Entry],
+    dest: &Path,
+    level: u32,
+    password: Option<&str>,
+    progress: &JobProgress,
+) -> io::Result<()> {
+    use sevenz_rust::{
+        lzma::LZMA2Options, AesEncoderOptions, SevenZArchiveEntry, SevenZMethod,
+        SevenZMethodConfiguration, SevenZWriter,
+    };
+
+    let mut writer = SevenZWriter::create(dest).map_err(io::Error::other)?;
+
+    let mut methods = vec![];
+    if let Some(password) = password {
+        methods.push(AesEncoderOptions::new(password.into()).into());
+    }
+    methods.push(
+        SevenZMethodConfiguration::new(SevenZMethod::LZMA2)
+            .with_options(LZMA2Options::with_preset(level).into()),
+    );
+    writer.set_content_methods(methods);
+
+    for entry in entries {
+        progress.check_cancelled()?;
+
+        let archive_entry = SevenZArchiveEntry::from_path(&entry.path, entry.name_str());
+
+        let file_type = entry.file_type();
+        let res = if file_type.is_dir() {
+            writer.push_archive_entry::<File>(archive_entry, None)
+        } else if file_type.is_file() {
+            let file = ProgressReader::new(File::open(&entry.path)?, progress);
+            writer.push_archive_entry(archive_entry, Some(file))
+        } else {
+            // 7z has no portable way of storing symlinks
+            continue;
+        };
+        res.map_err(io::Error::other)?;
+    }
+
+    writer.finish().map_err(io::Error::other)?;
+    Ok(())
+}
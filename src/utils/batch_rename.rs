@@ -0,0 +1,630 @@
+//! Renaming many files at once: find/replace, a name template with counters and dates,
+//! and a case change, checked for collisions before anything is touched
+
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Cursor, Read},
+};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local, NaiveDate, NaiveDateTime,
+};
+use exif::{In, Tag};
+use regex::Regex;
+
+use super::{
+    fs::{check_file_name, FileData},
+    jobs::JobProgress,
+    journal::Operation,
+    vfs::{self, Location},
+};
+
+/// EXIF sits near the start of photos, only this much of each file is read
+const EXIF_READ_LIMIT: u64 = 256 * 1024;
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum Case {
+    #[default]
+    Keep,
+    Lower,
+    Upper,
+    Title,
+}
+
+impl Case {
+    pub const ALL: [Self; 4] = [Self::Keep, Self::Lower, Self::Upper, Self::Title];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Case::Keep => "Keep",
+            Case::Lower => "lowercase",
+            Case::Upper => "UPPERCASE",
+            Case::Title => "Title Case",
+        }
+    }
+
+    fn apply(self, name: &str) -> String {
+        match self {
+            Case::Keep => name.to_string(),
+            Case::Lower => name.to_lowercase(),
+            Case::Upper => name.to_uppercase(),
+            Case::Title => {
+                let mut word_start = true;
+                name.chars()
+                    .flat_map(|c| {
+                        let res = match word_start {
+                            true => c.to_uppercase().collect::<Vec<_>>(),
+                            false => c.to_lowercase().collect(),
+                        };
+                        word_start = !c.is_alphanumeric();
+                        res
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Where `{date}` comes from
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum DateSource {
+    #[default]
+    Modified,
+    /// When the photo was taken, files without EXIF fall back to when they were modified
+    Exif,
+}
+
+impl DateSource {
+    pub const ALL: [Self; 2] = [Self::Modified, Self::Exif];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DateSource::Modified => "Modified",
+            DateSource::Exif => "EXIF",
+        }
+    }
+
+    /// Reads the file for `Exif`, so it's worth caching
+    pub fn date(self, file_data: &FileData) -> Option<NaiveDateTime> {
+        let modified = || {
+            file_data
+                .modified()
+                .map(|modified| DateTime::<Local>::from(modified).naive_local())
+        };
+
+        match self {
+            DateSource::Modified => modified(),
+            DateSource::Exif => exif_date(file_data).or_else(modified),
+        }
+    }
+}
+
+fn exif_date(file_data: &FileData) -> Option<NaiveDateTime> {
+    if file_data.is_dir() {
+        return None;
+    }
+
+    let mut buf = vec![];
+    file_data
+        .read()
+        .ok()?
+        .take(EXIF_READ_LIMIT)
+        .read_to_end(&mut buf)
+        .ok()?;
+
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(buf))
+        .ok()?;
+    let field = exif
+        .get_field(Tag::DateTimeOriginal, In::PRIMARY)
+        .or_else(|| exif.get_field(Tag::DateTime, In::PRIMARY))?;
+    let exif::Value::Ascii(ascii) = &field.value else {
+        return None;
+    };
+    let date = exif::DateTime::from_ascii(ascii.first()?).ok()?;
+
+    NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())?.and_hms_opt(
+        date.hour.into(),
+        date.minute.into(),
+        date.second.into(),
+    )
+}
+
+#[derive(Clone)]
+pub struct RenameRules {
+    pub find: String,
+    pub replace: String,
+    /// `replace` can refer to capture groups as `$1` or `${name}`
+    pub regex: bool,
+    /// `{name}` is the old name without its extension after find/replace, `{ext}` the
+    /// extension with its dot, `{n}` the counter and `{date}` or `{date:%Y%m%d}` the date
+    pub template: String,
+    pub counter_start: u32,
+    pub counter_step: u32,
+    /// Counters are zero-padded to this many digits
+    pub counter_padding: usize,
+    /// Applies to the whole new name
+    pub case: Case,
+    pub date_source: DateSource,
+}
+
+impl Default for RenameRules {
+    fn default() -> Self {
+        Self {
+            find: String::new(),
+            replace: String::new(),
+            regex: false,
+            template: "{name}{ext}".into(),
+            counter_start: 1,
+            counter_step: 1,
+            counter_padding: 3,
+            case: Case::Keep,
+            date_source: DateSource::Modified,
+        }
+    }
+}
+
+/// A piece of the parsed template
+enum Part {
+    Text(String),
+    Name,
+    Ext,
+    Counter,
+    Date(String),
+}
+
+/// A new name for one file of the batch
+pub struct PlannedRename {
+    pub from: Location,
+    pub to: Location,
+    pub old_name: String,
+    pub new_name: String,
+    /// Why the file can't get its new name, nothing is renamed while any has one
+    pub problem: Option<String>,
+}
+
+impl PlannedRename {
+    pub fn changed(&self) -> bool {
+        self.old_name != self.new_name
+    }
+}
+
+impl RenameRules {
+    /// Dates are only read when the template has them
+    pub fn uses_dates(&self) -> bool {
+        self.template.contains("{date")
+    }
+
+    /// New names for `files`, in their order for the counter. `dates` lines up with `files`
+    /// and `existing` names everything in their directory, hidden files included. Names the
+    /// batch renames away are free to take, as [`apply`] goes through temporary names.
+    /// `Err` when the regex or the template is invalid
+    pub fn plan(
+        &self,
+        files: &[FileData],
+        dates: &[Option<NaiveDateTime>],
+        existing: &HashSet<String>,
+    ) -> Result<Vec<PlannedRename>, String> {
+        let regex = match self.regex && !self.find.is_empty() {
+            true => Some(Regex::new(&self.find).map_err(|err| err.to_string())?),
+            false => None,
+        };
+        let template = parse_template(&self.template)?;
+
+        let mut plan = files
+            .iter()
+            .enumerate()
+            .map(|(i, file_data)| {
+                let old_name = file_data.name().clone();
+                let (stem, ext) = split_ext(&old_name, file_data.is_dir());
+
+                let stem = match &regex {
+                    Some(regex) => regex.replace_all(stem, self.replace.as_str()).to_string(),
+                    None if self.find.is_empty() => stem.to_string(),
+                    None => stem.replace(&self.find, &self.replace),
+                };
+                let counter = self.counter_start as u64 + i as u64 * self.counter_step as u64;
+                let date = dates.get(i).copied().flatten();
+
+                let new_name = template
+                    .iter()
+                    .map(|part| match part {
+                        Part::Text(text) => text.clone(),
+                        Part::Name => stem.clone(),
+                        Part::Ext => ext.to_string(),
+                        Part::Counter => format!("{counter:0width$}", width = self.counter_padding),
+                        Part::Date(format) => date
+                            .map(|date| date.format(format).to_string())
+                            .unwrap_or_default(),
+                    })
+                    .collect::<String>();
+                let new_name = self.case.apply(&new_name);
+
+                PlannedRename {
+                    from: file_data.location().clone(),
+                    to: Location::new(
+                        file_data.location().vfs(),
+                        file_data.path().with_file_name(&new_name),
+                    ),
+                    old_name,
+                    new_name,
+                    problem: None,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut counts = HashMap::<String, usize>::new();
+        plan.iter()
+            .for_each(|rename| *counts.entry(rename.new_name.clone()).or_default() += 1);
+        let freed = plan
+            .iter()
+            .filter(|rename| rename.changed())
+            .map(|rename| rename.old_name.clone())
+            .collect::<HashSet<_>>();
+
+        plan.iter_mut()
+            .filter(|rename| rename.changed())
+            .for_each(|rename| {
+                rename.problem = check_file_name(&rename.new_name).err().or_else(|| {
+                    match (
+                        counts[&rename.new_name] > 1,
+                        existing.contains(&rename.new_name) && !freed.contains(&rename.new_name),
+                    ) {
+                        (true, _) => Some("Another file in the batch gets this name".into()),
+                        (false, true) => Some(format!("{} already exists", rename.new_name)),
+                        (false, false) => None,
+                    }
+                });
+            });
+
+        Ok(plan)
+    }
+}
+
+/// Renames the files that change as one operation for the journal. Each goes to a temporary
+/// name first, so files can trade names. When a step fails the finished ones are undone, the
+/// error lists any that couldn't be
+pub fn apply(plan: &[PlannedRename]) -> io::Result<Operation> {
+    let renames = plan
+        .iter()
+        .filter(|rename| rename.changed())
+        .collect::<Vec<_>>();
+    let temps = renames
+        .iter()
+        .enumerate()
+        .map(|(i, rename)| {
+            let temp_name = format!(".rsfm-rename-{}-{i}", std::process::id());
+            Location::new(
+                rename.from.vfs(),
+                rename.from.path().with_file_name(temp_name),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let steps = renames
+        .iter()
+        .zip(&temps)
+        .map(|(rename, temp)| (&rename.from, temp))
+        .chain(
+            temps
+                .iter()
+                .zip(&renames)
+                .map(|(temp, rename)| (temp, &rename.to)),
+        );
+
+    let mut done = vec![];
+    for (from, to) in steps {
+        if let Err(err) = vfs::move_to(from, to, &JobProgress::default()) {
+            return Err(roll_back(done, err));
+        }
+
+        done.push(Operation::moved(from.clone(), to.clone()));
+    }
+
+    Ok(Operation::batch(
+        format!("Rename {} files", renames.len()),
+        done,
+    ))
+}
+
+/// Undoes the finished steps, last first, carrying on past the ones that fail
+fn roll_back(done: Vec<Operation>, err: io::Error) -> io::Error {
+    let left = done
+        .into_iter()
+        .rev()
+        .filter_map(|mut operation| {
+            let undo_err = operation.undo().err()?;
+            Some(format!("{} ({undo_err})", operation.describe()))
+        })
+        .collect::<Vec<_>>();
+
+    let message = match left.is_empty() {
+        true => format!("{err}. Nothing was changed"),
+        false => format!(
+            "{err}. These couldn't be undone and stay renamed: {}",
+            left.join(", ")
+        ),
+    };
+    io::Error::new(err.kind(), message)
+}
+
+/// Directories and dotfiles without another dot have no extension
+fn split_ext(name: &str, is_dir: bool) -> (&str, &str) {
+    match name.rfind('.').filter(|&dot| dot > 0 && !is_dir) {
+        Some(dot) => name.split_at(dot),
+        None => (name, ""),
+    }
+}
+
+fn parse_template(template: &str) -> Result<Vec<Part>, String> {
+    let mut parts = vec![];
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(Part::Text(rest[..start].to_string()));
+        }
+
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| format!("Unclosed \"{{\" in {template:?}"))?;
+        let token = &rest[start + 1..end];
+
+        parts.push(match token.split_once(':') {
+            None if token == "name" => Part::Name,
+            None if token == "ext" => Part::Ext,
+            None if token == "n" => Part::Counter,
+            None if token == "date" => Part::Date(DEFAULT_DATE_FORMAT.into()),
+            Some(("date", format)) => {
+                if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                    return Err(format!("{format:?} isn't a valid date format"));
+                }
+                Part::Date(format.into())
+            }
+            _ => return Err(format!("Unknown token {{{token}}}")),
+        });
+
+        rest = &rest[end + 1..];
+    }
+
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest.to_string()));
+    }
+
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, path::Path, sync::Arc};
+
+    use crate::utils::vfs::{MemoryVfs, Vfs, VfsEntry, VfsFileType, VfsMetadata};
+
+    use super::*;
+
+    fn file(vfs: &str, name: &str, file_type: VfsFileType) -> FileData {
+        let entry = VfsEntry {
+            path: format!("/{name}").into(),
+            meta: VfsMetadata {
+                file_type,
+                size: 0,
+                modified: None,
+                read_only: false,
+                link_target: None,
+                mode: None,
+            },
+        };
+        FileData::from_entry(vfs, entry)
+    }
+
+    fn new_names(plan: &[PlannedRename]) -> Vec<&str> {
+        plan.iter().map(|rename| rename.new_name.as_str()).collect()
+    }
+
+    fn problems(plan: &[PlannedRename]) -> Vec<Option<&str>> {
+        plan.iter()
+            .map(|rename| rename.problem.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn changes_case() {
+        assert_eq!(Case::Keep.apply("My photo.JPG"), "My photo.JPG");
+        assert_eq!(Case::Lower.apply("My photo.JPG"), "my photo.jpg");
+        assert_eq!(Case::Upper.apply("My photo.JPG"), "MY PHOTO.JPG");
+        assert_eq!(Case::Title.apply("my pHOTO-2023.jpg"), "My Photo-2023.Jpg");
+    }
+
+    #[test]
+    fn parses_templates() {
+        let parts = parse_template("IMG_{n}-{date:%Y}{ext}").unwrap();
+        assert!(matches!(
+            parts.as_slice(),
+            [
+                Part::Text(img),
+                Part::Counter,
+                Part::Text(dash),
+                Part::Date(format),
+                Part::Ext,
+            ] if img == "IMG_" && dash == "-" && format == "%Y"
+        ));
+        assert!(matches!(
+            parse_template("{name}{date}").unwrap().as_slice(),
+            [Part::Name, Part::Date(format)] if format == DEFAULT_DATE_FORMAT
+        ));
+
+        assert!(parse_template("{name").is_err());
+        assert!(parse_template("{size}").is_err());
+        assert!(parse_template("{date:%Q}").is_err());
+    }
+
+    #[test]
+    fn plans_new_names() {
+        let files = [
+            file("plan-test", "a.txt", VfsFileType::File),
+            file("plan-test", "b.txt", VfsFileType::File),
+            file("plan-test", "docs.old", VfsFileType::Dir),
+        ];
+        let rules = RenameRules {
+            find: "[ab]".into(),
+            replace: "x_$0".into(),
+            regex: true,
+            template: "{n}-{name}{ext}".into(),
+            counter_start: 5,
+            counter_step: 10,
+            counter_padding: 2,
+            ..RenameRules::default()
+        };
+
+        let plan = rules.plan(&files, &[], &HashSet::new()).unwrap();
+        assert_eq!(
+            new_names(&plan),
+            ["05-x_a.txt", "15-x_b.txt", "25-docs.old"]
+        );
+        assert_eq!(problems(&plan), [None, None, None]);
+
+        let rules = RenameRules {
+            find: "(".into(),
+            regex: true,
+            ..RenameRules::default()
+        };
+        assert!(rules.plan(&files, &[], &HashSet::new()).is_err());
+    }
+
+    #[test]
+    fn plans_around_collisions() {
+        let files = [
+            file("plan-test", "a", VfsFileType::File),
+            file("plan-test", "b", VfsFileType::File),
+            file("plan-test", "c", VfsFileType::File),
+        ];
+        let existing = ["a", "b", "c", "d"].map(String::from).into();
+        let rules = |template: &str| RenameRules {
+            template: template.into(),
+            ..RenameRules::default()
+        };
+
+        // Names the batch gives up are free, others aren't
+        let swap = RenameRules {
+            find: "a".into(),
+            replace: "b".into(),
+            ..RenameRules::default()
+        };
+        let plan = swap.plan(&files, &[], &existing).unwrap();
+        assert_eq!(new_names(&plan), ["b", "b", "c"]);
+        assert_eq!(
+            problems(&plan),
+            [Some("Another file in the batch gets this name"), None, None]
+        );
+
+        let plan = rules("{name}x").plan(&files[..1], &[], &existing).unwrap();
+        assert_eq!(problems(&plan), [None]);
+        let plan = rules("d").plan(&files[..1], &[], &existing).unwrap();
+        assert_eq!(problems(&plan), [Some("d already exists")]);
+        let plan = rules("c").plan(&files[..2], &[], &existing).unwrap();
+        assert_eq!(
+            problems(&plan),
+            [Some("Another file in the batch gets this name"); 2]
+        );
+        let plan = rules("{name}/").plan(&files[..1], &[], &existing).unwrap();
+        assert_eq!(problems(&plan), [Some("The name can't contain \"/\"")]);
+    }
+
+    fn memory_vfs(id: &str, names: &[&str]) -> MemoryVfs {
+        let memory = MemoryVfs::default();
+        names.iter().for_each(|name| {
+            memory
+                .write(&Path::new("/").join(name))
+                .unwrap()
+                .write_all(name.as_bytes())
+                .unwrap();
+        });
+        vfs::register(id, Arc::new(memory.clone()));
+        memory
+    }
+
+    fn contents(memory: &MemoryVfs, name: &str) -> String {
+        let mut contents = String::new();
+        memory
+            .read(&Path::new("/").join(name))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    fn names(memory: &MemoryVfs) -> Vec<String> {
+        let mut names = memory
+            .list(Path::new("/"))
+            .unwrap()
+            .into_iter()
+            .map(|entry| {
+                entry
+                    .path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn applies_swaps_and_undoes_them() {
+        let memory = memory_vfs("apply-test-swap", &["a", "b"]);
+        let files = [
+            file("apply-test-swap", "a", VfsFileType::File),
+            file("apply-test-swap", "b", VfsFileType::File),
+        ];
+        let swap = |file: &FileData, new_name: &str| PlannedRename {
+            from: file.location().clone(),
+            to: Location::new("apply-test-swap", Path::new("/").join(new_name)),
+            old_name: file.name().clone(),
+            new_name: new_name.into(),
+            problem: None,
+        };
+        let plan = [swap(&files[0], "b"), swap(&files[1], "a")];
+
+        let mut operation = apply(&plan).unwrap();
+        assert_eq!(names(&memory), ["a", "b"]);
+        assert_eq!(contents(&memory, "a"), "b");
+        assert_eq!(contents(&memory, "b"), "a");
+
+        operation.undo().unwrap();
+        assert_eq!(names(&memory), ["a", "b"]);
+        assert_eq!(contents(&memory, "a"), "a");
+    }
+
+    #[test]
+    fn rolls_back_when_a_rename_fails() {
+        let memory = memory_vfs("apply-test-fail", &["a", "b"]);
+        let files = [
+            file("apply-test-fail", "a", VfsFileType::File),
+            file("apply-test-fail", "b", VfsFileType::File),
+        ];
+        let rules = RenameRules {
+            template: "{name}.txt".into(),
+            ..RenameRules::default()
+        };
+        let plan = rules
+            .plan(&files, &[], &["a", "b"].map(String::from).into())
+            .unwrap();
+
+        // Taken after planning
+        memory.write(Path::new("/b.txt")).unwrap().flush().unwrap();
+
+        let Err(err) = apply(&plan) else {
+            panic!("Renamed onto the file taken after planning");
+        };
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(err.to_string().ends_with("Nothing was changed"));
+        assert_eq!(names(&memory), ["a", "b", "b.txt"]);
+        assert_eq!(contents(&memory, "a"), "a");
+        assert_eq!(contents(&memory, "b"), "b");
+    }
+}
@@ -0,0 +1,98 @@
+use reqwest::Url;
+
+use crate::config::Bookmark;
+
+/// Reads the bookmarks GTK file choosers and Nautilus share, skipping anything that isn't a local path
+pub fn gtk_bookmarks() -> Vec<Bookmark> {
+    let Some(file_path) = dirs::config_dir().map(|dir| dir.join("gtk-3.0").join("bookmarks"))
+    else {
+        return vec![];
+    };
+
+    match std::fs::read_to_string(&file_path) {
+        Ok(contents) => parse_gtk_bookmarks(&contents),
+        Err(err) => {
+            tracing::error!("Failed to read GTK bookmarks from {file_path:?}: {err}");
+            vec![]
+        }
+    }
+}
+
+/// Every line is `file:///percent/encoded/path Optional Label`
+fn parse_gtk_bookmarks(contents: &str) -> Vec<Bookmark> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (uri, label) = match line.trim().split_once(' ') {
+                Some((uri, label)) => (uri, Some(label.trim())),
+                None => (line.trim(), None),
+            };
+
+            let path = Url::parse(uri)
+                .ok()
+                .filter(|url| url.scheme() == "file")?
+                .to_file_path()
+                .ok()?;
+
+            Some(match label {
+                Some(label) if !label.is_empty() => Bookmark {
+                    label: label.into(),
+                    path,
+                },
+                _ => Bookmark::new(path),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn parsed(contents: &str) -> Vec<(String, PathBuf)> {
+        parse_gtk_bookmarks(contents)
+            .into_iter()
+            .map(|bookmark| (bookmark.label, bookmark.path))
+            .collect()
+    }
+
+    fn bookmark(label: &str, path: &str) -> (String, PathBuf) {
+        (label.into(), path.into())
+    }
+
+    #[test]
+    fn reads_labels_or_falls_back_to_the_name() {
+        let contents = "file:///home/user/Projects Work stuff\nfile:///home/user/Music\n";
+        assert_eq!(
+            parsed(contents),
+            [
+                bookmark("Work stuff", "/home/user/Projects"),
+                bookmark("Music", "/home/user/Music"),
+            ]
+        );
+
+        // A trailing space isn't a label
+        assert_eq!(parsed("file:///tmp "), [bookmark("tmp", "/tmp")]);
+    }
+
+    #[test]
+    fn skips_anything_but_local_files() {
+        let contents = "sftp://host/home/user Server\nsmb://share/docs\nfile://host/tmp\nnot a uri";
+        assert!(parsed(contents).is_empty());
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(
+            parsed("file:///home/user/My%20Files"),
+            [bookmark("My Files", "/home/user/My Files")]
+        );
+        // Invalid escapes are taken as they are
+        assert_eq!(
+            parsed("file:///home/user/100%zz"),
+            [bookmark("100%zz", "/home/user/100%zz")]
+        );
+    }
+}
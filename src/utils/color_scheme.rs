@@ -0,0 +1,103 @@
+//! The desktop's light/dark preference, read from the freedesktop settings portal
+//! (`org.freedesktop.appearance color-scheme`) and followed as it changes
+
+use std::sync::mpsc::{self, Receiver};
+
+use eframe::egui::Context;
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedValue, Value},
+};
+
+const PORTAL: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SETTINGS_IFACE: &str = "org.freedesktop.portal.Settings";
+
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+const COLOR_SCHEME_KEY: &str = "color-scheme";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColorScheme {
+    Dark,
+    Light,
+}
+
+impl ColorScheme {
+    /// `None` when the desktop has no preference
+    fn from_portal(value: &Value) -> Option<Self> {
+        match value {
+            // `Read` wraps the value in another variant
+            Value::Value(inner) => Self::from_portal(inner),
+            Value::U32(1) => Some(Self::Dark),
+            Value::U32(2) => Some(Self::Light),
+            _ => None,
+        }
+    }
+}
+
+impl From<eframe::Theme> for ColorScheme {
+    fn from(value: eframe::Theme) -> Self {
+        match value {
+            eframe::Theme::Dark => Self::Dark,
+            eframe::Theme::Light => Self::Light,
+        }
+    }
+}
+
+/// Listens for changes on a worker thread, without a portal it never reports anything
+pub struct SystemColorScheme {
+    changes: Receiver<Option<ColorScheme>>,
+    current: Option<ColorScheme>,
+}
+
+impl SystemColorScheme {
+    pub fn new(ctx: Context) -> Self {
+        let (sender, changes) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            let res = (|| -> zbus::Result<()> {
+                let conn = Connection::session()?;
+                let proxy = Proxy::new(&conn, PORTAL, PORTAL_PATH, SETTINGS_IFACE)?;
+
+                let value: OwnedValue =
+                    proxy.call("Read", &(APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY))?;
+                if sender.send(ColorScheme::from_portal(&value)).is_err() {
+                    return Ok(());
+                }
+                ctx.request_repaint();
+
+                for signal in proxy.receive_signal("SettingChanged")? {
+                    let (namespace, key, value): (String, String, OwnedValue) = signal.body()?;
+                    if namespace != APPEARANCE_NAMESPACE || key != COLOR_SCHEME_KEY {
+                        continue;
+                    }
+
+                    if sender.send(ColorScheme::from_portal(&value)).is_err() {
+                        break;
+                    }
+                    ctx.request_repaint();
+                }
+
+                Ok(())
+            })();
+
+            if let Err(err) = res {
+                tracing::warn!("Failed to follow the system color scheme: {err}");
+            }
+        });
+
+        Self {
+            changes,
+            current: None,
+        }
+    }
+
+    /// The latest preference, `None` without a portal or a preference
+    pub fn poll(&mut self) -> Option<ColorScheme> {
+        if let Some(latest) = self.changes.try_iter().last() {
+            self.current = latest;
+        }
+
+        self.current
+    }
+}
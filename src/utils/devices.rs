@@ -0,0 +1,314 @@
+//! Mounting, unmounting and ejecting block devices.
+//!
+//! The actual work is done by a [`DeviceBackend`], UDisks2 when it's reachable over D-Bus and the
+//! `mount`/`umount`/`eject` commands otherwise. [`DeviceManager`] runs the backend on a worker thread,
+//! since mounting may block while polkit asks for a password
+
+mod command;
+mod udisks;
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+};
+
+use eframe::egui::Context;
+
+pub use command::CommandBackend;
+pub use udisks::UDisks2Backend;
+
+/// A block device with a filesystem on it that isn't mounted
+#[derive(Clone)]
+pub struct BlockDevice {
+    pub device: PathBuf,
+    pub label: String,
+    pub fs_type: String,
+    pub size: u64,
+}
+
+#[derive(Debug)]
+pub enum DeviceError {
+    Busy(String),
+    PermissionDenied(String),
+    NotFound(String),
+    Other(String),
+}
+
+impl Display for DeviceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceError::Busy(msg) => write!(f, "Device is busy: {msg}"),
+            DeviceError::PermissionDenied(msg) => write!(f, "Permission denied: {msg}"),
+            DeviceError::NotFound(msg) => write!(f, "Device not found: {msg}"),
+            DeviceError::Other(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+pub trait DeviceBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Devices with a filesystem that can be mounted
+    fn unmounted_devices(&self) -> Result<Vec<BlockDevice>, DeviceError>;
+
+    /// Returns where the device ended up mounted
+    fn mount(&self, device: &Path) -> Result<PathBuf, DeviceError>;
+    fn unmount(&self, device: &Path) -> Result<(), DeviceError>;
+    /// Unmounts the device if needed and ejects the media
+    fn eject(&self, device: &Path) -> Result<(), DeviceError>;
+}
+
+/// UDisks2 if it's running, the command line tools otherwise
+pub fn default_backend() -> Arc<dyn DeviceBackend> {
+    match UDisks2Backend::connect() {
+        Ok(backend) => Arc::new(backend),
+        Err(err) => {
+            tracing::warn!("UDisks2 is unavailable, falling back to mount commands: {err}");
+            Arc::new(CommandBackend)
+        }
+    }
+}
+
+pub enum DeviceRequest {
+    List,
+    Mount(PathBuf),
+    Unmount(PathBuf),
+    Eject(PathBuf),
+}
+
+impl DeviceRequest {
+    fn describe(&self) -> String {
+        match self {
+            DeviceRequest::List => "list devices".into(),
+            DeviceRequest::Mount(device) => format!("mount {}", device.display()),
+            DeviceRequest::Unmount(device) => format!("unmount {}", device.display()),
+            DeviceRequest::Eject(device) => format!("eject {}", device.display()),
+        }
+    }
+}
+
+pub enum DeviceResponse {
+    Devices(Vec<BlockDevice>),
+    Mounted {
+        device: PathBuf,
+        mount_point: PathBuf,
+    },
+    Unmounted(PathBuf),
+    Ejected(PathBuf),
+    Failed {
+        action: String,
+        err: DeviceError,
+    },
+}
+
+pub struct DeviceManager {
+    requests: Sender<DeviceRequest>,
+    responses: Receiver<DeviceResponse>,
+}
+
+impl DeviceManager {
+    pub fn new(backend: Arc<dyn DeviceBackend>, ctx: Context) -> Self {
+        let (requests, request_receiver) = mpsc::channel::<DeviceRequest>();
+        let (response_sender, responses) = mpsc::channel();
+
+        tracing::info!("Managing devices with {}", backend.name());
+
+        std::thread::spawn(move || {
+            request_receiver.into_iter().for_each(|request| {
+                let action = request.describe();
+
+                let res = match request {
+                    DeviceRequest::List => backend.unmounted_devices().map(DeviceResponse::Devices),
+                    DeviceRequest::Mount(device) => {
+                        backend
+                            .mount(&device)
+                            .map(|mount_point| DeviceResponse::Mounted {
+                                device,
+                                mount_point,
+                            })
+                    }
+                    DeviceRequest::Unmount(device) => backend
+                        .unmount(&device)
+                        .map(|_| DeviceResponse::Unmounted(device)),
+                    DeviceRequest::Eject(device) => backend
+                        .eject(&device)
+                        .map(|_| DeviceResponse::Ejected(device)),
+                };
+
+                let response = res.unwrap_or_else(|err| DeviceResponse::Failed { action, err });
+
+                let _ = response_sender.send(response);
+                ctx.request_repaint();
+            })
+        });
+
+        Self {
+            requests,
+            responses,
+        }
+    }
+
+    pub fn request(&self, request: DeviceRequest) {
+        if self.requests.send(request).is_err() {
+            tracing::error!("Device worker is gone");
+        }
+    }
+
+    pub fn responses(&self) -> impl Iterator<Item = DeviceResponse> + '_ {
+        self.responses.try_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Mutex, time::Duration};
+
+    use super::*;
+
+    /// Keeps mounted devices in memory, mounting a busy device fails like a real one would
+    #[derive(Default)]
+    struct FakeBackend {
+        devices: Vec<BlockDevice>,
+        mounted: Mutex<Vec<PathBuf>>,
+        busy: Vec<PathBuf>,
+    }
+
+    impl FakeBackend {
+        fn with_device(mut self, device: &str) -> Self {
+            self.devices.push(BlockDevice {
+                device: device.into(),
+                label: String::new(),
+                fs_type: "ext4".into(),
+                size: 0,
+            });
+            self
+        }
+
+        fn check(&self, device: &Path) -> Result<(), DeviceError> {
+            if !self.devices.iter().any(|d| d.device == device) {
+                return Err(DeviceError::NotFound(device.display().to_string()));
+            }
+            if self.busy.iter().any(|busy| busy == device) {
+                return Err(DeviceError::Busy(device.display().to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    impl DeviceBackend for FakeBackend {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn unmounted_devices(&self) -> Result<Vec<BlockDevice>, DeviceError> {
+            let mounted = self.mounted.lock().unwrap();
+            Ok(self
+                .devices
+                .iter()
+                .filter(|d| !mounted.contains(&d.device))
+                .cloned()
+                .collect())
+        }
+
+        fn mount(&self, device: &Path) -> Result<PathBuf, DeviceError> {
+            self.check(device)?;
+            self.mounted.lock().unwrap().push(device.to_path_buf());
+            Ok(Path::new("/media").join(device.file_name().unwrap_or_default()))
+        }
+
+        fn unmount(&self, device: &Path) -> Result<(), DeviceError> {
+            self.check(device)?;
+            let mut mounted = self.mounted.lock().unwrap();
+            match mounted.iter().position(|m| m == device) {
+                Some(i) => {
+                    mounted.remove(i);
+                    Ok(())
+                }
+                None => Err(DeviceError::Other("Not mounted".into())),
+            }
+        }
+
+        fn eject(&self, device: &Path) -> Result<(), DeviceError> {
+            self.check(device)?;
+            self.mounted.lock().unwrap().retain(|m| m != device);
+            Ok(())
+        }
+    }
+
+    fn response(manager: &DeviceManager, request: DeviceRequest) -> DeviceResponse {
+        manager.request(request);
+        manager
+            .responses
+            .recv_timeout(Duration::from_secs(5))
+            .expect("No response from the device worker")
+    }
+
+    fn manager(backend: FakeBackend) -> DeviceManager {
+        DeviceManager::new(Arc::new(backend), Context::default())
+    }
+
+    #[test]
+    fn mounts_and_unmounts() {
+        let manager = manager(
+            FakeBackend::default()
+                .with_device("/dev/sdb1")
+                .with_device("/dev/sdc1"),
+        );
+
+        let DeviceResponse::Mounted {
+            device,
+            mount_point,
+        } = response(&manager, DeviceRequest::Mount("/dev/sdb1".into()))
+        else {
+            panic!("Expected the device to be mounted");
+        };
+        assert_eq!(device, PathBuf::from("/dev/sdb1"));
+        assert_eq!(mount_point, PathBuf::from("/media/sdb1"));
+
+        let DeviceResponse::Devices(devices) = response(&manager, DeviceRequest::List) else {
+            panic!("Expected the devices");
+        };
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].device, PathBuf::from("/dev/sdc1"));
+
+        assert!(matches!(
+            response(&manager, DeviceRequest::Unmount("/dev/sdb1".into())),
+            DeviceResponse::Unmounted(device) if device == Path::new("/dev/sdb1")
+        ));
+        assert!(matches!(
+            response(&manager, DeviceRequest::Eject("/dev/sdc1".into())),
+            DeviceResponse::Ejected(_)
+        ));
+    }
+
+    #[test]
+    fn reports_failures() {
+        let backend = FakeBackend {
+            busy: vec!["/dev/sdb1".into()],
+            ..FakeBackend::default().with_device("/dev/sdb1")
+        };
+        let manager = manager(backend);
+
+        let DeviceResponse::Failed { action, err } =
+            response(&manager, DeviceRequest::Unmount("/dev/sdb1".into()))
+        else {
+            panic!("Expected unmounting a busy device to fail");
+        };
+        assert_eq!(action, "unmount /dev/sdb1");
+        assert!(matches!(err, DeviceError::Busy(_)));
+
+        assert!(matches!(
+            response(&manager, DeviceRequest::Mount("/dev/missing".into())),
+            DeviceResponse::Failed {
+                err: DeviceError::NotFound(_),
+                ..
+            }
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use super::{BlockDevice, DeviceBackend, DeviceError};
+
+/// Falls back to util-linux tools. Without UDisks2 mounting only works for devices
+/// that `/etc/fstab` allows users to mount
+pub struct CommandBackend;
+
+impl CommandBackend {
+    fn run(program: &str, args: &[&str]) -> Result<String, DeviceError> {
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .map_err(|err| DeviceError::Other(format!("Failed to run {program}: {err}")))?;
+
+        match output.status.success() {
+            true => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+            false => Err(classify_stderr(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            )),
+        }
+    }
+
+    fn mount_point(device: &Path) -> Result<Option<PathBuf>, DeviceError> {
+        let device = device.to_string_lossy();
+        let output = Self::run("lsblk", &["-n", "-o", "MOUNTPOINT", &device])?;
+
+        Ok(output
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(PathBuf::from))
+    }
+}
+
+impl DeviceBackend for CommandBackend {
+    fn name(&self) -> &'static str {
+        "mount commands"
+    }
+
+    fn unmounted_devices(&self) -> Result<Vec<BlockDevice>, DeviceError> {
+        let output = Self::run(
+            "lsblk",
+            &["-P", "-b", "-o", "PATH,LABEL,FSTYPE,SIZE,MOUNTPOINT"],
+        )?;
+
+        Ok(output
+            .lines()
+            .map(parse_pairs)
+            .filter(|pairs| {
+                pairs.get("MOUNTPOINT").is_some_and(String::is_empty)
+                    && pairs
+                        .get("FSTYPE")
+                        .is_some_and(|fs_type| !fs_type.is_empty() && fs_type != "swap")
+            })
+            .filter_map(|mut pairs| {
+                Some(BlockDevice {
+                    device: PathBuf::from(pairs.remove("PATH")?),
+                    label: pairs.remove("LABEL").unwrap_or_default(),
+                    fs_type: pairs.remove("FSTYPE").unwrap_or_default(),
+                    size: pairs
+                        .get("SIZE")
+                        .and_then(|size| size.parse().ok())
+                        .unwrap_or_default(),
+                })
+            })
+            .collect())
+    }
+
+    fn mount(&self, device: &Path) -> Result<PathBuf, DeviceError> {
+        Self::run("mount", &[&device.to_string_lossy()])?;
+
+        Self::mount_point(device)?.ok_or_else(|| {
+            DeviceError::Other(format!("{} didn't end up mounted", device.display()))
+        })
+    }
+
+    fn unmount(&self, device: &Path) -> Result<(), DeviceError> {
+        Self::run("umount", &[&device.to_string_lossy()]).map(|_| ())
+    }
+
+    fn eject(&self, device: &Path) -> Result<(), DeviceError> {
+        if Self::mount_point(device)?.is_some() {
+            self.unmount(device)?;
+        }
+
+        Self::run("eject", &[&device.to_string_lossy()]).map(|_| ())
+    }
+}
+
+/// `lsblk -P` prints lines of `KEY="value"` pairs
+fn parse_pairs(line: &str) -> HashMap<String, String> {
+    let mut pairs = HashMap::new();
+    let mut rest = line.trim();
+
+    while let Some((key, tail)) = rest.split_once("=\"") {
+        let Some((value, tail)) = tail.split_once('"') else {
+            break;
+        };
+
+        pairs.insert(key.trim().to_string(), value.to_string());
+        rest = tail;
+    }
+
+    pairs
+}
+
+fn classify_stderr(stderr: String) -> DeviceError {
+    let lower = stderr.to_lowercase();
+
+    if lower.contains("target is busy") || lower.contains("device is busy") {
+        DeviceError::Busy(stderr)
+    } else if lower.contains("permission denied")
+        || lower.contains("must be superuser")
+        || lower.contains("only root")
+    {
+        DeviceError::PermissionDenied(stderr)
+    } else if lower.contains("can't find") || lower.contains("no such") {
+        DeviceError::NotFound(stderr)
+    } else {
+        DeviceError::Other(stderr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lsblk_pairs() {
+        let pairs = parse_pairs(r#"PATH="/dev/sdb1" LABEL="My Drive" FSTYPE="vfat" MOUNTPOINT="""#);
+
+        assert_eq!(pairs["PATH"], "/dev/sdb1");
+        assert_eq!(pairs["LABEL"], "My Drive");
+        assert_eq!(pairs["FSTYPE"], "vfat");
+        assert_eq!(pairs["MOUNTPOINT"], "");
+    }
+
+    #[test]
+    fn classifies_errors() {
+        let classify = |stderr: &str| classify_stderr(stderr.into());
+
+        assert!(matches!(
+            classify("umount: /media/usb: target is busy."),
+            DeviceError::Busy(_)
+        ));
+        assert!(matches!(
+            classify("mount: only root can do that"),
+            DeviceError::PermissionDenied(_)
+        ));
+        assert!(matches!(
+            classify("mount: /dev/sdx1: can't find in /etc/fstab."),
+            DeviceError::NotFound(_)
+        ));
+        assert!(matches!(classify("something else"), DeviceError::Other(_)));
+    }
+}
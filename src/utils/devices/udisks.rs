@@ -0,0 +1,185 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+};
+
+use super::{BlockDevice, DeviceBackend, DeviceError};
+
+const UDISKS2: &str = "org.freedesktop.UDisks2";
+const UDISKS2_PATH: &str = "/org/freedesktop/UDisks2";
+const MANAGER_PATH: &str = "/org/freedesktop/UDisks2/Manager";
+
+const BLOCK_IFACE: &str = "org.freedesktop.UDisks2.Block";
+const FILESYSTEM_IFACE: &str = "org.freedesktop.UDisks2.Filesystem";
+const DRIVE_IFACE: &str = "org.freedesktop.UDisks2.Drive";
+const MANAGER_IFACE: &str = "org.freedesktop.UDisks2.Manager";
+const OBJECT_MANAGER_IFACE: &str = "org.freedesktop.DBus.ObjectManager";
+
+type Properties = HashMap<String, OwnedValue>;
+type ManagedObjects = HashMap<OwnedObjectPath, HashMap<String, Properties>>;
+
+/// Talks to udisksd over the system bus, the same way desktop file managers do,
+/// so polkit decides whether the user may mount without root
+pub struct UDisks2Backend {
+    conn: Connection,
+}
+
+impl UDisks2Backend {
+    pub fn connect() -> zbus::Result<Self> {
+        let conn = Connection::system()?;
+
+        // Fail early if udisksd isn't there
+        Proxy::new(&conn, UDISKS2, MANAGER_PATH, MANAGER_IFACE)?
+            .get_property::<String>("Version")?;
+
+        Ok(Self { conn })
+    }
+
+    fn proxy<'a>(&'a self, path: &'a str, iface: &'a str) -> Result<Proxy<'a>, DeviceError> {
+        Proxy::new(&self.conn, UDISKS2, path, iface).map_err(to_device_error)
+    }
+
+    /// Finds the UDisks2 block object of a `/dev/...` path
+    fn block_object(&self, device: &Path) -> Result<OwnedObjectPath, DeviceError> {
+        let spec = HashMap::from([("path", Value::from(device.to_string_lossy().to_string()))]);
+
+        self.proxy(MANAGER_PATH, MANAGER_IFACE)?
+            .call::<_, _, Vec<OwnedObjectPath>>("ResolveDevice", &(spec, no_options()))
+            .map_err(to_device_error)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| DeviceError::NotFound(device.display().to_string()))
+    }
+}
+
+impl DeviceBackend for UDisks2Backend {
+    fn name(&self) -> &'static str {
+        "UDisks2"
+    }
+
+    fn unmounted_devices(&self) -> Result<Vec<BlockDevice>, DeviceError> {
+        let objects = self
+            .proxy(UDISKS2_PATH, OBJECT_MANAGER_IFACE)?
+            .call::<_, _, ManagedObjects>("GetManagedObjects", &())
+            .map_err(to_device_error)?;
+
+        Ok(objects
+            .into_values()
+            .filter_map(|ifaces| {
+                let block = ifaces.get(BLOCK_IFACE)?;
+                let filesystem = ifaces.get(FILESYSTEM_IFACE)?;
+
+                let mount_points: Vec<Vec<u8>> = property(filesystem, "MountPoints")?;
+                let ignored: bool = property(block, "HintIgnore").unwrap_or(false);
+                if !mount_points.is_empty() || ignored {
+                    return None;
+                }
+
+                Some(BlockDevice {
+                    device: bytestring_path(property(block, "PreferredDevice")?),
+                    label: property(block, "IdLabel").unwrap_or_default(),
+                    fs_type: property(block, "IdType").unwrap_or_default(),
+                    size: property(block, "Size").unwrap_or_default(),
+                })
+            })
+            .collect())
+    }
+
+    fn mount(&self, device: &Path) -> Result<PathBuf, DeviceError> {
+        let object = self.block_object(device)?;
+
+        let mount_point = self
+            .proxy(object.as_str(), FILESYSTEM_IFACE)?
+            .call::<_, _, String>("Mount", &(no_options(),))
+            .map(PathBuf::from)
+            .map_err(to_device_error);
+        mount_point
+    }
+
+    fn unmount(&self, device: &Path) -> Result<(), DeviceError> {
+        let object = self.block_object(device)?;
+
+        let res = self
+            .proxy(object.as_str(), FILESYSTEM_IFACE)?
+            .call::<_, _, ()>("Unmount", &(no_options(),))
+            .map_err(to_device_error);
+        res
+    }
+
+    fn eject(&self, device: &Path) -> Result<(), DeviceError> {
+        let object = self.block_object(device)?;
+
+        let filesystem = self.proxy(object.as_str(), FILESYSTEM_IFACE)?;
+        let mount_points = filesystem
+            .get_property::<Vec<Vec<u8>>>("MountPoints")
+            .unwrap_or_default();
+        if !mount_points.is_empty() {
+            filesystem
+                .call::<_, _, ()>("Unmount", &(no_options(),))
+                .map_err(to_device_error)?;
+        }
+
+        let drive = self
+            .proxy(object.as_str(), BLOCK_IFACE)?
+            .get_property::<OwnedObjectPath>("Drive")
+            .map_err(to_device_error)?;
+        if drive.as_str() == "/" {
+            return Err(DeviceError::NotFound(format!(
+                "{} doesn't belong to a drive",
+                device.display()
+            )));
+        }
+
+        let res = self
+            .proxy(drive.as_str(), DRIVE_IFACE)?
+            .call::<_, _, ()>("Eject", &(no_options(),))
+            .map_err(to_device_error);
+        res
+    }
+}
+
+fn no_options() -> HashMap<&'static str, Value<'static>> {
+    HashMap::new()
+}
+
+fn property<T: TryFrom<OwnedValue>>(properties: &Properties, name: &str) -> Option<T> {
+    properties
+        .get(name)
+        .cloned()
+        .and_then(|value| T::try_from(value).ok())
+}
+
+/// Paths are sent as NUL-terminated byte arrays
+fn bytestring_path(mut bytes: Vec<u8>) -> PathBuf {
+    if bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+
+    PathBuf::from(OsStr::from_bytes(&bytes))
+}
+
+fn to_device_error(err: zbus::Error) -> DeviceError {
+    match &err {
+        zbus::Error::MethodError(name, detail, _) => {
+            let msg = detail.clone().unwrap_or_else(|| name.to_string());
+
+            match name.as_str() {
+                "org.freedesktop.UDisks2.Error.DeviceBusy" => DeviceError::Busy(msg),
+                "org.freedesktop.UDisks2.Error.NotAuthorized"
+                | "org.freedesktop.UDisks2.Error.NotAuthorizedCanObtain"
+                | "org.freedesktop.UDisks2.Error.NotAuthorizedDismissed" => {
+                    DeviceError::PermissionDenied(msg)
+                }
+                _ => DeviceError::Other(msg),
+            }
+        }
+        _ => DeviceError::Other(err.to_string()),
+    }
+}
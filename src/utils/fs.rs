@@ -1,20 +1,70 @@
 use std::{
-    fs::{self, File, FileType, Metadata, OpenOptions},
-    path::PathBuf,
+    ffi::CString,
+    fs,
+    io::{self, ErrorKind, Read},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 
-use super::icons::PhosphorIcon;
+use super::{
+    archive::{ArchiveKind, ArchivePath},
+    icons::PhosphorIcon,
+    vfs::{Location, VfsEntry, VfsFileType, VfsMetadata},
+};
+
+/// What a file is, as far as coloring it goes
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileKind {
+    Dir,
+    File,
+    Symlink,
+    /// A symlink whose target is gone
+    BrokenSymlink,
+    Archive,
+    /// Missing metadata or a device, socket, ...
+    Unknown,
+}
 
+#[derive(Clone)]
 pub struct FileData {
-    path: PathBuf,
+    location: Location,
     name: String,
     ext: Option<String>,
-    meta: Option<Metadata>,
+    meta: Option<VfsMetadata>,
+    /// Local paths inside an archive, which is read-only
+    archive_member: bool,
 }
 
 impl FileData {
     pub fn new(path: impl Into<PathBuf>) -> Self {
-        let path: PathBuf = path.into();
+        Self::at(Location::local(path))
+    }
+
+    /// Reads the metadata of local files right away. Remote metadata and that of archive members,
+    /// which needs the archive indexed, is left for the caller to [fill in](Self::set_meta) off
+    /// the UI thread, e.g. from a listing
+    pub fn at(location: Location) -> Self {
+        let mut res = Self::with_meta(location, None);
+        if !res.location.is_local() || res.archive_member {
+            return res;
+        }
+
+        match res.location.backend().and_then(|vfs| vfs.stat(res.path())) {
+            Ok(meta) => res.meta = Some(meta),
+            Err(err) => tracing::error!("Failed to get file {} metadata: {err}", res.location),
+        }
+
+        res
+    }
+
+    /// Wraps an entry [listed](super::vfs::Vfs::list) by the `vfs` backend
+    pub fn from_entry(vfs: &str, entry: VfsEntry) -> Self {
+        Self::with_meta(Location::new(vfs, entry.path), Some(entry.meta))
+    }
+
+    fn with_meta(location: Location, meta: Option<VfsMetadata>) -> Self {
+        let path = location.path();
         let name = path
             .file_name()
             .unwrap_or(path.as_os_str())
@@ -23,69 +73,73 @@ impl FileData {
         let ext = path
             .extension()
             .map(|ext| ext.to_string_lossy().to_string());
-        let meta = match path.metadata() {
-            Ok(meta) => Some(meta),
-            Err(err) => {
-                tracing::error!("Failed to get file {path:?} metadata: {err}");
-                None
-            }
-        };
+        let archive_member = location.is_local()
+            && ArchivePath::parse(path).is_some_and(|archive_path| archive_path.is_member());
 
         Self {
-            path,
+            location,
             name,
             ext,
             meta,
+            archive_member,
         }
     }
 
+    pub fn set_meta(&mut self, meta: VfsMetadata) {
+        self.meta = Some(meta);
+    }
+
+    pub fn location(&self) -> &Location {
+        &self.location
+    }
+
     pub fn path(&self) -> &PathBuf {
-        &self.path
+        self.location.path()
+    }
+
+    pub fn ext(&self) -> Option<&str> {
+        self.ext.as_deref()
     }
 
     pub fn name(&self) -> &String {
         &self.name
     }
 
-    pub fn rename(&mut self, new_name: impl AsRef<str>) {
+    /// Renames the file in place, refusing invalid names and never overwriting another file
+    pub fn rename(&mut self, new_name: impl AsRef<str>) -> io::Result<()> {
         let new_name = new_name.as_ref();
+        check_file_name(new_name).map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))?;
 
-        let mut new_path = self.path.clone();
+        let mut new_path = self.path().clone();
         new_path.set_file_name(new_name);
 
-        match fs::rename(&self.path, &new_path) {
-            Ok(_) => {
-                self.path = new_path;
-            }
-            Err(err) => {
-                // TODO: Toast
-                tracing::error!("Failed to rename the file: {err}");
-            }
-        }
-    }
-
-    pub fn open(&self, options: &OpenOptions) -> std::io::Result<File> {
-        options.open(&self.path)
-    }
+        self.location
+            .backend()?
+            .rename(self.path(), &new_path)
+            .map_err(|err| match err.kind() {
+                ErrorKind::AlreadyExists => io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{new_name} already exists"),
+                ),
+                _ => err,
+            })?;
 
-    pub fn linked(&self) -> Option<FileData> {
-        match self.file_ty()?.is_symlink() {
-            true => match fs::read_link(&self.path) {
-                Ok(path) => Some(Self::new(path)),
-                Err(err) => {
-                    tracing::error!("Failed to get linked FileData for {:?}: {err}", &self.path);
-                    None
-                }
-            },
-            _ => None,
-        }
+        *self = Self::with_meta(
+            Location::new(self.location.vfs(), new_path),
+            self.meta.take(),
+        );
+        Ok(())
     }
 
-    pub fn rec_linked(&self) -> Option<FileData> {
-        self.linked().map(|fd| fd.linked().unwrap_or(fd))
+    pub fn read(&self) -> io::Result<Box<dyn Read + Send>> {
+        self.location.backend()?.read(self.path())
     }
 
     pub fn icon(&self) -> PhosphorIcon {
+        if self.is_archive() {
+            return PhosphorIcon::FileZip;
+        }
+
         self.file_ty()
             .map(
                 |file_ty| match (file_ty.is_symlink(), file_ty.is_file(), file_ty.is_dir()) {
@@ -103,11 +157,332 @@ impl FileData {
             .unwrap_or(PhosphorIcon::SealWarning)
     }
 
-    pub fn file_ty(&self) -> Option<FileType> {
-        self.meta.as_ref().map(|m| m.file_type())
+    /// Backends follow symlinks, so a file that's still typed as one is a broken symlink
+    pub fn kind(&self) -> FileKind {
+        match self.file_ty() {
+            Some(file_ty) if file_ty.is_symlink() => FileKind::BrokenSymlink,
+            Some(_) if self.is_symlink() => FileKind::Symlink,
+            _ => self.target_kind(),
+        }
+    }
+
+    /// [`Self::kind`] of what a symlink points to, backends report its metadata for the link
+    pub fn target_kind(&self) -> FileKind {
+        if self.is_archive() {
+            return FileKind::Archive;
+        }
+
+        match self.file_ty() {
+            Some(file_ty) if file_ty.is_dir() => FileKind::Dir,
+            Some(file_ty) if file_ty.is_file() => FileKind::File,
+            Some(file_ty) if file_ty.is_symlink() => FileKind::BrokenSymlink,
+            _ => FileKind::Unknown,
+        }
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.meta
+            .as_ref()
+            .is_some_and(|meta| meta.link_target.is_some())
+    }
+
+    pub fn file_ty(&self) -> Option<VfsFileType> {
+        self.meta.as_ref().map(|m| m.file_type)
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_ty().is_some_and(|file_ty| file_ty.is_dir())
+    }
+
+    /// An archive file on disk that can be browsed like a directory
+    pub fn is_archive(&self) -> bool {
+        self.location.is_local()
+            && !self.archive_member
+            && self.file_ty().is_some_and(|file_ty| file_ty.is_file())
+            && ArchiveKind::from_path(self.path()).is_some()
+    }
+
+    pub fn is_archive_member(&self) -> bool {
+        self.archive_member
+    }
+
+    /// As far as the backend can tell, `false` without metadata
+    pub fn is_read_only(&self) -> bool {
+        self.meta.as_ref().is_some_and(|meta| meta.read_only)
     }
 
-    pub fn rec_linked_file_ty(&self) -> Option<FileType> {
-        self.rec_linked().and_then(|fd| fd.file_ty())
+    pub fn size(&self) -> Option<u64> {
+        self.meta.as_ref().map(|m| m.size)
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.meta.as_ref().and_then(|m| m.modified)
+    }
+
+    /// See [`VfsMetadata::mode`]
+    pub fn mode(&self) -> Option<u32> {
+        self.meta.as_ref().and_then(|m| m.mode)
+    }
+}
+
+/// Copies `from` to `to`, descending into directories and recreating symlinks as-is.
+/// Never overwrites an existing `to`
+pub fn copy_recursive(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+
+    check_transfer_target(from, to)?;
+
+    let meta = fs::symlink_metadata(from)?;
+    let file_ty = meta.file_type();
+
+    if file_ty.is_symlink() {
+        std::os::unix::fs::symlink(fs::read_link(from)?, to)
+    } else if file_ty.is_dir() {
+        fs::create_dir(to)?;
+        fs::set_permissions(to, meta.permissions())?;
+
+        fs::read_dir(from)?.try_for_each(|entry| {
+            let entry = entry?;
+            copy_recursive(entry.path(), to.join(entry.file_name()))
+        })
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Moves `from` to `to`, falling back to copy + remove when crossing filesystems.
+/// Never overwrites an existing `to`
+pub fn move_path(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+
+    check_transfer_target(from, to)?;
+
+    match rename_no_replace(from, to) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::CrossesDevices => {
+            copy_recursive(from, to)?;
+            remove_path(from)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Renames `from` to `to`, failing with [`ErrorKind::AlreadyExists`] if something is there. The
+/// kernel checks that as part of the rename where the filesystem supports it. Changing only the
+/// case of a name is allowed, on case-insensitive filesystems the target is the file itself
+pub fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    match renameat2_no_replace(from, to) {
+        Err(err) if err.kind() == ErrorKind::AlreadyExists && is_case_change(from, to) => {
+            fs::rename(from, to)
+        }
+        // Not supported by the filesystem, checking first is all that's left
+        Err(err) if matches!(err.raw_os_error(), Some(libc::EINVAL | libc::ENOSYS)) => {
+            match to.symlink_metadata().is_ok() && !is_case_change(from, to) {
+                true => Err(io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{to:?} already exists"),
+                )),
+                false => fs::rename(from, to),
+            }
+        }
+        res => res,
+    }
+}
+
+fn renameat2_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    let c_path = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, err))
+    };
+    let (from_c, to_c) = (c_path(from)?, c_path(to)?);
+
+    // SAFETY: both paths are NUL terminated strings that outlive the call
+    match unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            from_c.as_ptr(),
+            libc::AT_FDCWD,
+            to_c.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Whether `to` is `from` in the same directory, its name only differing in case. A hard link
+/// named like that on a case-sensitive filesystem has its own entry in the directory
+fn is_case_change(from: &Path, to: &Path) -> bool {
+    let same_name = match (from.file_name(), to.file_name()) {
+        (Some(from_name), Some(to_name)) => {
+            from_name.to_string_lossy().to_lowercase() == to_name.to_string_lossy().to_lowercase()
+        }
+        _ => false,
+    };
+    let same_file = match (from.symlink_metadata(), to.symlink_metadata()) {
+        (Ok(from_meta), Ok(to_meta)) => {
+            from_meta.dev() == to_meta.dev() && from_meta.ino() == to_meta.ino()
+        }
+        _ => false,
+    };
+
+    let own_entry = || {
+        let (Some(parent), Some(to_name)) = (to.parent(), to.file_name()) else {
+            return false;
+        };
+        fs::read_dir(parent).is_ok_and(|mut entries| {
+            entries.any(|entry| entry.is_ok_and(|entry| entry.file_name() == to_name))
+        })
+    };
+
+    same_name && from.parent() == to.parent() && same_file && !own_entry()
+}
+
+pub fn remove_path(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+
+    match fs::symlink_metadata(path)?.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path),
+    }
+}
+
+/// Longest file name most filesystems accept, in bytes
+const MAX_NAME_LEN: usize = 255;
+
+/// Why `name` can't be a file name, if it can't
+pub fn check_file_name(name: &str) -> Result<(), String> {
+    match name {
+        _ if name.trim().is_empty() => Err("The name can't be empty".into()),
+        "." | ".." => Err(format!("{name:?} isn't a valid name")),
+        _ if name.contains('/') => Err("The name can't contain \"/\"".into()),
+        _ if name.contains('\0') => Err("The name can't contain NUL characters".into()),
+        _ if name.len() > MAX_NAME_LEN => {
+            Err(format!("The name is longer than {MAX_NAME_LEN} bytes"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `path` itself if nothing exists there yet, otherwise the first free `name (2).ext`-like sibling
+pub fn unique_path(path: &Path) -> PathBuf {
+    if path.symlink_metadata().is_err() {
+        return path.to_path_buf();
+    }
+
+    let stem = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    (2..)
+        .map(|i| path.with_file_name(format!("{stem} ({i}){ext}")))
+        .find(|candidate| candidate.symlink_metadata().is_err())
+        .expect("Ran out of file names")
+}
+
+fn check_transfer_target(from: &Path, to: &Path) -> io::Result<()> {
+    if to.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{to:?} already exists"),
+        ));
+    }
+
+    if to.starts_with(from) {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Can't transfer {from:?} into itself"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Formats a byte count with binary units, e.g. `1.5 GiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{bytes} {}", UNITS[0]),
+        _ => format!("{size:.1} {}", UNITS[unit]),
+    }
+}
+
+/// An empty directory for a test to play in, removed again when dropped
+#[cfg(test)]
+pub struct TestDir(pub PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("rsfm-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renames_without_replacing() {
+        let dir = TestDir::new("rename");
+        let (a, b, c) = (dir.0.join("a"), dir.0.join("b"), dir.0.join("c"));
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+
+        let err = rename_no_replace(&a, &b).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&b).unwrap(), "b");
+
+        // Another link to the same file isn't a case change
+        fs::hard_link(&a, dir.0.join("A")).unwrap();
+        let err = rename_no_replace(&a, &dir.0.join("A")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert!(!is_case_change(&a, &b));
+
+        rename_no_replace(&a, &c).unwrap();
+        assert!(a.symlink_metadata().is_err());
+        assert_eq!(fs::read_to_string(&c).unwrap(), "a");
+    }
+
+    #[test]
+    fn renames_files_through_their_backend() {
+        let dir = TestDir::new("file-data-rename");
+        fs::write(dir.0.join("old.txt"), "old").unwrap();
+        fs::write(dir.0.join("taken.txt"), "taken").unwrap();
+
+        let mut file_data = FileData::new(dir.0.join("old.txt"));
+        let err = file_data.rename("taken.txt").unwrap_err();
+        assert_eq!(err.to_string(), "taken.txt already exists");
+        assert!(file_data.rename("a/b").is_err());
+
+        file_data.rename("new.txt").unwrap();
+        assert_eq!(file_data.name(), "new.txt");
+        assert_eq!(fs::read_to_string(dir.0.join("new.txt")).unwrap(), "old");
     }
 }
@@ -0,0 +1,177 @@
+//! Which files count as hidden: dotfiles, names listed in their directory's `.hidden` file
+//! the way GNOME does it, and names matching the user's glob patterns
+
+use std::{
+    collections::HashSet,
+    io::{ErrorKind, Read},
+    sync::RwLock,
+};
+
+use glob::Pattern;
+use lazy_static::lazy_static;
+
+use crate::config::HiddenFiles;
+
+use super::vfs::Location;
+
+const DOT_HIDDEN: &str = ".hidden";
+
+lazy_static! {
+    static ref RULES: RwLock<HideRules> = RwLock::new(HideRules::default());
+}
+
+#[derive(Default)]
+struct HideRules {
+    patterns: Vec<Pattern>,
+    dot_hidden: bool,
+}
+
+impl HideRules {
+    /// Skips patterns that aren't valid globs, the second value names them
+    fn new(config: &HiddenFiles) -> (Self, Vec<String>) {
+        let mut invalid = vec![];
+        let patterns = config
+            .patterns
+            .iter()
+            .filter_map(|pattern| match Pattern::new(pattern) {
+                Ok(pattern) => Some(pattern),
+                Err(err) => {
+                    invalid.push(format!("{pattern:?} ({err})"));
+                    None
+                }
+            })
+            .collect();
+
+        let rules = Self {
+            patterns,
+            dot_hidden: config.dot_hidden,
+        };
+        (rules, invalid)
+    }
+
+    fn hides(&self, name: &str, dot_hidden: &HashSet<String>) -> bool {
+        name.starts_with('.')
+            || dot_hidden.contains(name)
+            || self.patterns.iter().any(|pattern| pattern.matches(name))
+    }
+}
+
+/// Replaces the rules listings are filtered with.
+/// Patterns that aren't valid globs are skipped, `Err` names them
+pub fn set_rules(config: &HiddenFiles) -> Result<(), String> {
+    let (rules, invalid) = HideRules::new(config);
+    *RULES.write().expect("Hide rules poisoned") = rules;
+
+    match invalid.is_empty() {
+        true => Ok(()),
+        false => Err(format!(
+            "Ignoring invalid hide patterns: {}",
+            invalid.join(", ")
+        )),
+    }
+}
+
+/// Names listed in `dir`'s `.hidden` file, one per line. Empty when it's missing or turned off
+pub fn read_dot_hidden(dir: &Location) -> HashSet<String> {
+    match RULES.read().expect("Hide rules poisoned").dot_hidden {
+        true => read_names(dir),
+        false => HashSet::new(),
+    }
+}
+
+fn read_names(dir: &Location) -> HashSet<String> {
+    let path = dir.path().join(DOT_HIDDEN);
+    let res = dir.backend().and_then(|vfs| {
+        let mut src = String::new();
+        vfs.read(&path)?.read_to_string(&mut src)?;
+        Ok(src)
+    });
+
+    match res {
+        Ok(src) => src
+            .lines()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(err) if err.kind() == ErrorKind::NotFound => HashSet::new(),
+        Err(err) => {
+            tracing::warn!("Failed to read {path:?}: {err}");
+            HashSet::new()
+        }
+    }
+}
+
+/// `dot_hidden` is what [`read_dot_hidden`] returned for the file's directory
+pub fn is_hidden(name: &str, dot_hidden: &HashSet<String>) -> bool {
+    RULES
+        .read()
+        .expect("Hide rules poisoned")
+        .hides(name, dot_hidden)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, path::Path, sync::Arc};
+
+    use crate::utils::vfs::{self, MemoryVfs, Vfs};
+
+    use super::*;
+
+    fn rules(patterns: &[&str]) -> HideRules {
+        let config = HiddenFiles {
+            patterns: patterns.iter().map(|pattern| pattern.to_string()).collect(),
+            dot_hidden: true,
+        };
+        let (rules, invalid) = HideRules::new(&config);
+        assert!(invalid.is_empty());
+        rules
+    }
+
+    #[test]
+    fn hides_dotfiles_and_names_matching_patterns() {
+        let rules = rules(&["*.pyc", "build-?"]);
+        let none = HashSet::new();
+
+        assert!(rules.hides(".git", &none));
+        assert!(rules.hides("main.pyc", &none));
+        assert!(rules.hides("build-1", &none));
+        assert!(!rules.hides("main.py", &none));
+        assert!(!rules.hides("build-10", &none));
+    }
+
+    #[test]
+    fn names_invalid_patterns() {
+        let config = HiddenFiles {
+            patterns: vec!["[".into(), "*.o".into()],
+            dot_hidden: false,
+        };
+        let (rules, invalid) = HideRules::new(&config);
+        assert_eq!(rules.patterns.len(), 1);
+        assert_eq!(invalid.len(), 1);
+        assert!(invalid[0].starts_with("\"[\""));
+    }
+
+    #[test]
+    fn hides_names_listed_in_dot_hidden() {
+        let memory = MemoryVfs::default();
+        memory
+            .write(Path::new("/.hidden"))
+            .unwrap()
+            .write_all(b"snap\n  \n Templates \n")
+            .unwrap();
+        vfs::register("hidden-test", Arc::new(memory));
+
+        let dir = Location::new("hidden-test", "/");
+        let names = read_names(&dir);
+        assert_eq!(names, HashSet::from(["snap".into(), "Templates".into()]));
+
+        let rules = rules(&[]);
+        assert!(rules.hides("snap", &names));
+        assert!(!rules.hides("Music", &names));
+
+        // A directory without one hides nothing extra
+        let empty = Location::new("hidden-test", "/missing");
+        assert!(read_names(&empty).is_empty());
+    }
+}
@@ -12,28 +12,114 @@ enum PhosphorIcon {
     ArrowRight,
     #[ri()]
     ArrowClockwise,
+    #[ri()]
+    ArrowsLeftRight,
+    #[ri()]
+    ArrowSquareOut,
+    #[ri()]
+    ArrowUUpLeft,
+    #[ri()]
+    ArrowUUpRight,
+
+    #[ri()]
+    BookmarkSimple,
+
+    #[ri()]
+    ClockCounterClockwise,
+    #[ri()]
+    Cloud,
+    #[ri()]
+    Columns,
+    #[ri()]
+    Copy,
 
     #[ri()]
     Desktop,
     #[ri()]
     DotsNine,
     #[ri()]
+    DotsSixVertical,
+    #[ri()]
     Download,
 
+    #[ri()]
+    Eject,
+    #[ri()]
+    Export,
+    #[ri()]
+    Eye,
+
+    #[ri()]
+    FloppyDisk,
     #[ri()]
     Folder,
     #[ri()]
+    FolderPlus,
+    #[ri()]
     File,
+    #[ri()]
+    FilePlus,
+    #[ri()]
+    FileDashed,
+    #[ri()]
+    FileZip,
+    #[ri()]
+    Files,
+    #[ri()]
+    FilmStrip,
+
+    #[ri()]
+    Gear,
+    #[ri()]
+    Globe,
 
+    #[ri()]
+    HardDrive,
+    #[ri()]
+    HardDrives,
     #[ri()]
     House,
 
+    #[ri()]
+    Image,
+    #[ri()]
+    Info,
+
     #[ri()]
     Link,
+    #[ri()]
+    List,
+    #[ri()]
+    ListDashes,
+
+    #[ri()]
+    MagnifyingGlassPlus,
+    #[ri()]
+    MusicNotes,
 
     #[ri()]
     Palette,
+    #[ri()]
+    Plus,
 
     #[ri()]
     SealWarning,
+    #[ri()]
+    SquaresFour,
+
+    #[ri()]
+    Textbox,
+    #[ri()]
+    Trash,
+
+    #[ri()]
+    Usb,
+    #[ri()]
+    UsersThree,
+
+    #[ri()]
+    Warning,
+
+    #[ri()]
+    X,
 }
@@ -0,0 +1,232 @@
+//! Long running file operations on background threads, reporting progress and cancellable
+
+use std::{
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use eframe::egui::{Context, ProgressBar, Ui};
+
+use super::{icons::PhosphorIcon, journal::Operation, vfs::Location};
+
+#[derive(Default)]
+pub struct JobProgress {
+    done: AtomicU64,
+    total: AtomicU64,
+    cancelled: AtomicBool,
+    /// What got done so far, recorded even when the job fails halfway
+    operations: Mutex<Vec<Operation>>,
+}
+
+impl JobProgress {
+    pub fn set_total(&self, total: u64) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, amount: u64) {
+        self.done.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Call between units of work, jobs stop by bubbling the error up
+    pub fn check_cancelled(&self) -> io::Result<()> {
+        match self.is_cancelled() {
+            true => Err(io::Error::other("Cancelled")),
+            false => Ok(()),
+        }
+    }
+
+    pub fn record(&self, operation: Operation) {
+        self.operations
+            .lock()
+            .expect("Job operations poisoned")
+            .push(operation);
+    }
+
+    /// Everything recorded as one operation for the journal, named after the job
+    fn take_operation(&self, title: &str) -> Option<Operation> {
+        let mut operations =
+            std::mem::take(&mut *self.operations.lock().expect("Job operations poisoned"));
+
+        match operations.len() {
+            0 | 1 => operations.pop(),
+            _ => {
+                let mut chars = title.chars();
+                let label = chars
+                    .next()
+                    .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                    .unwrap_or_default();
+                Some(Operation::batch(label, operations))
+            }
+        }
+    }
+
+    /// `None` until the total is known
+    pub fn fraction(&self) -> Option<f32> {
+        match self.total.load(Ordering::Relaxed) {
+            0 => None,
+            total => Some((self.done.load(Ordering::Relaxed) as f32 / total as f32).min(1.0)),
+        }
+    }
+}
+
+/// Counts the bytes read towards the job's progress and stops once it's cancelled
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    progress: &'a JobProgress,
+}
+
+impl<'a, R> ProgressReader<'a, R> {
+    pub fn new(inner: R, progress: &'a JobProgress) -> Self {
+        Self { inner, progress }
+    }
+}
+
+impl<'a, R: Read> Read for ProgressReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.progress.check_cancelled()?;
+
+        let read = self.inner.read(buf)?;
+        self.progress.advance(read as u64);
+
+        Ok(read)
+    }
+}
+
+pub enum JobOutcome {
+    Done(String),
+    Failed(String),
+    Cancelled(String),
+}
+
+pub struct FinishedJob {
+    pub outcome: JobOutcome,
+    /// Directories the job changed, views showing them should be reloaded
+    pub dirs: Vec<Location>,
+    /// What to record in the journal
+    pub operation: Option<Operation>,
+}
+
+struct Job {
+    title: String,
+    dirs: Vec<Location>,
+    progress: Arc<JobProgress>,
+    receiver: Receiver<io::Result<String>>,
+}
+
+pub struct Jobs {
+    ctx: Context,
+    jobs: Vec<Job>,
+}
+
+impl Jobs {
+    pub fn new(ctx: Context) -> Self {
+        Self { ctx, jobs: vec![] }
+    }
+
+    /// Runs `f` on a new thread. It returns the message shown once the job is done
+    pub fn spawn(
+        &mut self,
+        title: impl Into<String>,
+        dirs: Vec<Location>,
+        f: impl FnOnce(&JobProgress) -> io::Result<String> + Send + 'static,
+    ) {
+        let title = title.into();
+        let progress = Arc::new(JobProgress::default());
+        let (sender, receiver) = mpsc::channel();
+
+        let thread_progress = progress.clone();
+        let ctx = self.ctx.clone();
+        let spawned = std::thread::Builder::new()
+            .name(format!("job: {title}"))
+            .spawn(move || {
+                let _ = sender.send(f(&thread_progress));
+                ctx.request_repaint();
+            });
+
+        match spawned {
+            Ok(_) => self.jobs.push(Job {
+                title,
+                dirs,
+                progress,
+                receiver,
+            }),
+            Err(err) => tracing::error!("Failed to start {title}: {err}"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// Removes and returns the jobs that are no longer running
+    pub fn finished(&mut self) -> Vec<FinishedJob> {
+        let mut finished = vec![];
+
+        self.jobs.retain(|job| {
+            let result = match job.receiver.try_recv() {
+                Ok(result) => result,
+                Err(mpsc::TryRecvError::Empty) => return true,
+                Err(mpsc::TryRecvError::Disconnected) => Err(io::Error::other("The job panicked")),
+            };
+
+            let outcome = match result {
+                Ok(message) => JobOutcome::Done(message),
+                Err(_) if job.progress.is_cancelled() => {
+                    JobOutcome::Cancelled(format!("Cancelled {}", job.title))
+                }
+                Err(err) => JobOutcome::Failed(format!("Failed to {}: {err}", job.title)),
+            };
+            finished.push(FinishedJob {
+                outcome,
+                dirs: job.dirs.clone(),
+                operation: job.progress.take_operation(&job.title),
+            });
+
+            false
+        });
+
+        finished
+    }
+
+    /// Progress bar and cancel button for every running job
+    pub fn ui(&self, ui: &mut Ui) {
+        self.jobs.iter().for_each(|job| {
+            ui.horizontal(|ui| {
+                if ui
+                    .small_button(PhosphorIcon::X.rich_text())
+                    .on_hover_text("Cancel")
+                    .clicked()
+                {
+                    job.progress.cancel();
+                }
+
+                let bar = match job.progress.fraction() {
+                    Some(fraction) => ProgressBar::new(fraction).text(format!(
+                        "{} ({:.0}%)",
+                        job.title,
+                        fraction * 100.0
+                    )),
+                    None => ProgressBar::new(0.0).animate(true).text(&job.title),
+                };
+                ui.add(bar);
+            });
+        });
+
+        if !self.jobs.is_empty() {
+            ui.ctx().request_repaint_after(Duration::from_millis(100));
+        }
+    }
+}
@@ -0,0 +1,668 @@
+//! Undo and redo for the file operations done through the UI.
+//!
+//! Operations remember what the file they left behind looked like, so undoing refuses to touch
+//! files that changed since. Nothing is ever deleted: undoing a copy or a new file trashes it
+
+use std::{
+    io::{self, ErrorKind},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Local};
+
+use super::{
+    jobs::JobProgress,
+    trash::{self, Trashed},
+    vfs::{self, Location},
+};
+
+/// Oldest operations are forgotten past this many
+const MAX_HISTORY: usize = 100;
+
+/// What a file looked like right after an operation
+#[derive(Clone, PartialEq)]
+pub struct Stamp {
+    is_dir: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Stamp {
+    /// `None` when the file can't be stat'ed
+    fn of(location: &Location) -> Option<Self> {
+        let meta = location
+            .backend()
+            .and_then(|vfs| vfs.stat(location.path()))
+            .ok()?;
+
+        Some(Self {
+            is_dir: meta.file_type.is_dir(),
+            size: meta.size,
+            modified: meta.modified,
+        })
+    }
+}
+
+/// A finished operation, with what's needed to invert it.
+/// Stamps describe the file where it is now, `None` ones aren't checked
+#[derive(Clone)]
+pub enum Operation {
+    /// Renames too
+    Move {
+        from: Location,
+        to: Location,
+        stamp: Option<Stamp>,
+    },
+    /// `trashed` is where the copy went while it's undone
+    Copy {
+        from: Location,
+        to: Location,
+        stamp: Option<Stamp>,
+        trashed: Option<Trashed>,
+    },
+    /// `trashed` is where the new file went while it's undone
+    Create {
+        location: Location,
+        stamp: Option<Stamp>,
+        trashed: Option<Trashed>,
+    },
+    /// `trashed` is `None` while it's undone
+    Trash {
+        location: Location,
+        stamp: Option<Stamp>,
+        trashed: Option<Trashed>,
+    },
+    /// Undone and redone together, e.g. a batch rename
+    Batch {
+        label: String,
+        operations: Vec<Operation>,
+    },
+}
+
+impl Operation {
+    /// Call once `to` is there
+    pub fn moved(from: Location, to: Location) -> Self {
+        Operation::Move {
+            stamp: Stamp::of(&to),
+            from,
+            to,
+        }
+    }
+
+    /// Call once `to` is there
+    pub fn copied(from: Location, to: Location) -> Self {
+        Operation::Copy {
+            stamp: Stamp::of(&to),
+            from,
+            to,
+            trashed: None,
+        }
+    }
+
+    pub fn created(location: Location) -> Self {
+        Operation::Create {
+            stamp: Stamp::of(&location),
+            location,
+            trashed: None,
+        }
+    }
+
+    pub fn trashed(trashed: Trashed) -> Self {
+        Operation::Trash {
+            location: Location::local(trashed.original.clone()),
+            stamp: Stamp::of(&Location::local(trashed.file.clone())),
+            trashed: Some(trashed),
+        }
+    }
+
+    pub fn batch(label: impl Into<String>, operations: Vec<Operation>) -> Self {
+        Operation::Batch {
+            label: label.into(),
+            operations,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Operation::Move { from, to, .. } => match from.parent() == to.parent() {
+                true => format!("Rename {} to {}", name(from), name(to)),
+                false => format!("Move {} to {}", name(from), parent_name(to)),
+            },
+            Operation::Copy { from, to, .. } => {
+                format!("Copy {} to {}", name(from), parent_name(to))
+            }
+            Operation::Create { location, .. } => format!("Create {}", name(location)),
+            Operation::Trash { location, .. } => format!("Trash {}", name(location)),
+            Operation::Batch { label, .. } => label.clone(),
+        }
+    }
+
+    /// Directories whose listings the operation changes
+    pub fn dirs(&self) -> Vec<Location> {
+        match self {
+            Operation::Move { from, to, .. } | Operation::Copy { from, to, .. } => [from, to]
+                .into_iter()
+                .filter_map(Location::parent)
+                .collect(),
+            Operation::Create { location, .. } | Operation::Trash { location, .. } => {
+                location.parent().into_iter().collect()
+            }
+            Operation::Batch { operations, .. } => operations
+                .iter()
+                .flat_map(Operation::dirs)
+                .fold(vec![], |mut dirs, dir| {
+                    if !dirs.contains(&dir) {
+                        dirs.push(dir);
+                    }
+                    dirs
+                }),
+        }
+    }
+
+    /// Errs when undoing would clobber something
+    fn check_undo(&self) -> io::Result<()> {
+        match self {
+            Operation::Move { from, to, stamp } => {
+                check_unchanged(to, stamp)?;
+                check_free(from)
+            }
+            Operation::Copy { to, stamp, .. } => check_unchanged(to, stamp),
+            Operation::Create {
+                location, stamp, ..
+            } => check_unchanged(location, stamp),
+            Operation::Trash {
+                location, trashed, ..
+            } => {
+                check_trashed(trashed)?;
+                check_free(location)
+            }
+            // Earlier steps change what later ones find, each checks itself as it goes
+            Operation::Batch { .. } => Ok(()),
+        }
+    }
+
+    /// Errs when redoing would clobber something
+    fn check_redo(&self) -> io::Result<()> {
+        match self {
+            Operation::Move { from, to, stamp } => {
+                check_unchanged(from, stamp)?;
+                check_free(to)
+            }
+            Operation::Copy { to, trashed, .. } => {
+                check_trashed(trashed)?;
+                check_free(to)
+            }
+            Operation::Create {
+                location, trashed, ..
+            } => {
+                check_trashed(trashed)?;
+                check_free(location)
+            }
+            Operation::Trash {
+                location, stamp, ..
+            } => check_unchanged(location, stamp),
+            Operation::Batch { .. } => Ok(()),
+        }
+    }
+
+    /// Checks everything before touching anything, moves and restores never overwrite on top.
+    /// A batch checks each step before it and redoes what it already undid when one fails
+    pub fn undo(&mut self) -> io::Result<()> {
+        self.check_undo()?;
+
+        match self {
+            Operation::Move { from, to, stamp } => {
+                vfs::move_to(to, from, &JobProgress::default())?;
+                *stamp = Stamp::of(from);
+            }
+            Operation::Copy {
+                to: location,
+                trashed,
+                ..
+            }
+            | Operation::Create {
+                location, trashed, ..
+            } => *trashed = Some(trash_location(location)?),
+            Operation::Trash {
+                location,
+                stamp,
+                trashed,
+            } => {
+                trash::restore(trashed.as_ref().expect("Checked above"))?;
+                *trashed = None;
+                *stamp = Stamp::of(location);
+            }
+            Operation::Batch { operations, .. } => {
+                for i in (0..operations.len()).rev() {
+                    if let Err(err) = operations[i].undo() {
+                        operations[i + 1..].iter_mut().for_each(|undone| {
+                            if let Err(err) = undone.redo() {
+                                tracing::error!("Failed to redo {}: {err}", undone.describe());
+                            }
+                        });
+                        return Err(err);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Same as [`Self::undo`] the other way around
+    pub fn redo(&mut self) -> io::Result<()> {
+        self.check_redo()?;
+
+        match self {
+            Operation::Move { from, to, stamp } => {
+                vfs::move_to(from, to, &JobProgress::default())?;
+                *stamp = Stamp::of(to);
+            }
+            Operation::Copy {
+                to: location,
+                stamp,
+                trashed,
+                ..
+            }
+            | Operation::Create {
+                location,
+                stamp,
+                trashed,
+            } => {
+                trash::restore(trashed.as_ref().expect("Checked above"))?;
+                *trashed = None;
+                *stamp = Stamp::of(location);
+            }
+            Operation::Trash {
+                location,
+                stamp,
+                trashed,
+            } => {
+                let new_trashed = trash_location(location)?;
+                *stamp = Stamp::of(&Location::local(new_trashed.file.clone()));
+                *trashed = Some(new_trashed);
+            }
+            Operation::Batch { operations, .. } => {
+                for i in 0..operations.len() {
+                    if let Err(err) = operations[i].redo() {
+                        operations[..i].iter_mut().rev().for_each(|redone| {
+                            if let Err(err) = redone.undo() {
+                                tracing::error!("Failed to undo {}: {err}", redone.describe());
+                            }
+                        });
+                        return Err(err);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn check_unchanged(location: &Location, stamp: &Option<Stamp>) -> io::Result<()> {
+    let Some(stamp) = stamp else {
+        return Ok(());
+    };
+
+    match Stamp::of(location) {
+        None => Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("{location} is gone"),
+        )),
+        Some(current) if &current != stamp => Err(io::Error::other(format!(
+            "{location} changed since, leaving it alone"
+        ))),
+        Some(_) => Ok(()),
+    }
+}
+
+fn check_free(location: &Location) -> io::Result<()> {
+    match Stamp::of(location) {
+        Some(_) => Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("Something else is at {location} now"),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn check_trashed(trashed: &Option<Trashed>) -> io::Result<()> {
+    match trashed {
+        Some(trashed) if trashed.file.symlink_metadata().is_ok() => Ok(()),
+        Some(trashed) => Err(io::Error::new(
+            ErrorKind::NotFound,
+            format!("{:?} isn't in the trash anymore", trashed.original),
+        )),
+        None => Err(io::Error::other("Nothing was trashed")),
+    }
+}
+
+fn trash_location(location: &Location) -> io::Result<Trashed> {
+    match location.is_local() {
+        true => trash::trash(location.path()),
+        false => Err(io::Error::new(
+            ErrorKind::Unsupported,
+            format!("Only local files can go to the trash, {location} isn't"),
+        )),
+    }
+}
+
+/// A recorded operation and when it happened
+pub struct Entry {
+    pub operation: Operation,
+    pub at: DateTime<Local>,
+}
+
+#[derive(Default)]
+pub struct Journal {
+    /// Oldest first
+    done: Vec<Entry>,
+    /// Most recently undone last
+    undone: Vec<Entry>,
+    /// Operations ever recorded, tells [`replay`] what was recorded while it ran
+    recorded: usize,
+}
+
+impl Journal {
+    /// Recording something new forgets what was undone
+    pub fn record(&mut self, operation: Operation) {
+        self.done.push(Entry {
+            operation,
+            at: Local::now(),
+        });
+        self.undone.clear();
+        self.recorded += 1;
+
+        if self.done.len() > MAX_HISTORY {
+            self.done.remove(0);
+        }
+    }
+
+    pub fn done(&self) -> &[Entry] {
+        &self.done
+    }
+
+    pub fn undone(&self) -> &[Entry] {
+        &self.undone
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    /// Takes the newest operation to undo, or to redo when `redo`, for [`replay`].
+    /// Also returns the [recorded](Self::record) count to hand back with it
+    fn take(&mut self, redo: bool) -> Option<(Entry, usize)> {
+        let entry = match redo {
+            false => self.done.pop(),
+            true => self.undone.pop(),
+        };
+        entry.map(|entry| (entry, self.recorded))
+    }
+
+    /// Hands back what [`Self::take`] took, to the other side once it `ran`. Operations recorded
+    /// since stay newer, and forgot what was undone, so an undone entry is dropped with the rest
+    fn put_back(&mut self, entry: Entry, taken_at: usize, redo: bool, ran: bool) {
+        let recorded_since = self.recorded - taken_at;
+        match redo == ran {
+            true => {
+                let at = self.done.len().saturating_sub(recorded_since);
+                self.done.insert(at, entry);
+                if self.done.len() > MAX_HISTORY {
+                    self.done.remove(0);
+                }
+            }
+            false if recorded_since > 0 => {}
+            false => self.undone.push(entry),
+        }
+    }
+}
+
+/// Undoes the newest operation, or redoes the last undone one when `redo`. The journal isn't
+/// locked while it runs, one that fails stays where it was. `None` when there's nothing to
+pub fn replay(journal: &Mutex<Journal>, redo: bool) -> Option<io::Result<Operation>> {
+    let (mut entry, taken_at) = journal.lock().expect("Journal poisoned").take(redo)?;
+
+    let res = match redo {
+        false => entry.operation.undo(),
+        true => entry.operation.redo(),
+    };
+    let operation = entry.operation.clone();
+    journal
+        .lock()
+        .expect("Journal poisoned")
+        .put_back(entry, taken_at, redo, res.is_ok());
+
+    Some(res.map(|()| operation))
+}
+
+fn name(location: &Location) -> String {
+    location
+        .path()
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| location.to_string())
+}
+
+fn parent_name(location: &Location) -> String {
+    location
+        .parent()
+        .map_or_else(|| location.to_string(), |parent| name(&parent))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Once};
+
+    use crate::utils::fs::TestDir;
+
+    use super::*;
+
+    /// Trashed files go to a trash in the temp dir rather than the user's
+    fn test_dir(name: &str) -> TestDir {
+        static DATA_HOME: Once = Once::new();
+        DATA_HOME.call_once(|| {
+            let data_home = std::env::temp_dir().join(format!("rsfm-data-{}", std::process::id()));
+            std::env::set_var("XDG_DATA_HOME", data_home);
+        });
+
+        TestDir::new(&format!("journal-{name}"))
+    }
+
+    fn location(dir: &TestDir, name: &str) -> Location {
+        Location::local(dir.0.join(name))
+    }
+
+    fn read(location: &Location) -> Option<String> {
+        fs::read_to_string(location.path()).ok()
+    }
+
+    fn write(location: &Location, contents: &str) {
+        fs::write(location.path(), contents).unwrap();
+    }
+
+    fn moved(from: &Location, to: &Location) -> Operation {
+        vfs::move_to(from, to, &JobProgress::default()).unwrap();
+        Operation::moved(from.clone(), to.clone())
+    }
+
+    #[test]
+    fn undoes_and_redoes_moves() {
+        let dir = test_dir("move");
+        let (a, b) = (location(&dir, "a"), location(&dir, "b"));
+        write(&a, "a");
+
+        let mut operation = moved(&a, &b);
+        operation.undo().unwrap();
+        assert_eq!((read(&a).as_deref(), read(&b)), (Some("a"), None));
+
+        operation.redo().unwrap();
+        assert_eq!((read(&a), read(&b).as_deref()), (None, Some("a")));
+    }
+
+    #[test]
+    fn undoes_and_redoes_copies() {
+        let dir = test_dir("copy");
+        let (a, b) = (location(&dir, "a"), location(&dir, "b"));
+        write(&a, "a");
+        vfs::copy(&a, &b, &JobProgress::default()).unwrap();
+
+        let mut operation = Operation::copied(a.clone(), b.clone());
+        operation.undo().unwrap();
+        assert_eq!((read(&a).as_deref(), read(&b)), (Some("a"), None));
+
+        operation.redo().unwrap();
+        assert_eq!(
+            (read(&a).as_deref(), read(&b).as_deref()),
+            (Some("a"), Some("a"))
+        );
+    }
+
+    #[test]
+    fn undoes_and_redoes_creates() {
+        let dir = test_dir("create");
+        let new = location(&dir, "new");
+        fs::create_dir(new.path()).unwrap();
+
+        let mut operation = Operation::created(new.clone());
+        operation.undo().unwrap();
+        assert!(new.path().symlink_metadata().is_err());
+
+        operation.redo().unwrap();
+        assert!(new.path().is_dir());
+    }
+
+    #[test]
+    fn undoes_and_redoes_trashing() {
+        let dir = test_dir("trash");
+        let a = location(&dir, "a");
+        write(&a, "a");
+
+        let mut operation = Operation::trashed(trash::trash(a.path()).unwrap());
+        assert_eq!(read(&a), None);
+
+        operation.undo().unwrap();
+        assert_eq!(read(&a).as_deref(), Some("a"));
+
+        operation.redo().unwrap();
+        assert_eq!(read(&a), None);
+        operation.undo().unwrap();
+    }
+
+    #[test]
+    fn refuses_changed_files() {
+        let dir = test_dir("changed");
+        let (a, b) = (location(&dir, "a"), location(&dir, "b"));
+        write(&a, "a");
+
+        let mut operation = moved(&a, &b);
+        write(&b, "changed since");
+
+        assert!(operation.undo().is_err());
+        assert_eq!(
+            (read(&a), read(&b).as_deref()),
+            (None, Some("changed since"))
+        );
+    }
+
+    #[test]
+    fn refuses_occupied_targets() {
+        let dir = test_dir("occupied");
+        let (a, b) = (location(&dir, "a"), location(&dir, "b"));
+        write(&a, "a");
+
+        let mut operation = moved(&a, &b);
+        write(&a, "new a");
+
+        let err = operation.undo().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(
+            (read(&a).as_deref(), read(&b).as_deref()),
+            (Some("new a"), Some("a"))
+        );
+    }
+
+    #[test]
+    fn puts_batches_back_when_they_fail_halfway() {
+        let dir = test_dir("batch");
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|name| location(&dir, name));
+        write(&a, "a");
+        write(&c, "c");
+
+        let mut operation = Operation::batch("Move 2 files", vec![moved(&a, &b), moved(&c, &d)]);
+        // Undoing d goes through, then b can't go back
+        write(&a, "new a");
+
+        assert!(operation.undo().is_err());
+        assert_eq!((read(&c), read(&d).as_deref()), (None, Some("c")));
+        assert_eq!(read(&b).as_deref(), Some("a"));
+
+        fs::remove_file(a.path()).unwrap();
+        operation.undo().unwrap();
+        assert_eq!(
+            (read(&a).as_deref(), read(&c).as_deref()),
+            (Some("a"), Some("c"))
+        );
+    }
+
+    #[test]
+    fn replays_through_the_journal() {
+        let dir = test_dir("replay");
+        let (a, b) = (location(&dir, "a"), location(&dir, "b"));
+        write(&a, "a");
+
+        let journal = Mutex::new(Journal::default());
+        journal.lock().unwrap().record(moved(&a, &b));
+
+        write(&a, "new a");
+        assert!(replay(&journal, false).unwrap().is_err());
+        assert!(journal.lock().unwrap().can_undo());
+
+        fs::remove_file(a.path()).unwrap();
+        let operation = replay(&journal, false).unwrap().unwrap();
+        assert_eq!(operation.describe(), "Rename a to b");
+        assert!(!journal.lock().unwrap().can_undo());
+        assert!(replay(&journal, false).is_none());
+
+        replay(&journal, true).unwrap().unwrap();
+        assert_eq!(read(&b).as_deref(), Some("a"));
+        assert!(journal.lock().unwrap().can_undo());
+    }
+
+    #[test]
+    fn keeps_operations_recorded_during_a_replay_newest() {
+        let created = |name: &str| Operation::created(Location::local(format!("/missing/{name}")));
+        let describe = |entries: &[Entry]| {
+            entries
+                .iter()
+                .map(|entry| entry.operation.describe())
+                .collect::<Vec<_>>()
+        };
+
+        // An undo that failed goes back under what was recorded meanwhile
+        let mut journal = Journal::default();
+        journal.record(created("a"));
+        let (entry, taken_at) = journal.take(false).unwrap();
+        journal.record(created("b"));
+        journal.put_back(entry, taken_at, false, false);
+        assert_eq!(describe(journal.done()), ["Create a", "Create b"]);
+
+        // One that ran would be redone on top of the new operation, so it's forgotten
+        let (entry, taken_at) = journal.take(false).unwrap();
+        journal.record(created("c"));
+        journal.put_back(entry, taken_at, false, true);
+        assert_eq!(describe(journal.done()), ["Create a", "Create c"]);
+        assert!(!journal.can_redo());
+
+        // Without anything recorded it can be redone
+        let (entry, taken_at) = journal.take(false).unwrap();
+        journal.put_back(entry, taken_at, false, true);
+        assert_eq!(describe(journal.undone()), ["Create c"]);
+    }
+}
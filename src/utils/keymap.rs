@@ -0,0 +1,181 @@
+//! Keyboard shortcuts as they're written in the config, e.g. `Ctrl+Shift+Z`
+
+use std::{fmt::Display, str::FromStr};
+
+use eframe::egui::{Context, Key, KeyboardShortcut, Modifiers};
+use serde::{Deserialize, Serialize};
+
+/// Every key a shortcut can end with, egui can't look them up by name
+const KEYS: [Key; 73] = [
+    Key::ArrowDown,
+    Key::ArrowLeft,
+    Key::ArrowRight,
+    Key::ArrowUp,
+    Key::Escape,
+    Key::Tab,
+    Key::Backspace,
+    Key::Enter,
+    Key::Space,
+    Key::Insert,
+    Key::Delete,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+    Key::Minus,
+    Key::PlusEquals,
+    Key::Num0,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+    Key::F13,
+    Key::F14,
+    Key::F15,
+    Key::F16,
+    Key::F17,
+    Key::F18,
+    Key::F19,
+    Key::F20,
+];
+
+/// Modifier names in the order they're written
+const MODIFIERS: [(&str, Modifiers); 4] = [
+    ("Ctrl", Modifiers::CTRL),
+    ("Alt", Modifiers::ALT),
+    ("Shift", Modifiers::SHIFT),
+    ("Cmd", Modifiers::MAC_CMD),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Shortcut(KeyboardShortcut);
+
+impl Shortcut {
+    pub const fn new(modifiers: Modifiers, key: Key) -> Self {
+        Self(KeyboardShortcut::new(modifiers, key))
+    }
+
+    /// Whether it was pressed this frame, so nothing else reacts to it
+    pub fn consume(&self, ctx: &Context) -> bool {
+        ctx.input_mut(|i| i.consume_shortcut(&self.0))
+    }
+}
+
+impl Display for Shortcut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        MODIFIERS
+            .iter()
+            .filter(|(_, modifier)| self.0.modifiers.contains(*modifier))
+            .try_for_each(|(name, _)| write!(f, "{name}+"))?;
+
+        write!(f, "{}", self.0.key.name())
+    }
+}
+
+impl FromStr for Shortcut {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('+').map(str::trim).collect::<Vec<_>>();
+        let key = parts.pop().filter(|key| !key.is_empty());
+        let key = key
+            .and_then(|key| {
+                KEYS.into_iter()
+                    .find(|k| k.name().eq_ignore_ascii_case(key))
+            })
+            .ok_or_else(|| format!("{s:?} doesn't end with a key"))?;
+
+        let modifiers = parts
+            .into_iter()
+            .try_fold(Modifiers::NONE, |modifiers, part| {
+                MODIFIERS
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(part))
+                    .map(|(_, modifier)| modifiers | *modifier)
+                    .ok_or_else(|| format!("Unknown modifier {part:?} in {s:?}"))
+            })?;
+
+        Ok(Self::new(modifiers, key))
+    }
+}
+
+impl TryFrom<String> for Shortcut {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Shortcut> for String {
+    fn from(value: Shortcut) -> Self {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_writes_shortcuts() {
+        let shortcut: Shortcut = "ctrl + shift+z".parse().unwrap();
+        assert!(shortcut == Shortcut::new(Modifiers::CTRL | Modifiers::SHIFT, Key::Z));
+        assert_eq!(shortcut.to_string(), "Ctrl+Shift+Z");
+
+        let shortcut: Shortcut = "Delete".parse().unwrap();
+        assert!(shortcut == Shortcut::new(Modifiers::NONE, Key::Delete));
+        assert_eq!(shortcut.to_string(), "Delete");
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_modifiers() {
+        assert!("Ctrl+".parse::<Shortcut>().is_err());
+        assert!("Ctrl+Banana".parse::<Shortcut>().is_err());
+        assert!("Hyper+Z".parse::<Shortcut>().is_err());
+    }
+}
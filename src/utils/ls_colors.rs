@@ -0,0 +1,369 @@
+//! File colors the way `ls --color` picks them, from `LS_COLORS` or a `dircolors` database.
+//!
+//! The 16 basic terminal colors map onto the theme's palette, so they follow theme changes
+
+use std::{collections::HashMap, io, path::Path, sync::RwLock};
+
+use eframe::epaint::Color32;
+use lazy_static::lazy_static;
+use libc::{
+    S_IFBLK, S_IFCHR, S_IFIFO, S_IFMT, S_IFSOCK, S_ISGID, S_ISUID, S_ISVTX, S_IWOTH, S_IXGRP,
+    S_IXOTH, S_IXUSR,
+};
+
+use super::{
+    fs::{FileData, FileKind},
+    theme::Theme,
+};
+
+lazy_static! {
+    static ref LS_COLORS: RwLock<Option<LsColors>> = RwLock::new(None);
+}
+
+/// Replaces the colors listings are styled with, `None` leaves files to the theme
+pub fn set(colors: Option<LsColors>) {
+    *LS_COLORS.write().expect("LS_COLORS poisoned") = colors;
+}
+
+/// `None` without colors or when none match the file
+pub fn style(file_data: &FileData, kind: FileKind) -> Option<LsStyle> {
+    LS_COLORS
+        .read()
+        .expect("LS_COLORS poisoned")
+        .as_ref()?
+        .style(file_data, kind)
+}
+
+/// An SGR color, `Ansi` covers the 256 color palette
+#[derive(Clone, Copy, PartialEq)]
+pub enum LsColor {
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl LsColor {
+    pub fn resolve(self, theme: &Theme) -> Color32 {
+        match self {
+            LsColor::Ansi(n @ 0..=15) => [
+                theme.surface1,
+                theme.red,
+                theme.green,
+                theme.yellow,
+                theme.blue,
+                theme.pink,
+                theme.teal,
+                theme.subtext1,
+                theme.surface2,
+                theme.red,
+                theme.green,
+                theme.yellow,
+                theme.blue,
+                theme.pink,
+                theme.teal,
+                theme.subtext0,
+            ][n as usize],
+            LsColor::Ansi(n @ 16..=231) => {
+                let n = n - 16;
+                let level = |c: u8| match c {
+                    0 => 0,
+                    c => 55 + c * 40,
+                };
+                Color32::from_rgb(level(n / 36), level(n / 6 % 6), level(n % 6))
+            }
+            LsColor::Ansi(n) => {
+                let level = 8 + (n - 232) * 10;
+                Color32::from_rgb(level, level, level)
+            }
+            LsColor::Rgb(r, g, b) => Color32::from_rgb(r, g, b),
+        }
+    }
+}
+
+/// The colors of an `LS_COLORS` entry, other attributes like bold are ignored
+#[derive(Default, Clone, Copy, PartialEq)]
+pub struct LsStyle {
+    fg: Option<LsColor>,
+    bg: Option<LsColor>,
+}
+
+impl LsStyle {
+    /// Parses SGR codes, e.g. `01;38;5;208`
+    fn parse(codes: &str) -> Self {
+        let mut style = Self::default();
+        let mut codes = codes.split(';').map(|code| match code {
+            "" => Some(0),
+            code => code.parse::<u8>().ok(),
+        });
+
+        while let Some(code) = codes.next() {
+            let mut extended = || match codes.next().flatten() {
+                Some(5) => codes.next().flatten().map(LsColor::Ansi),
+                Some(2) => {
+                    let (r, g, b) = (codes.next()??, codes.next()??, codes.next()??);
+                    Some(LsColor::Rgb(r, g, b))
+                }
+                _ => None,
+            };
+
+            match code {
+                Some(0) => style = Self::default(),
+                Some(code @ 30..=37) => style.fg = Some(LsColor::Ansi(code - 30)),
+                Some(code @ 90..=97) => style.fg = Some(LsColor::Ansi(code - 90 + 8)),
+                Some(code @ 40..=47) => style.bg = Some(LsColor::Ansi(code - 40)),
+                Some(code @ 100..=107) => style.bg = Some(LsColor::Ansi(code - 100 + 8)),
+                Some(38) => style.fg = extended(),
+                Some(48) => style.bg = extended(),
+                Some(39) => style.fg = None,
+                Some(49) => style.bg = None,
+                _ => {}
+            }
+        }
+
+        style
+    }
+
+    fn is_plain(&self) -> bool {
+        self.fg.is_none() && self.bg.is_none()
+    }
+
+    /// Icons can't have a background, so that one wins, e.g. for setuid files
+    pub fn icon_color(&self, theme: &Theme) -> Option<Color32> {
+        self.bg.or(self.fg).map(|color| color.resolve(theme))
+    }
+
+    pub fn text_color(&self, theme: &Theme) -> Option<Color32> {
+        self.fg.map(|color| color.resolve(theme))
+    }
+
+    pub fn background(&self, theme: &Theme) -> Option<Color32> {
+        self.bg.map(|color| color.resolve(theme))
+    }
+}
+
+/// A parsed `LS_COLORS`
+#[derive(Default)]
+pub struct LsColors {
+    /// Keyed by the two letter indicators, e.g. `di` for directories
+    indicators: HashMap<String, LsStyle>,
+    /// Lowercase `*.ext` and `*suffix` entries, later ones win
+    suffixes: Vec<(String, LsStyle)>,
+    /// `ln=target`, symlinks take their target's style
+    links_as_target: bool,
+}
+
+impl LsColors {
+    /// `None` when the variable isn't set
+    pub fn from_env() -> Option<Self> {
+        std::env::var("LS_COLORS")
+            .ok()
+            .filter(|ls_colors| !ls_colors.is_empty())
+            .map(|ls_colors| Self::parse(&ls_colors))
+    }
+
+    /// `di=01;34:ln=01;36:*.tar=01;31:...`
+    pub fn parse(ls_colors: &str) -> Self {
+        let mut res = Self::default();
+
+        ls_colors
+            .split(':')
+            .filter_map(|entry| entry.split_once('='))
+            .for_each(|(key, codes)| res.insert(key, codes));
+
+        res
+    }
+
+    /// A `dircolors` database, see `dircolors --print-database`.
+    /// `TERM` sections aren't checked, every entry applies
+    pub fn load_dircolors(path: &Path) -> io::Result<Self> {
+        let src = std::fs::read_to_string(path)?;
+        let mut res = Self::default();
+
+        src.lines()
+            .map(|line| line.split_once('#').map_or(line, |(line, _comment)| line))
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                Some((words.next()?, words.next()?))
+            })
+            .for_each(|(keyword, codes)| {
+                let key = match keyword.to_ascii_uppercase().as_str() {
+                    "NORMAL" | "NORM" => "no",
+                    "FILE" => "fi",
+                    "DIR" => "di",
+                    "LINK" | "LNK" | "SYMLINK" => "ln",
+                    "ORPHAN" => "or",
+                    "MISSING" => "mi",
+                    "FIFO" | "PIPE" => "pi",
+                    "SOCK" => "so",
+                    "BLK" | "BLOCK" => "bd",
+                    "CHR" | "CHAR" => "cd",
+                    "SETUID" => "su",
+                    "SETGID" => "sg",
+                    "STICKY_OTHER_WRITABLE" | "OWT" => "tw",
+                    "OTHER_WRITABLE" | "OWR" => "ow",
+                    "STICKY" => "st",
+                    "EXEC" => "ex",
+                    _ if keyword.starts_with('.') => {
+                        res.insert(&format!("*{keyword}"), codes);
+                        return;
+                    }
+                    _ if keyword.starts_with('*') => {
+                        res.insert(keyword, codes);
+                        return;
+                    }
+                    // TERM, COLOR, OPTIONS, ...
+                    _ => return,
+                };
+                res.insert(key, codes);
+            });
+
+        Ok(res)
+    }
+
+    fn insert(&mut self, key: &str, codes: &str) {
+        match key.strip_prefix('*') {
+            Some(suffix) => self
+                .suffixes
+                .push((suffix.to_lowercase(), LsStyle::parse(codes))),
+            None if key == "ln" && codes == "target" => self.links_as_target = true,
+            None => {
+                self.indicators
+                    .insert(key.to_string(), LsStyle::parse(codes));
+            }
+        }
+    }
+
+    fn indicator(&self, key: &str) -> Option<LsStyle> {
+        self.indicators
+            .get(key)
+            .copied()
+            .filter(|style| !style.is_plain())
+    }
+
+    fn suffix(&self, name: &str) -> Option<LsStyle> {
+        let name = name.to_lowercase();
+
+        self.suffixes
+            .iter()
+            .rev()
+            .find(|(suffix, _)| name.ends_with(suffix.as_str()))
+            .map(|(_, style)| *style)
+            .filter(|style| !style.is_plain())
+    }
+
+    /// Follows the precedence of GNU `ls`: permissions before extensions
+    fn style(&self, file_data: &FileData, kind: FileKind) -> Option<LsStyle> {
+        let mode = file_data.mode().unwrap_or_default();
+        let has = |bits: u32| mode & bits == bits;
+
+        match kind {
+            FileKind::BrokenSymlink => self.indicator("or").or_else(|| self.indicator("ln")),
+            FileKind::Symlink if self.links_as_target => {
+                self.style(file_data, file_data.target_kind())
+            }
+            FileKind::Symlink => self.indicator("ln"),
+            FileKind::Dir => {
+                let by_permissions = match (has(S_ISVTX), has(S_IWOTH)) {
+                    (true, true) => self.indicator("tw"),
+                    (false, true) => self.indicator("ow"),
+                    (true, false) => self.indicator("st"),
+                    (false, false) => None,
+                };
+
+                by_permissions.or_else(|| self.indicator("di"))
+            }
+            FileKind::File | FileKind::Archive => {
+                let by_permissions = if has(S_ISUID) {
+                    self.indicator("su")
+                } else if has(S_ISGID) {
+                    self.indicator("sg")
+                } else if mode & (S_IXUSR | S_IXGRP | S_IXOTH) != 0 {
+                    self.indicator("ex")
+                } else {
+                    None
+                };
+
+                by_permissions
+                    .or_else(|| self.suffix(file_data.name()))
+                    .or_else(|| self.indicator("fi"))
+            }
+            FileKind::Unknown => match mode & S_IFMT {
+                S_IFIFO => self.indicator("pi"),
+                S_IFSOCK => self.indicator("so"),
+                S_IFBLK => self.indicator("bd"),
+                S_IFCHR => self.indicator("cd"),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::vfs::{VfsEntry, VfsFileType, VfsMetadata};
+
+    use super::*;
+
+    fn file(name: &str, file_type: VfsFileType, link_target: Option<&str>) -> FileData {
+        let entry = VfsEntry {
+            path: format!("/{name}").into(),
+            meta: VfsMetadata {
+                file_type,
+                size: 0,
+                modified: None,
+                read_only: false,
+                link_target: link_target.map(Into::into),
+                mode: Some(0o644),
+            },
+        };
+        FileData::from_entry("ls-colors-test", entry)
+    }
+
+    fn fg(colors: &LsColors, file_data: &FileData) -> Option<LsColor> {
+        colors.style(file_data, file_data.kind())?.fg
+    }
+
+    #[test]
+    fn parses_sgr_codes() {
+        let style = LsStyle::parse("01;38;5;208;48;2;1;2;3");
+        assert!(style.fg == Some(LsColor::Ansi(208)));
+        assert!(style.bg == Some(LsColor::Rgb(1, 2, 3)));
+
+        let style = LsStyle::parse("34;0;91");
+        assert!(style.fg == Some(LsColor::Ansi(9)) && style.bg.is_none());
+    }
+
+    #[test]
+    fn colors_directories() {
+        let colors = LsColors::parse("di=01;34:fi=0");
+        let dir = file("docs", VfsFileType::Dir, None);
+
+        assert!(fg(&colors, &dir) == Some(LsColor::Ansi(4)));
+        // Plain entries leave files to the theme
+        assert!(fg(&colors, &file("a.txt", VfsFileType::File, None)).is_none());
+    }
+
+    #[test]
+    fn colors_extensions_case_insensitively() {
+        let colors = LsColors::parse("*.tar=01;31:*.TXT=32:*README=33");
+
+        let color = |name| fg(&colors, &file(name, VfsFileType::File, None));
+        assert!(color("backup.TAR") == Some(LsColor::Ansi(1)));
+        assert!(color("notes.txt") == Some(LsColor::Ansi(2)));
+        assert!(color("README") == Some(LsColor::Ansi(3)));
+        assert!(color("main.rs").is_none());
+    }
+
+    #[test]
+    fn colors_links_as_their_targets() {
+        let link = file("docs", VfsFileType::Dir, Some("real_docs"));
+        let broken = file("gone", VfsFileType::Symlink, Some("missing"));
+
+        let colors = LsColors::parse("di=34:ln=36:or=31");
+        assert!(fg(&colors, &link) == Some(LsColor::Ansi(6)));
+        assert!(fg(&colors, &broken) == Some(LsColor::Ansi(1)));
+
+        let colors = LsColors::parse("di=34:ln=target:or=31");
+        assert!(fg(&colors, &link) == Some(LsColor::Ansi(4)));
+        assert!(fg(&colors, &broken) == Some(LsColor::Ansi(1)));
+    }
+}
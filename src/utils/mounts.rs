@@ -0,0 +1,371 @@
+use std::{
+    ffi::CString,
+    fs::File,
+    os::{fd::AsRawFd, unix::ffi::OsStrExt},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+};
+
+use eframe::egui::Context;
+
+const MOUNTINFO_PATH: &str = "/proc/self/mountinfo";
+const PARTITIONS_PATH: &str = "/proc/partitions";
+
+/// How often capacities are refreshed when no mounts change
+const STATS_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Filesystems that don't hold user files
+const PSEUDO_FS_TYPES: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "efivarfs",
+    "fusectl",
+    "fuse.gvfsd-fuse",
+    "fuse.portal",
+    "hugetlbfs",
+    "mqueue",
+    "nsfs",
+    "proc",
+    "pstore",
+    "ramfs",
+    "rpc_pipefs",
+    "securityfs",
+    "selinuxfs",
+    "squashfs",
+    "sysfs",
+    "tmpfs",
+    "tracefs",
+];
+
+#[derive(Clone)]
+pub struct Mount {
+    pub source: String,
+    pub mount_point: PathBuf,
+    pub fs_type: String,
+    pub read_only: bool,
+    pub removable: bool,
+    pub label: String,
+    pub stats: Option<MountStats>,
+}
+
+#[derive(Clone, Copy)]
+pub struct MountStats {
+    pub total: u64,
+    /// Space available to unprivileged users
+    pub available: u64,
+}
+
+impl MountStats {
+    pub fn used_fraction(&self) -> f32 {
+        match self.total {
+            0 => 0.0,
+            total => 1.0 - self.available as f32 / total as f32,
+        }
+    }
+}
+
+impl Mount {
+    /// Pseudo and virtual filesystems, as well as anything not backed by a device or network share
+    pub fn is_pseudo(&self) -> bool {
+        let backed = self.source.starts_with("/dev/") || self.source.contains(':');
+
+        PSEUDO_FS_TYPES.contains(&self.fs_type.as_str()) || !backed
+    }
+}
+
+pub fn read_mounts() -> std::io::Result<Vec<Mount>> {
+    let contents = std::fs::read_to_string(MOUNTINFO_PATH)?;
+
+    Ok(parse_mountinfo(&contents)
+        .into_iter()
+        .map(|mut mount| {
+            mount.stats = mount_stats(&mount.mount_point);
+            mount
+        })
+        .collect())
+}
+
+/// Names of the block devices the kernel knows about, these change when media is plugged in
+/// or removed without anything getting mounted
+pub fn read_block_devices() -> std::io::Result<Vec<String>> {
+    Ok(parse_partitions(&std::fs::read_to_string(PARTITIONS_PATH)?))
+}
+
+/// A header followed by lines like `   8        1  524288 sda1`
+fn parse_partitions(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            fields.next()?.parse::<u32>().ok()?;
+            fields.nth(2).map(str::to_string)
+        })
+        .collect()
+}
+
+/// Every line looks like
+/// `36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue`,
+/// see `proc(5)` for the meaning of each field
+fn parse_mountinfo(contents: &str) -> Vec<Mount> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (mount_fields, fs_fields) = line.split_once(" - ")?;
+
+            let mut mount_fields = mount_fields.split(' ');
+            let mount_point = PathBuf::from(unescape_octal(mount_fields.nth(4)?));
+            let mount_options = mount_fields.next()?;
+
+            let mut fs_fields = fs_fields.split(' ');
+            let fs_type = fs_fields.next()?.to_string();
+            let source = unescape_octal(fs_fields.next()?);
+
+            let read_only = mount_options.split(',').any(|option| option == "ro");
+            let removable = source.starts_with("/dev/") && is_removable(&source);
+            let label = device_label(&source).unwrap_or_else(|| match mount_point.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => "File System".into(),
+            });
+
+            Some(Mount {
+                source,
+                mount_point,
+                fs_type,
+                read_only,
+                removable,
+                label,
+                stats: None,
+            })
+        })
+        .collect()
+}
+
+/// Spaces and other special characters are written as `\040`-like octal escapes
+fn unescape_octal(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+
+    while let Some((&b, tail)) = rest.split_first() {
+        let escaped = (b == b'\\')
+            .then(|| tail.get(..3))
+            .flatten()
+            .and_then(|oct| u8::from_str_radix(std::str::from_utf8(oct).ok()?, 8).ok());
+
+        match escaped {
+            Some(escaped) => {
+                bytes.push(escaped);
+                rest = &tail[3..];
+            }
+            None => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// Looks the device up in `/dev/disk/by-label`, where labels are escaped like `My\x20Drive`
+fn device_label(source: &str) -> Option<String> {
+    let device = std::fs::canonicalize(source).ok()?;
+
+    std::fs::read_dir("/dev/disk/by-label")
+        .ok()?
+        .flatten()
+        .find(|entry| std::fs::canonicalize(entry.path()).ok().as_ref() == Some(&device))
+        .map(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .replace("\\x20", " ")
+                .replace("\\x2f", "/")
+        })
+}
+
+/// Checks `removable` of the device in sysfs, or of its parent disk if it's a partition
+fn is_removable(source: &str) -> bool {
+    let Some(name) = Path::new(source).file_name() else {
+        return false;
+    };
+    let Ok(sys_path) = std::fs::canonicalize(Path::new("/sys/class/block").join(name)) else {
+        return false;
+    };
+
+    let removable = [Some(sys_path.as_path()), sys_path.parent()]
+        .into_iter()
+        .flatten()
+        .any(|path| {
+            std::fs::read_to_string(path.join("removable"))
+                .is_ok_and(|removable| removable.trim() == "1")
+        });
+    removable
+}
+
+fn mount_stats(mount_point: &Path) -> Option<MountStats> {
+    let path = CString::new(mount_point.as_os_str().as_bytes()).ok()?;
+
+    // SAFETY: statvfs is plain old data, all zeroes is a valid value
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is a NUL terminated string and `stat` a valid statvfs that outlive the call,
+    // its fields are only read after it succeeded
+    match unsafe { libc::statvfs(path.as_ptr(), &mut stat) } {
+        0 => Some(MountStats {
+            total: stat.f_blocks as u64 * stat.f_frsize as u64,
+            available: stat.f_bavail as u64 * stat.f_frsize as u64,
+        }),
+        _ => None,
+    }
+}
+
+/// What [`MountWatcher`] reads each time
+pub struct MountsUpdate {
+    pub mounts: Vec<Mount>,
+    /// See [`read_block_devices`]
+    pub block_devices: Vec<String>,
+}
+
+/// Re-reads the mounts on a background thread whenever the kernel reports a change in
+/// `/proc/self/mountinfo`, and periodically to keep the capacities up to date
+/// and to notice devices being plugged in
+pub struct MountWatcher {
+    receiver: Receiver<MountsUpdate>,
+}
+
+impl MountWatcher {
+    pub fn new(ctx: Context) -> std::io::Result<Self> {
+        let mountinfo = File::open(MOUNTINFO_PATH)?;
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::Builder::new()
+            .name("mount-watcher".into())
+            .spawn(move || loop {
+                match read_mounts() {
+                    Ok(mounts) => {
+                        let block_devices = read_block_devices().unwrap_or_else(|err| {
+                            tracing::error!("Failed to read block devices: {err}");
+                            vec![]
+                        });
+
+                        let update = MountsUpdate {
+                            mounts,
+                            block_devices,
+                        };
+                        if sender.send(update).is_err() {
+                            break;
+                        }
+                        ctx.request_repaint();
+                    }
+                    Err(err) => tracing::error!("Failed to read mounts: {err}"),
+                }
+
+                let mut poll_fd = libc::pollfd {
+                    fd: mountinfo.as_raw_fd(),
+                    events: libc::POLLPRI,
+                    revents: 0,
+                };
+                // The kernel signals POLLPRI | POLLERR once the mount table changes
+                // SAFETY: `poll_fd` is a single valid pollfd, matching the count of 1, and its fd
+                // stays open for the call since `mountinfo` is alive. Errors and timeouts both
+                // just mean re-reading the table
+                unsafe { libc::poll(&mut poll_fd, 1, STATS_REFRESH_INTERVAL.as_millis() as i32) };
+            })?;
+
+        Ok(Self { receiver })
+    }
+
+    /// The most recent mounts, if they were re-read since the last call
+    pub fn latest(&self) -> Option<MountsUpdate> {
+        self.receiver.try_iter().last()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mount(source: &str, fs_type: &str) -> Mount {
+        Mount {
+            source: source.into(),
+            mount_point: PathBuf::from("/mnt"),
+            fs_type: fs_type.into(),
+            read_only: false,
+            removable: false,
+            label: String::new(),
+            stats: None,
+        }
+    }
+
+    #[test]
+    fn parses_mountinfo() {
+        let mounts = parse_mountinfo(
+            "22 1 0:21 / /proc rw,nosuid - proc proc rw\n\
+             36 35 98:0 / /media/My\\040Drive ro,noatime master:1 - ext4 /dev/sdz1 ro\n\
+             garbage\n",
+        );
+
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0].mount_point, PathBuf::from("/proc"));
+        assert_eq!(mounts[0].fs_type, "proc");
+        assert!(!mounts[0].read_only);
+
+        assert_eq!(mounts[1].mount_point, PathBuf::from("/media/My Drive"));
+        assert_eq!(mounts[1].source, "/dev/sdz1");
+        assert_eq!(mounts[1].fs_type, "ext4");
+        assert!(mounts[1].read_only);
+        assert_eq!(mounts[1].label, "My Drive");
+    }
+
+    #[test]
+    fn unescapes_octal() {
+        assert_eq!(unescape_octal("a\\040b\\134c"), "a b\\c");
+        assert_eq!(unescape_octal("trailing\\04"), "trailing\\04");
+        assert_eq!(unescape_octal("\\999"), "\\999");
+    }
+
+    #[test]
+    fn parses_partitions() {
+        let devices = parse_partitions(
+            "major minor  #blocks  name\n\
+             \n\
+             \x20  8        0  976762584 sda\n\
+             \x20  8        1     524288 sda1\n\
+             \x20259        0  500107608 nvme0n1\n",
+        );
+
+        assert_eq!(devices, ["sda", "sda1", "nvme0n1"]);
+    }
+
+    #[test]
+    fn pseudo_mounts() {
+        assert!(mount("proc", "proc").is_pseudo());
+        assert!(mount("tmpfs", "tmpfs").is_pseudo());
+        assert!(mount("/dev/loop0", "squashfs").is_pseudo());
+        assert!(!mount("/dev/sda1", "ext4").is_pseudo());
+        assert!(!mount("server:/export", "nfs").is_pseudo());
+    }
+
+    #[test]
+    fn used_fraction() {
+        let stats = MountStats {
+            total: 100,
+            available: 25,
+        };
+        assert_eq!(stats.used_fraction(), 0.75);
+
+        let empty = MountStats {
+            total: 0,
+            available: 0,
+        };
+        assert_eq!(empty.used_fraction(), 0.0);
+    }
+}
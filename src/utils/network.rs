@@ -0,0 +1,115 @@
+//! Connections to remote machines and buckets from the sidebar's Network section.
+//!
+//! Connecting can take a while (DNS, key exchange, asking the agent), so [`NetworkManager`]
+//! connects on a worker thread and [registers](super::vfs::register) the filesystem once it's up
+
+use std::{
+    io,
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+};
+
+use eframe::egui::Context;
+
+use crate::config::{ConnectionKind, ConnectionProfile};
+
+use super::vfs::{self, Location, S3Vfs, SftpVfs, UnknownHostKey, Vfs, WebDavVfs};
+
+pub enum NetworkResponse {
+    /// `location` is the directory the profile opens in
+    Connected {
+        name: String,
+        location: Location,
+    },
+    /// The user has to check the server's key before connecting, see [`UnknownHostKey::trust`]
+    UnknownHostKey {
+        profile: Box<ConnectionProfile>,
+        key: UnknownHostKey,
+    },
+    Failed {
+        name: String,
+        err: io::Error,
+    },
+}
+
+pub struct NetworkManager {
+    ctx: Context,
+    sender: Sender<NetworkResponse>,
+    responses: Receiver<NetworkResponse>,
+}
+
+impl NetworkManager {
+    pub fn new(ctx: Context) -> Self {
+        let (sender, responses) = mpsc::channel();
+
+        Self {
+            ctx,
+            sender,
+            responses,
+        }
+    }
+
+    pub fn connect(&self, profile: ConnectionProfile) {
+        let sender = self.sender.clone();
+        let ctx = self.ctx.clone();
+
+        std::thread::spawn(move || {
+            let response = match open(&profile) {
+                Ok((backend, root)) => {
+                    let id = profile.vfs_id();
+                    let location = Location::new(&id, root);
+                    vfs::register(id, backend);
+
+                    NetworkResponse::Connected {
+                        name: profile.name,
+                        location,
+                    }
+                }
+                Err(err) => match unknown_host_key(&err) {
+                    Some(key) => NetworkResponse::UnknownHostKey {
+                        profile: Box::new(profile),
+                        key,
+                    },
+                    None => NetworkResponse::Failed {
+                        name: profile.name,
+                        err,
+                    },
+                },
+            };
+
+            let _ = sender.send(response);
+            ctx.request_repaint();
+        });
+    }
+
+    /// Views still showing the connection fail to list until it's connected again
+    pub fn disconnect(&self, profile: &ConnectionProfile) {
+        vfs::unregister(&profile.vfs_id());
+    }
+
+    pub fn responses(&self) -> impl Iterator<Item = NetworkResponse> + '_ {
+        self.responses.try_iter()
+    }
+}
+
+fn unknown_host_key(err: &io::Error) -> Option<UnknownHostKey> {
+    err.get_ref()?.downcast_ref::<UnknownHostKey>().cloned()
+}
+
+/// The connected filesystem and the directory to open first
+fn open(profile: &ConnectionProfile) -> io::Result<(Arc<dyn Vfs>, PathBuf)> {
+    let root = profile.path.clone().unwrap_or_else(|| PathBuf::from("/"));
+
+    Ok(match profile.kind {
+        ConnectionKind::Sftp => {
+            let sftp = SftpVfs::connect(profile)?;
+            let root = sftp.root().clone();
+            (Arc::new(sftp), root)
+        }
+        ConnectionKind::WebDav => (Arc::new(WebDavVfs::connect(profile)?), root),
+        ConnectionKind::S3 => (Arc::new(S3Vfs::connect(profile)?), root),
+    })
+}
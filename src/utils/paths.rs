@@ -1,11 +1,20 @@
-use std::path::PathBuf;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
 
 use crate::config::PathsOverrides;
 
+use super::icons::PhosphorIcon;
+
+/// Fields before the `;` get getters, the rest are only read through [`Paths::user_dirs`]
 macro_rules! paths_struct {
-    ($($name:ident: $ty:ty),+) => {
+    ($($name:ident: $ty:ty),+; $($dir:ident: $dir_ty:ty),+) => {
         pub struct Paths {
-            $($name: $ty),+
+            $($name: $ty,)+
+            $($dir: $dir_ty),+
         }
 
         impl Paths {
@@ -21,22 +30,146 @@ macro_rules! paths_struct {
 paths_struct! {
     home_dir: PathBuf,
     downloads_dir: Option<PathBuf>,
+    templates_dir: Option<PathBuf>;
     desktop_dir: Option<PathBuf>,
-    documents_dir: Option<PathBuf>
+    documents_dir: Option<PathBuf>,
+    music_dir: Option<PathBuf>,
+    pictures_dir: Option<PathBuf>,
+    videos_dir: Option<PathBuf>,
+    public_dir: Option<PathBuf>
 }
 
 impl Paths {
     pub fn load(overrides: &PathsOverrides) -> Self {
         let home_dir = dirs::home_dir().expect("Failed to find home dir");
-        let downloads_dir = overrides.downloads().clone().or(dirs::download_dir());
-        let desktop_dir = dirs::desktop_dir();
-        let documents_dir = dirs::document_dir();
+        let user_dirs = xdg_user_dirs(&home_dir);
+
+        macro_rules! user_dir {
+            ($override:ident, $xdg_name:literal, $fallback:expr) => {
+                overrides
+                    .$override()
+                    .clone()
+                    .or_else(|| user_dirs.get($xdg_name).cloned())
+                    .or_else(|| $fallback)
+            };
+        }
 
         Self {
+            downloads_dir: user_dir!(downloads, "DOWNLOAD", dirs::download_dir()),
+            desktop_dir: user_dir!(desktop, "DESKTOP", dirs::desktop_dir()),
+            documents_dir: user_dir!(documents, "DOCUMENTS", dirs::document_dir()),
+            music_dir: user_dir!(music, "MUSIC", dirs::audio_dir()),
+            pictures_dir: user_dir!(pictures, "PICTURES", dirs::picture_dir()),
+            videos_dir: user_dir!(videos, "VIDEOS", dirs::video_dir()),
+            templates_dir: user_dir!(templates, "TEMPLATES", dirs::template_dir()),
+            public_dir: user_dir!(public, "PUBLICSHARE", dirs::public_dir()),
             home_dir,
-            downloads_dir,
-            desktop_dir,
-            documents_dir,
         }
     }
+
+    /// Home followed by every user dir that is set and exists
+    pub fn user_dirs(&self) -> Vec<(UserDir, PathBuf)> {
+        use UserDir::*;
+
+        let user_dirs = [
+            (Desktop, &self.desktop_dir),
+            (Documents, &self.documents_dir),
+            (Downloads, &self.downloads_dir),
+            (Music, &self.music_dir),
+            (Pictures, &self.pictures_dir),
+            (Videos, &self.videos_dir),
+            (Templates, &self.templates_dir),
+            (Public, &self.public_dir),
+        ]
+        .into_iter()
+        .filter_map(|(user_dir, path)| {
+            path.as_ref()
+                // xdg-user-dirs points unset dirs to $HOME
+                .filter(|path| path.is_dir() && *path != &self.home_dir)
+                .map(|path| (user_dir, path.clone()))
+        });
+
+        std::iter::once((Home, self.home_dir.clone()))
+            .chain(user_dirs)
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UserDir {
+    Home,
+    Desktop,
+    Documents,
+    Downloads,
+    Music,
+    Pictures,
+    Videos,
+    Templates,
+    Public,
+}
+
+impl UserDir {
+    pub fn label(&self) -> &'static str {
+        match self {
+            UserDir::Home => "Home",
+            UserDir::Desktop => "Desktop",
+            UserDir::Documents => "Documents",
+            UserDir::Downloads => "Downloads",
+            UserDir::Music => "Music",
+            UserDir::Pictures => "Pictures",
+            UserDir::Videos => "Videos",
+            UserDir::Templates => "Templates",
+            UserDir::Public => "Public",
+        }
+    }
+
+    pub fn icon(&self) -> PhosphorIcon {
+        match self {
+            UserDir::Home => PhosphorIcon::House,
+            UserDir::Desktop => PhosphorIcon::DotsNine,
+            UserDir::Documents => PhosphorIcon::Files,
+            UserDir::Downloads => PhosphorIcon::Download,
+            UserDir::Music => PhosphorIcon::MusicNotes,
+            UserDir::Pictures => PhosphorIcon::Image,
+            UserDir::Videos => PhosphorIcon::FilmStrip,
+            UserDir::Templates => PhosphorIcon::FileDashed,
+            UserDir::Public => PhosphorIcon::UsersThree,
+        }
+    }
+}
+
+/// Parses `$XDG_CONFIG_HOME/user-dirs.dirs`, which holds lines like `XDG_MUSIC_DIR="$HOME/Music"`.
+/// Keys are returned without the `XDG_` prefix and `_DIR` suffix
+fn xdg_user_dirs(home_dir: &Path) -> HashMap<String, PathBuf> {
+    let Some(file_path) = dirs::config_dir().map(|dir| dir.join("user-dirs.dirs")) else {
+        return HashMap::new();
+    };
+
+    let contents = match std::fs::read_to_string(&file_path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            tracing::warn!("Failed to read {file_path:?}: {err}");
+            return HashMap::new();
+        }
+    };
+
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let name = key.trim().strip_prefix("XDG_")?.strip_suffix("_DIR")?;
+            let value = value.trim().trim_matches('"');
+
+            let path = match value.strip_prefix("$HOME") {
+                Some(rest) => home_dir.join(rest.trim_start_matches('/')),
+                None if value.starts_with('/') => PathBuf::from(value),
+                // Relative paths are not allowed by the spec
+                None => return None,
+            };
+
+            Some((name.to_string(), path))
+        })
+        .collect()
 }
@@ -0,0 +1,137 @@
+//! The order listings are shown in, set from the config like the hide rules
+
+use std::{cmp::Ordering, sync::RwLock};
+
+use lazy_static::lazy_static;
+
+use crate::config::{SortBy, Sorting};
+
+use super::fs::FileData;
+
+lazy_static! {
+    static ref SORTING: RwLock<Sorting> = RwLock::new(Sorting::default());
+}
+
+/// Replaces the order listings are sorted in
+pub fn set(sorting: Sorting) {
+    *SORTING.write().expect("Sorting poisoned") = sorting;
+}
+
+/// Directories first, then links to them, links to files, files, broken links and what couldn't
+/// be read. Each group is sorted by the configured key, ties by name
+pub fn cmp_files(f1: &FileData, f2: &FileData) -> Ordering {
+    cmp_with(&SORTING.read().expect("Sorting poisoned"), f1, f2)
+}
+
+fn cmp_with(sorting: &Sorting, f1: &FileData, f2: &FileData) -> Ordering {
+    let groups = match sorting.directories_first {
+        true => group(f1).cmp(&group(f2)),
+        false => Ordering::Equal,
+    };
+
+    let by = match sorting.by {
+        SortBy::Name => Ordering::Equal,
+        SortBy::Extension => f1.ext().cmp(&f2.ext()),
+        SortBy::Size => f1.size().cmp(&f2.size()),
+        SortBy::Modified => f1.modified().cmp(&f2.modified()),
+    }
+    .then_with(|| f1.name().cmp(f2.name()));
+
+    groups.then(match sorting.descending {
+        true => by.reverse(),
+        false => by,
+    })
+}
+
+fn group(file: &FileData) -> u8 {
+    match (file.file_ty(), file.is_symlink()) {
+        (None, _) => 5,
+        (Some(ty), _) if ty.is_symlink() => 4,
+        (Some(ty), false) if ty.is_dir() => 0,
+        (Some(ty), true) if ty.is_dir() => 1,
+        (Some(_), true) => 2,
+        (Some(_), false) => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use crate::utils::vfs::{VfsEntry, VfsFileType, VfsMetadata};
+
+    use super::*;
+
+    fn file(name: &str, file_type: VfsFileType, size: u64, age: u64) -> FileData {
+        let entry = VfsEntry {
+            path: format!("/{name}").into(),
+            meta: VfsMetadata {
+                file_type,
+                size,
+                modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1000 - age)),
+                read_only: false,
+                link_target: None,
+                mode: None,
+            },
+        };
+        FileData::from_entry("sorting-test", entry)
+    }
+
+    fn sorted(sorting: Sorting) -> Vec<String> {
+        let mut files = vec![
+            file("b.txt", VfsFileType::File, 30, 1),
+            file("a.png", VfsFileType::File, 20, 3),
+            file("docs", VfsFileType::Dir, 0, 2),
+            file("c.md", VfsFileType::File, 10, 2),
+            file("broken", VfsFileType::Symlink, 0, 0),
+        ];
+        files.sort_by(|f1, f2| cmp_with(&sorting, f1, f2));
+        files.into_iter().map(|file| file.name().clone()).collect()
+    }
+
+    #[test]
+    fn sorts_by_each_key() {
+        let by = |by| Sorting {
+            by,
+            ..Sorting::default()
+        };
+
+        assert_eq!(
+            sorted(by(SortBy::Name)),
+            ["docs", "a.png", "b.txt", "c.md", "broken"]
+        );
+        assert_eq!(
+            sorted(by(SortBy::Extension)),
+            ["docs", "c.md", "a.png", "b.txt", "broken"]
+        );
+        assert_eq!(
+            sorted(by(SortBy::Size)),
+            ["docs", "c.md", "a.png", "b.txt", "broken"]
+        );
+        assert_eq!(
+            sorted(by(SortBy::Modified)),
+            ["docs", "a.png", "c.md", "b.txt", "broken"]
+        );
+    }
+
+    #[test]
+    fn descending_keeps_directories_first() {
+        let sorting = Sorting {
+            descending: true,
+            ..Sorting::default()
+        };
+        assert_eq!(
+            sorted(sorting),
+            ["docs", "c.md", "b.txt", "a.png", "broken"]
+        );
+
+        let sorting = Sorting {
+            directories_first: false,
+            ..Sorting::default()
+        };
+        assert_eq!(
+            sorted(sorting),
+            ["a.png", "b.txt", "broken", "c.md", "docs"]
+        );
+    }
+}
@@ -1,34 +1,173 @@
-use eframe::epaint::Color32;
+use std::ops::Deref;
 
-pub trait ThemeExt {
-    fn button_fill_color(&self) -> Color32;
-    fn button_border_color(&self) -> Color32;
-    fn button_clicked_color(&self) -> Color32;
-    fn button_hover_color(&self) -> Color32;
-    fn button_selected_color(&self) -> Color32;
+use eframe::{egui::Context, epaint::Color32};
+use serde::{Deserialize, Deserializer};
+
+use super::fs::FileKind;
+
+macro_rules! theme_tokens {
+    ($($(#[$meta:meta])* $token:ident: |$theme:ident| $default:expr),+ $(,)?) => {
+        /// Semantic colors components draw with instead of picking palette colors themselves
+        pub trait ThemeExt {
+            $($(#[$meta])* fn $token(&self) -> Color32;)+
+        }
+
+        /// Overrides of the [`ThemeExt`] colors, the unset ones are derived from the palette
+        #[derive(Default, Clone, Copy, PartialEq, Deserialize)]
+        #[serde(default, deny_unknown_fields)]
+        pub struct ExtColors {
+            $(pub $token: Option<HexColor>,)+
+            /// Opacity of hidden files' icons and labels
+            pub hidden_opacity: Option<f32>,
+        }
+
+        impl ThemeExt for Theme {
+            $(
+                fn $token(&self) -> Color32 {
+                    self.ext.$token.map_or_else(
+                        || {
+                            let $theme = self;
+                            $default
+                        },
+                        |color| color.0,
+                    )
+                }
+            )+
+        }
+    };
 }
 
-impl ThemeExt for catppuccin_egui::Theme {
-    fn button_fill_color(&self) -> Color32 {
-        self.surface1
+theme_tokens! {
+    button_fill_color: |t| t.surface1,
+    button_border_color: |t| t.crust,
+    button_clicked_color: |t| translucent(t.overlay2),
+    button_hover_color: |t| translucent(t.overlay1),
+    button_selected_color: |t| t.selection_color(),
+
+    /// Fill behind selected items
+    selection_color: |t| translucent(t.overlay0),
+    /// Outline of whatever has keyboard focus, e.g. the commander's active pane
+    focus_ring_color: |t| t.accent,
+    /// Outline of the area dragged files would be dropped into
+    drop_target_color: |t| t.blue,
+    /// Outline of items that differ between the commander's panes
+    difference_color: |t| t.peach,
+
+    error_color: |t| t.red,
+    warning_color: |t| t.yellow,
+    info_color: |t| t.blue,
+    success_color: |t| t.green,
+    /// Secondary text, e.g. unmounted devices
+    muted_text_color: |t| t.overlay1,
+
+    text_color: |t| t.text,
+    dir_color: |t| t.blue,
+    file_color: |t| t.text,
+    symlink_color: |t| t.teal,
+    broken_symlink_color: |t| t.red,
+    archive_color: |t| t.peach,
+}
+
+/// A Catppuccin palette with the accent and any [`ThemeExt`] colors a custom theme picks on top.
+/// Derefs to the palette, so `theme.red` and friends work as before
+#[derive(Clone, Copy)]
+pub struct Theme {
+    palette: catppuccin_egui::Theme,
+    accent: Color32,
+    ext: ExtColors,
+}
+
+impl Theme {
+    pub fn new(palette: catppuccin_egui::Theme, accent: Color32) -> Self {
+        Self {
+            palette,
+            accent,
+            ext: ExtColors::default(),
+        }
+    }
+
+    pub fn with_ext(mut self, ext: ExtColors) -> Self {
+        self.ext = ext;
+        self
     }
 
-    fn button_border_color(&self) -> Color32 {
-        self.crust
+    pub fn file_kind_color(&self, kind: FileKind) -> Color32 {
+        match kind {
+            FileKind::Dir => self.dir_color(),
+            FileKind::File => self.file_color(),
+            FileKind::Symlink => self.symlink_color(),
+            FileKind::BrokenSymlink => self.broken_symlink_color(),
+            FileKind::Archive => self.archive_color(),
+            FileKind::Unknown => self.muted_text_color(),
+        }
     }
 
-    fn button_clicked_color(&self) -> Color32 {
-        let [r, g, b, _a] = self.overlay2.to_array();
-        Color32::from_rgba_unmultiplied(r, g, b, 100)
+    /// `color` faded the way hidden files are
+    pub fn dim_hidden(&self, color: Color32) -> Color32 {
+        color.gamma_multiply(self.ext.hidden_opacity.unwrap_or(0.5).clamp(0.0, 1.0))
     }
 
-    fn button_hover_color(&self) -> Color32 {
-        let [r, g, b, _a] = self.overlay1.to_array();
-        Color32::from_rgba_unmultiplied(r, g, b, 100)
+    /// Styles egui's own widgets, the rest read the theme when they're drawn
+    pub fn apply(&self, ctx: &Context) {
+        catppuccin_egui::set_theme(ctx, self.palette);
+
+        ctx.style_mut(|style| {
+            let visuals = &mut style.visuals;
+            visuals.selection.bg_fill = self.accent.linear_multiply(0.4);
+            visuals.selection.stroke.color = self.focus_ring_color();
+            visuals.hyperlink_color = self.accent;
+            visuals.error_fg_color = self.error_color();
+            visuals.warn_fg_color = self.warning_color();
+        });
     }
+}
 
-    fn button_selected_color(&self) -> Color32 {
-        let [r, g, b, _a] = self.overlay0.to_array();
-        Color32::from_rgba_unmultiplied(r, g, b, 100)
+impl Deref for Theme {
+    type Target = catppuccin_egui::Theme;
+
+    fn deref(&self) -> &Self::Target {
+        &self.palette
     }
 }
+
+/// `#rrggbb` or `#rrggbbaa`
+#[derive(Clone, Copy, PartialEq)]
+pub struct HexColor(pub Color32);
+
+impl HexColor {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+                .ok_or_else(|| format!("{s:?} isn't a #rrggbb or #rrggbbaa color"))
+        };
+
+        match hex.len() {
+            6 => Ok(Self(Color32::from_rgb(
+                channel(0)?,
+                channel(2)?,
+                channel(4)?,
+            ))),
+            8 => Ok(Self(Color32::from_rgba_unmultiplied(
+                channel(0)?,
+                channel(2)?,
+                channel(4)?,
+                channel(6)?,
+            ))),
+            _ => Err(format!("{s:?} isn't a #rrggbb or #rrggbbaa color")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for HexColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+fn translucent(color: Color32) -> Color32 {
+    let [r, g, b, _a] = color.to_array();
+    Color32::from_rgba_unmultiplied(r, g, b, 100)
+}
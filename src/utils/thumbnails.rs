@@ -0,0 +1,172 @@
+//! Image files shown by their contents instead of an icon. Their bytes come through the VFS on a
+//! background thread, egui's image loaders decode them
+
+use std::{
+    collections::HashMap,
+    io::{self, Read},
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use eframe::egui::{
+    load::{Bytes, BytesLoadResult, BytesLoader, BytesPoll, LoadError},
+    Context,
+};
+use lazy_static::lazy_static;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+
+use crate::config::Thumbnails;
+
+use super::{fs::FileData, vfs::Location};
+
+/// Extensions of the formats the image loaders are built with
+const EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+const SCHEME: &str = "rsfm-vfs://";
+
+lazy_static! {
+    static ref SETTINGS: RwLock<Thumbnails> = RwLock::new(Thumbnails::default());
+}
+
+/// Replaces which files get a thumbnail
+pub fn set(thumbnails: Thumbnails) {
+    *SETTINGS.write().expect("Thumbnail settings poisoned") = thumbnails;
+}
+
+/// Lets egui load the URIs from [`uri`]
+pub fn install(ctx: &Context) {
+    ctx.add_bytes_loader(Arc::new(VfsLoader::default()));
+}
+
+/// Image URI of the thumbnail, `None` when the file keeps its icon
+pub fn uri(file_data: &FileData) -> Option<String> {
+    let settings = *SETTINGS.read().expect("Thumbnail settings poisoned");
+    let location = file_data.location();
+
+    let wanted = settings.enabled
+        && (settings.remote || location.is_local())
+        && file_data.size()? <= settings.max_file_size * 1024 * 1024
+        && file_data
+            .ext()
+            .is_some_and(|ext| EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext)));
+
+    wanted.then(|| to_uri(location))
+}
+
+fn to_uri(location: &Location) -> String {
+    format!(
+        "{SCHEME}{}{}",
+        utf8_percent_encode(location.vfs(), NON_ALPHANUMERIC),
+        location.path().display()
+    )
+}
+
+fn parse_uri(uri: &str) -> Option<Location> {
+    let rest = uri.strip_prefix(SCHEME)?;
+    let (vfs, path) = rest.split_at(rest.find('/')?);
+    let vfs = percent_decode_str(vfs).decode_utf8().ok()?;
+
+    Some(Location::new(vfs.as_ref(), PathBuf::from(path)))
+}
+
+type Loaded = Option<Result<Arc<[u8]>, String>>;
+
+/// Reads files through their backend, `None` while that's still going
+#[derive(Default)]
+struct VfsLoader {
+    cache: Arc<Mutex<HashMap<String, Loaded>>>,
+}
+
+impl VfsLoader {
+    fn read(location: &Location) -> io::Result<Arc<[u8]>> {
+        let mut bytes = vec![];
+        location
+            .backend()?
+            .read(location.path())?
+            .read_to_end(&mut bytes)?;
+
+        Ok(bytes.into())
+    }
+}
+
+impl BytesLoader for VfsLoader {
+    fn id(&self) -> &str {
+        eframe::egui::generate_loader_id!(VfsLoader)
+    }
+
+    fn load(&self, ctx: &Context, uri: &str) -> BytesLoadResult {
+        let Some(location) = parse_uri(uri) else {
+            return Err(LoadError::NotSupported);
+        };
+
+        let mut cache = self.cache.lock().expect("Thumbnail cache poisoned");
+        match cache.get(uri) {
+            Some(Some(Ok(bytes))) => Ok(BytesPoll::Ready {
+                size: None,
+                bytes: Bytes::Shared(bytes.clone()),
+                mime: None,
+            }),
+            Some(Some(Err(err))) => Err(LoadError::Loading(err.clone())),
+            Some(None) => Ok(BytesPoll::Pending { size: None }),
+            None => {
+                cache.insert(uri.to_string(), None);
+
+                let (cache, ctx, uri) = (self.cache.clone(), ctx.clone(), uri.to_string());
+                std::thread::spawn(move || {
+                    let loaded = Self::read(&location).map_err(|err| {
+                        tracing::warn!("Failed to read {location} for its thumbnail: {err}");
+                        err.to_string()
+                    });
+
+                    // Forgotten while loading, e.g. the listing was reloaded
+                    if let Some(entry) = cache
+                        .lock()
+                        .expect("Thumbnail cache poisoned")
+                        .get_mut(&uri)
+                    {
+                        *entry = Some(loaded);
+                    }
+                    ctx.request_repaint();
+                });
+
+                Ok(BytesPoll::Pending { size: None })
+            }
+        }
+    }
+
+    fn forget(&self, uri: &str) {
+        self.cache
+            .lock()
+            .expect("Thumbnail cache poisoned")
+            .remove(uri);
+    }
+
+    fn forget_all(&self) {
+        self.cache.lock().expect("Thumbnail cache poisoned").clear();
+    }
+
+    fn byte_size(&self) -> usize {
+        self.cache
+            .lock()
+            .expect("Thumbnail cache poisoned")
+            .values()
+            .map(|loaded| match loaded {
+                Some(Ok(bytes)) => bytes.len(),
+                Some(Err(err)) => err.len(),
+                None => 0,
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uris_round_trip() {
+        let location = Location::new("sftp:me@host/x", "/photos/a b.png");
+        assert_eq!(parse_uri(&to_uri(&location)), Some(location));
+        assert_eq!(parse_uri("file:///photos/a.png"), None);
+    }
+}
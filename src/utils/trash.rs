@@ -0,0 +1,184 @@
+//! The freedesktop.org trash: the home trash for files on its filesystem, `$topdir/.Trash/$uid`
+//! or `$topdir/.Trash-$uid` at the top of any other. Files never get copied into a trash
+
+use std::{
+    fs::{self, DirBuilder, OpenOptions},
+    io::{self, ErrorKind, Write},
+    os::unix::fs::{DirBuilderExt, MetadataExt},
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use super::fs::move_path;
+
+/// Characters escaped in `.trashinfo` paths, the way GIO does it
+const TRASH_PATH: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b']')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// A file in the trash and where it came from
+#[derive(Clone)]
+pub struct Trashed {
+    pub original: PathBuf,
+    pub file: PathBuf,
+    info: PathBuf,
+}
+
+fn home_trash_dir() -> io::Result<PathBuf> {
+    dirs::data_dir()
+        .map(|data_dir| data_dir.join("Trash"))
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "There's no data directory"))
+}
+
+/// The trash for `original` on the device `dev`
+fn trash_dir(original: &Path, dev: u64) -> io::Result<PathBuf> {
+    let home_trash = home_trash_dir()?;
+    fs::create_dir_all(&home_trash)?;
+    if fs::metadata(&home_trash)?.dev() == dev {
+        return Ok(home_trash);
+    }
+
+    let topdir = topdir(original, dev);
+    top_trash_dir(&topdir).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!(
+                "{original:?} is on another filesystem than the home trash, \
+                 and {topdir:?} has no trash to use: {err}"
+            ),
+        )
+    })
+}
+
+/// Where the filesystem `path` is on is mounted, its furthest ancestor on the same device
+fn topdir(path: &Path, dev: u64) -> PathBuf {
+    path.ancestors()
+        .skip(1)
+        .take_while(|dir| dir.metadata().is_ok_and(|meta| meta.dev() == dev))
+        .last()
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+/// `$topdir/.Trash/$uid` when an administrator set up `$topdir/.Trash`, otherwise
+/// `$topdir/.Trash-$uid`. Both are created as needed
+fn top_trash_dir(topdir: &Path) -> io::Result<PathBuf> {
+    // SAFETY: getuid has no preconditions and can't fail
+    let uid = unsafe { libc::getuid() };
+    let create = |dir: &Path| match DirBuilder::new().mode(0o700).create(dir) {
+        Err(err) if err.kind() != ErrorKind::AlreadyExists => Err(err),
+        _ => Ok(()),
+    };
+
+    // Only with the sticky bit and not as a symlink, anything else isn't safe to share
+    let shared = topdir.join(".Trash");
+    let shared_usable = shared
+        .symlink_metadata()
+        .is_ok_and(|meta| meta.is_dir() && meta.mode() & libc::S_ISVTX != 0);
+    if shared_usable {
+        let dir = shared.join(uid.to_string());
+        match create(&dir).and_then(|()| dir.symlink_metadata()) {
+            Ok(meta) if meta.is_dir() && meta.uid() == uid => return Ok(dir),
+            Ok(_) => tracing::warn!("Skipping {dir:?}, it isn't a directory of ours"),
+            Err(err) => tracing::warn!("Skipping {dir:?}: {err}"),
+        }
+    }
+
+    let dir = topdir.join(format!(".Trash-{uid}"));
+    create(&dir)?;
+    let meta = dir.symlink_metadata()?;
+    match meta.is_dir() && meta.uid() == uid {
+        true => Ok(dir),
+        false => Err(io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{dir:?} isn't a directory of ours"),
+        )),
+    }
+}
+
+/// Moves `path` into the trash, next to a `.trashinfo` file to restore it from
+pub fn trash(path: &Path) -> io::Result<Trashed> {
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("Can't trash {path:?}"),
+        ));
+    };
+    // Only the parent, a trashed symlink is the link and not its target
+    let original = parent.canonicalize()?.join(name);
+    let dev = original.symlink_metadata()?.dev();
+    let name = name.to_string_lossy().to_string();
+
+    let trash_dir = trash_dir(&original, dev)?;
+    let (files_dir, info_dir) = (trash_dir.join("files"), trash_dir.join("info"));
+    fs::create_dir_all(&files_dir)?;
+    fs::create_dir_all(&info_dir)?;
+
+    // Claiming the info file first keeps two trashings from picking the same name
+    let (trashed_name, mut info_file) = (1..)
+        .map(|i| match i {
+            1 => name.clone(),
+            i => format!("{name}.{i}"),
+        })
+        .find_map(|trashed_name| {
+            if files_dir.join(&trashed_name).symlink_metadata().is_ok() {
+                return None;
+            }
+
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(info_dir.join(format!("{trashed_name}.trashinfo")))
+            {
+                Ok(info_file) => Some(Ok((trashed_name, info_file))),
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => None,
+                Err(err) => Some(Err(err)),
+            }
+        })
+        .expect("Ran out of trash names")?;
+
+    let trashed = Trashed {
+        file: files_dir.join(&trashed_name),
+        info: info_dir.join(format!("{trashed_name}.trashinfo")),
+        original,
+    };
+
+    let res = write!(
+        info_file,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        utf8_percent_encode(&trashed.original.to_string_lossy(), TRASH_PATH),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    )
+    .and_then(|_| move_path(&trashed.original, &trashed.file));
+
+    match res {
+        Ok(()) => Ok(trashed),
+        Err(err) => {
+            let _ = fs::remove_file(&trashed.info);
+            Err(err)
+        }
+    }
+}
+
+/// Moves the file back where it was trashed from, never overwriting what's there now
+pub fn restore(trashed: &Trashed) -> io::Result<()> {
+    move_path(&trashed.file, &trashed.original)?;
+
+    if let Err(err) = fs::remove_file(&trashed.info) {
+        tracing::warn!("Failed to remove {:?}: {err}", trashed.info);
+    }
+
+    Ok(())
+}
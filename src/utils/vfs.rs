@@ -0,0 +1,609 @@
+//! Filesystems [`FileData`](super::fs::FileData) and the views can be backed by.
+//!
+//! Every backend is registered under an id, a [`Location`] pairs that id with a path
+//! inside the backend. The local filesystem is always registered as [`LOCAL_VFS`]
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt::Display,
+    fs::Metadata,
+    io::{self, Cursor, ErrorKind, Read, Write},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::SystemTime,
+};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{
+    fs::{copy_recursive, move_path},
+    jobs::{JobProgress, ProgressReader},
+};
+
+mod local;
+#[cfg(test)]
+mod memory;
+mod s3;
+mod sftp;
+mod webdav;
+
+pub use self::s3::S3Vfs;
+pub use local::LocalVfs;
+#[cfg(test)]
+pub use memory::MemoryVfs;
+pub use sftp::{SftpVfs, UnknownHostKey};
+pub use webdav::WebDavVfs;
+
+pub const LOCAL_VFS: &str = "file";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum VfsFileType {
+    File,
+    Dir,
+    Symlink,
+    Other,
+}
+
+impl VfsFileType {
+    pub fn is_file(&self) -> bool {
+        *self == VfsFileType::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        *self == VfsFileType::Dir
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        *self == VfsFileType::Symlink
+    }
+}
+
+#[derive(Clone)]
+pub struct VfsMetadata {
+    pub file_type: VfsFileType,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub read_only: bool,
+    pub link_target: Option<PathBuf>,
+    /// Unix `st_mode`, the file type and permission bits, when the backend has them
+    pub mode: Option<u32>,
+}
+
+impl From<&Metadata> for VfsMetadata {
+    fn from(meta: &Metadata) -> Self {
+        let file_ty = meta.file_type();
+
+        Self {
+            file_type: match (file_ty.is_file(), file_ty.is_dir(), file_ty.is_symlink()) {
+                (true, _, _) => VfsFileType::File,
+                (_, true, _) => VfsFileType::Dir,
+                (_, _, true) => VfsFileType::Symlink,
+                _ => VfsFileType::Other,
+            },
+            size: meta.len(),
+            modified: meta.modified().ok(),
+            read_only: meta.permissions().readonly(),
+            link_target: None,
+            mode: Some(meta.mode()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct VfsEntry {
+    pub path: PathBuf,
+    pub meta: VfsMetadata,
+}
+
+/// Keeps a [`Vfs::watch`] alive, the callback stops being called once it's dropped
+pub struct WatchHandle {
+    _guard: Box<dyn Any + Send + Sync>,
+}
+
+impl WatchHandle {
+    pub fn new(guard: impl Any + Send + Sync) -> Self {
+        Self {
+            _guard: Box::new(guard),
+        }
+    }
+}
+
+pub type OnChange = Box<dyn Fn() + Send + Sync>;
+
+pub trait Vfs: Send + Sync {
+    /// Direct children of the directory
+    fn list(&self, path: &Path) -> io::Result<Vec<VfsEntry>>;
+    /// Same as [`Vfs::list`], handing the entries over as the backend receives them.
+    /// Stops early once `on_page` returns `false`
+    fn list_pages(
+        &self,
+        path: &Path,
+        on_page: &mut dyn FnMut(Vec<VfsEntry>) -> bool,
+    ) -> io::Result<()> {
+        on_page(self.list(path)?);
+        Ok(())
+    }
+    fn stat(&self, path: &Path) -> io::Result<VfsMetadata>;
+    fn read(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+    /// Creates or truncates the file
+    fn write(&self, path: &Path) -> io::Result<Box<dyn Write + Send>>;
+    /// Creates the file, failing with [`ErrorKind::AlreadyExists`] instead of truncating one.
+    /// Backends that can't do that in one step check first
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        match self.stat(path) {
+            Ok(_) => Err(already_exists(path)),
+            Err(err) if err.kind() == ErrorKind::NotFound => self.write(path),
+            Err(err) => Err(err),
+        }
+    }
+    /// Fails with [`ErrorKind::AlreadyExists`] rather than replacing an existing `to`
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    /// Removes files and whole directories alike
+    fn remove(&self, path: &Path) -> io::Result<()>;
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+    /// Calls `on_change` whenever the file or the directory's direct children change
+    fn watch(&self, path: &Path, on_change: OnChange) -> io::Result<WatchHandle>;
+}
+
+lazy_static! {
+    static ref BACKENDS: RwLock<HashMap<String, Arc<dyn Vfs>>> = RwLock::new(HashMap::from([(
+        LOCAL_VFS.to_string(),
+        Arc::new(LocalVfs) as Arc<dyn Vfs>
+    )]));
+}
+
+pub fn register(id: impl Into<String>, vfs: Arc<dyn Vfs>) {
+    BACKENDS
+        .write()
+        .expect("VFS registry poisoned")
+        .insert(id.into(), vfs);
+}
+
+pub fn unregister(id: &str) {
+    if id == LOCAL_VFS {
+        return;
+    }
+
+    BACKENDS.write().expect("VFS registry poisoned").remove(id);
+}
+
+pub fn backend(id: &str) -> io::Result<Arc<dyn Vfs>> {
+    BACKENDS
+        .read()
+        .expect("VFS registry poisoned")
+        .get(id)
+        .cloned()
+        .ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::NotFound,
+                format!("No filesystem is registered as {id:?}"),
+            )
+        })
+}
+
+/// A path inside a registered [`Vfs`].
+/// Written as a plain path for local files and as `id://path` for everything else
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Location {
+    vfs: String,
+    path: PathBuf,
+}
+
+impl Location {
+    pub fn new(vfs: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            vfs: vfs.into(),
+            path: path.into(),
+        }
+    }
+
+    pub fn local(path: impl Into<PathBuf>) -> Self {
+        Self::new(LOCAL_VFS, path)
+    }
+
+    pub fn vfs(&self) -> &str {
+        &self.vfs
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn is_local(&self) -> bool {
+        self.vfs == LOCAL_VFS
+    }
+
+    pub fn backend(&self) -> io::Result<Arc<dyn Vfs>> {
+        backend(&self.vfs)
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> Self {
+        Self::new(self.vfs.clone(), self.path.join(path))
+    }
+
+    pub fn parent(&self) -> Option<Self> {
+        self.path
+            .parent()
+            .map(|parent| Self::new(self.vfs.clone(), parent))
+    }
+
+    fn parse(s: &str) -> Self {
+        match s.split_once("://") {
+            Some((vfs, path)) if !s.starts_with('/') => Self::new(vfs, format!("/{path}")),
+            _ => Self::local(s),
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.is_local() {
+            true => write!(f, "{}", self.path.display()),
+            false => write!(
+                f,
+                "{}://{}",
+                self.vfs,
+                self.path.display().to_string().trim_start_matches('/')
+            ),
+        }
+    }
+}
+
+impl From<PathBuf> for Location {
+    fn from(path: PathBuf) -> Self {
+        Self::local(path)
+    }
+}
+
+impl From<&Path> for Location {
+    fn from(path: &Path) -> Self {
+        Self::local(path)
+    }
+}
+
+impl Serialize for Location {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Location {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|s| Self::parse(&s))
+    }
+}
+
+/// Copies `from` to `to` between any two backends, descending into directories.
+/// Never overwrites an existing `to`
+pub fn copy(from: &Location, to: &Location, progress: &JobProgress) -> io::Result<()> {
+    if from.is_local() && to.is_local() {
+        return copy_recursive(from.path(), to.path());
+    }
+
+    let (from_vfs, to_vfs) = (from.backend()?, to.backend()?);
+    check_target(to_vfs.as_ref(), to)?;
+
+    copy_between(
+        from_vfs.as_ref(),
+        from.path(),
+        to_vfs.as_ref(),
+        to.path(),
+        progress,
+    )
+}
+
+/// Renames within a backend, copies and removes the original between backends.
+/// Never overwrites an existing `to`
+pub fn move_to(from: &Location, to: &Location, progress: &JobProgress) -> io::Result<()> {
+    if from.is_local() && to.is_local() {
+        return move_path(from.path(), to.path());
+    }
+
+    let from_vfs = from.backend()?;
+    match from.vfs() == to.vfs() {
+        true => {
+            check_target(from_vfs.as_ref(), to)?;
+            from_vfs.rename(from.path(), to.path())
+        }
+        false => {
+            copy(from, to, progress)?;
+            from_vfs.remove(from.path())
+        }
+    }
+}
+
+/// Calls `create` with `location`, then with `name (2).ext`-like siblings for as long as it fails
+/// with [`ErrorKind::AlreadyExists`]. Returns where it succeeded
+pub fn create_unique(
+    location: &Location,
+    mut create: impl FnMut(&Location) -> io::Result<()>,
+) -> io::Result<Location> {
+    let path = location.path();
+    let stem = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let ext = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    let candidates = (2..).map(|i| {
+        Location::new(
+            location.vfs(),
+            path.with_file_name(format!("{stem} ({i}){ext}")),
+        )
+    });
+    for candidate in std::iter::once(location.clone()).chain(candidates) {
+        match create(&candidate) {
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            res => return res.map(|_| candidate),
+        }
+    }
+
+    unreachable!("Ran out of file names")
+}
+
+#[derive(Clone, Copy)]
+pub enum TransferMode {
+    Copy,
+    Move,
+}
+
+impl TransferMode {
+    pub fn verb(&self) -> &'static str {
+        match self {
+            TransferMode::Copy => "copy",
+            TransferMode::Move => "move",
+        }
+    }
+
+    pub fn run(&self, from: &Location, to: &Location, progress: &JobProgress) -> io::Result<()> {
+        match self {
+            TransferMode::Copy => copy(from, to, progress),
+            TransferMode::Move => move_to(from, to, progress),
+        }
+    }
+}
+
+fn check_target(vfs: &dyn Vfs, to: &Location) -> io::Result<()> {
+    match vfs.stat(to.path()) {
+        Ok(_) => Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{to} already exists"),
+        )),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+fn already_exists(path: &Path) -> io::Error {
+    io::Error::new(ErrorKind::AlreadyExists, format!("{path:?} already exists"))
+}
+
+fn copy_between(
+    from_vfs: &dyn Vfs,
+    from: &Path,
+    to_vfs: &dyn Vfs,
+    to: &Path,
+    progress: &JobProgress,
+) -> io::Result<()> {
+    progress.check_cancelled()?;
+
+    match from_vfs.stat(from)?.file_type {
+        VfsFileType::Dir => {
+            to_vfs.create_dir(to)?;
+
+            from_vfs.list(from)?.into_iter().try_for_each(|entry| {
+                let name = entry.path.file_name().unwrap_or_default();
+                copy_between(from_vfs, &entry.path, to_vfs, &to.join(name), progress)
+            })
+        }
+        _ => {
+            let mut writer = to_vfs.write(to)?;
+            io::copy(
+                &mut ProgressReader::new(from_vfs.read(from)?, progress),
+                &mut writer,
+            )?;
+            writer.flush()
+        }
+    }
+}
+
+/// How many chunks a [`pipe`] holds before the writing end waits for the reading end
+const PIPE_CHUNKS: usize = 16;
+
+/// A bounded in-memory pipe between two threads. The writing end has to be [closed](PipeWriter::close)
+/// for the reading end to see the end of the stream, dropping it midway reads as an error
+fn pipe() -> (PipeWriter, PipeReader) {
+    let (sender, receiver) = mpsc::sync_channel(PIPE_CHUNKS);
+    (
+        PipeWriter { sender },
+        PipeReader {
+            receiver,
+            chunk: Cursor::new(vec![]),
+            done: false,
+        },
+    )
+}
+
+/// The writing end of a [`pipe`]
+struct PipeWriter {
+    // An empty chunk marks the end of the stream
+    sender: SyncSender<io::Result<Vec<u8>>>,
+}
+
+impl PipeWriter {
+    /// Ends the stream, with `result` as the reading end's last read
+    fn close(self, result: io::Result<()>) {
+        // Nobody is left to tell if the reading end is gone
+        let _ = self.sender.send(result.map(|()| vec![]));
+    }
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        self.sender
+            .send(Ok(buf.to_vec()))
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "The reading end is gone"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The reading end of a [`pipe`]
+struct PipeReader {
+    receiver: Receiver<io::Result<Vec<u8>>>,
+    chunk: Cursor<Vec<u8>>,
+    done: bool,
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.done && self.chunk.position() as usize == self.chunk.get_ref().len() {
+            match self.receiver.recv() {
+                Ok(Ok(chunk)) if chunk.is_empty() => self.done = true,
+                Ok(Ok(chunk)) => self.chunk = Cursor::new(chunk),
+                Ok(Err(err)) => {
+                    self.done = true;
+                    return Err(err);
+                }
+                Err(_) => {
+                    self.done = true;
+                    return Err(io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "The stream ended early",
+                    ));
+                }
+            }
+        }
+
+        self.chunk.read(buf)
+    }
+}
+
+/// Streams the written bytes to `upload` on its own thread, for backends that store a whole file
+/// in one request. [`Write::flush`] ends the upload and returns how it went, so callers have to
+/// flush to see upload errors. Nothing can be written after that
+struct UploadWriter {
+    pipe: Option<PipeWriter>,
+    upload: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl UploadWriter {
+    pub fn new(upload: impl FnOnce(PipeReader) -> io::Result<()> + Send + 'static) -> Self {
+        let (writer, reader) = pipe();
+
+        Self {
+            pipe: Some(writer),
+            upload: Some(thread::spawn(move || upload(reader))),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(pipe) = self.pipe.take() {
+            pipe.close(Ok(()));
+        }
+
+        match self.upload.take() {
+            Some(upload) => upload
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("The upload panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for UploadWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(pipe) = &mut self.pipe else {
+            return Err(io::Error::other("The upload is already finished"));
+        };
+
+        match pipe.write(buf) {
+            Ok(written) => Ok(written),
+            // The upload stopped reading, its own error says why
+            Err(err) => Err(self.finish().err().unwrap_or(err)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.finish()
+    }
+}
+
+impl Drop for UploadWriter {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            tracing::error!("Failed to upload: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipe_streams_until_closed() {
+        let (mut writer, mut reader) = pipe();
+        let writing = thread::spawn(move || {
+            (0..100).for_each(|i| writer.write_all(&[i]).unwrap());
+            writer.close(Ok(()));
+        });
+
+        let mut read = vec![];
+        reader.read_to_end(&mut read).unwrap();
+        writing.join().unwrap();
+        assert_eq!(read, (0..100).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn pipe_reports_early_ends() {
+        let (writer, mut reader) = pipe();
+        drop(writer);
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        let (writer, mut reader) = pipe();
+        writer.close(Err(io::Error::new(ErrorKind::NotFound, "gone")));
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn upload_writer_streams_and_reports_failures() {
+        let uploaded = Arc::new(RwLock::new(vec![]));
+        let target = uploaded.clone();
+        let mut writer = UploadWriter::new(move |mut reader| {
+            reader.read_to_end(&mut target.write().unwrap()).map(|_| ())
+        });
+        writer.write_all(b"hello ").unwrap();
+        writer.write_all(b"world").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(*uploaded.read().unwrap(), b"hello world");
+        assert!(writer.write_all(b"more").is_err());
+
+        let mut writer = UploadWriter::new(|_| Err(io::Error::other("refused")));
+        let err = (0..PIPE_CHUNKS * 2)
+            .try_for_each(|_| writer.write_all(b"data"))
+            .and_then(|()| writer.flush())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "refused");
+    }
+}
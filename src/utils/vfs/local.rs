@@ -0,0 +1,160 @@
+use std::{
+    fs::{self, File},
+    io::{self, Cursor, ErrorKind, Read, Write},
+    path::Path,
+};
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::utils::{
+    archive::{self, ArchiveEntry, ArchivePath},
+    fs::{remove_path, rename_no_replace},
+};
+
+use super::{OnChange, Vfs, VfsEntry, VfsFileType, VfsMetadata, WatchHandle};
+
+/// `std::fs`, with archives browsable as read-only directories
+pub struct LocalVfs;
+
+impl LocalVfs {
+    /// Paths inside an archive, the archive file itself is a regular file
+    fn archive_member(path: &Path) -> Option<ArchivePath> {
+        ArchivePath::parse(path).filter(ArchivePath::is_member)
+    }
+
+    fn read_only_err(path: &Path) -> io::Error {
+        io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{path:?} is inside an archive, archives are read-only"),
+        )
+    }
+}
+
+impl From<&ArchiveEntry> for VfsMetadata {
+    fn from(entry: &ArchiveEntry) -> Self {
+        Self {
+            file_type: match entry.is_dir {
+                true => VfsFileType::Dir,
+                false => VfsFileType::File,
+            },
+            size: entry.size,
+            modified: entry.modified,
+            read_only: true,
+            link_target: None,
+            mode: None,
+        }
+    }
+}
+
+impl Vfs for LocalVfs {
+    fn list(&self, path: &Path) -> io::Result<Vec<VfsEntry>> {
+        if let Some(archive_path) = ArchivePath::parse(path) {
+            return Ok(archive::list(&archive_path)?
+                .iter()
+                .map(|entry| VfsEntry {
+                    path: archive_path.archive.join(&entry.path),
+                    meta: entry.into(),
+                })
+                .collect());
+        }
+
+        Ok(fs::read_dir(path)?
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+
+                match self.stat(&path) {
+                    Ok(meta) => Some(VfsEntry { path, meta }),
+                    Err(err) => {
+                        tracing::error!("Failed to get file {path:?} metadata: {err}");
+                        None
+                    }
+                }
+            })
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<VfsMetadata> {
+        match path.metadata() {
+            Ok(meta) => {
+                let mut res = VfsMetadata::from(&meta);
+                res.link_target = fs::read_link(path).ok();
+                Ok(res)
+            }
+            Err(err) => match Self::archive_member(path) {
+                Some(archive_path) => archive::stat(&archive_path).map(|entry| (&entry).into()),
+                // A broken symlink, it keeps its symlink type
+                None => match path.symlink_metadata() {
+                    Ok(link_meta) if link_meta.file_type().is_symlink() => {
+                        let mut res = VfsMetadata::from(&link_meta);
+                        res.link_target = fs::read_link(path).ok();
+                        Ok(res)
+                    }
+                    _ => Err(err),
+                },
+            },
+        }
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        match Self::archive_member(path) {
+            Some(archive_path) => Ok(Box::new(Cursor::new(archive::read(&archive_path)?))),
+            None => Ok(Box::new(File::open(path)?)),
+        }
+    }
+
+    fn write(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        match Self::archive_member(path) {
+            Some(_) => Err(Self::read_only_err(path)),
+            None => Ok(Box::new(File::create(path)?)),
+        }
+    }
+
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        match Self::archive_member(path) {
+            Some(_) => Err(Self::read_only_err(path)),
+            None => Ok(Box::new(File::create_new(path)?)),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        match Self::archive_member(from).or_else(|| Self::archive_member(to)) {
+            Some(_) => Err(Self::read_only_err(from)),
+            None => rename_no_replace(from, to),
+        }
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        match Self::archive_member(path) {
+            Some(_) => Err(Self::read_only_err(path)),
+            None => remove_path(path),
+        }
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        match Self::archive_member(path) {
+            Some(_) => Err(Self::read_only_err(path)),
+            None => fs::create_dir(path),
+        }
+    }
+
+    fn watch(&self, path: &Path, on_change: OnChange) -> io::Result<WatchHandle> {
+        // Archives only change as a whole
+        let watched = match ArchivePath::parse(path) {
+            Some(archive_path) => archive_path.archive,
+            None => path.to_path_buf(),
+        };
+
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if !event.kind.is_access() => on_change(),
+                Ok(_) => {}
+                Err(err) => tracing::error!("Failed to watch files: {err}"),
+            })
+            .map_err(io::Error::other)?;
+        watcher
+            .watch(&watched, RecursiveMode::NonRecursive)
+            .map_err(io::Error::other)?;
+
+        Ok(WatchHandle::new(watcher))
+    }
+}
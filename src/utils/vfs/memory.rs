@@ -0,0 +1,455 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, Weak},
+    time::SystemTime,
+};
+
+use super::{already_exists, OnChange, Vfs, VfsEntry, VfsFileType, VfsMetadata, WatchHandle};
+
+/// A filesystem that only lives in memory, for tests.
+/// Paths are absolute, `/` always exists
+#[derive(Clone, Default)]
+pub struct MemoryVfs {
+    inner: Arc<Mutex<MemoryInner>>,
+}
+
+#[derive(Default)]
+struct MemoryInner {
+    nodes: BTreeMap<PathBuf, MemoryNode>,
+    watchers: Vec<(PathBuf, Weak<OnChange>)>,
+}
+
+struct MemoryNode {
+    /// `None` for directories
+    contents: Option<Vec<u8>>,
+    modified: SystemTime,
+}
+
+impl MemoryNode {
+    fn meta(&self) -> VfsMetadata {
+        VfsMetadata {
+            file_type: match self.contents {
+                Some(_) => VfsFileType::File,
+                None => VfsFileType::Dir,
+            },
+            size: self
+                .contents
+                .as_ref()
+                .map_or(0, |contents| contents.len() as u64),
+            modified: Some(self.modified),
+            read_only: false,
+            link_target: None,
+            mode: None,
+        }
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(ErrorKind::NotFound, format!("{path:?} doesn't exist"))
+}
+
+impl MemoryInner {
+    fn get(&self, path: &Path) -> io::Result<VfsMetadata> {
+        match path == Path::new("/") {
+            true => Ok(VfsMetadata {
+                file_type: VfsFileType::Dir,
+                size: 0,
+                modified: None,
+                read_only: false,
+                link_target: None,
+                mode: None,
+            }),
+            false => self
+                .nodes
+                .get(path)
+                .map(MemoryNode::meta)
+                .ok_or_else(|| not_found(path)),
+        }
+    }
+
+    fn check_parent(&self, path: &Path) -> io::Result<()> {
+        let parent = path.parent().ok_or_else(|| not_found(path))?;
+
+        match self.get(parent)?.file_type {
+            VfsFileType::Dir => Ok(()),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{parent:?} isn't a directory"),
+            )),
+        }
+    }
+
+    /// Runs the callbacks watching the path or its parent.
+    /// They run with the lock held, so they must not call back into the VFS
+    fn notify(&mut self, path: &Path) {
+        self.watchers
+            .retain(|(_, on_change)| on_change.strong_count() > 0);
+
+        self.watchers
+            .iter()
+            .filter(|(watched, _)| watched == path || Some(watched.as_path()) == path.parent())
+            .filter_map(|(_, on_change)| on_change.upgrade())
+            .for_each(|on_change| on_change());
+    }
+}
+
+impl MemoryVfs {
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemoryInner> {
+        self.inner.lock().expect("Memory VFS poisoned")
+    }
+}
+
+/// Buffers the written bytes and stores them once flushed or dropped
+struct MemoryWriter {
+    vfs: MemoryVfs,
+    path: PathBuf,
+    buf: Vec<u8>,
+}
+
+impl Write for MemoryWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut inner = self.vfs.lock();
+
+        inner.nodes.insert(
+            self.path.clone(),
+            MemoryNode {
+                contents: Some(self.buf.clone()),
+                modified: SystemTime::now(),
+            },
+        );
+        inner.notify(&self.path);
+
+        Ok(())
+    }
+}
+
+impl Drop for MemoryWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl Vfs for MemoryVfs {
+    fn list(&self, path: &Path) -> io::Result<Vec<VfsEntry>> {
+        let inner = self.lock();
+
+        if !inner.get(path)?.file_type.is_dir() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{path:?} isn't a directory"),
+            ));
+        }
+
+        Ok(inner
+            .nodes
+            .iter()
+            .filter(|(node_path, _)| node_path.parent() == Some(path))
+            .map(|(node_path, node)| VfsEntry {
+                path: node_path.clone(),
+                meta: node.meta(),
+            })
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<VfsMetadata> {
+        self.lock().get(path)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        match self.lock().nodes.get(path).map(|node| &node.contents) {
+            Some(Some(contents)) => Ok(Box::new(Cursor::new(contents.clone()))),
+            Some(None) => Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{path:?} is a directory"),
+            )),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn write(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let inner = self.lock();
+
+        inner.check_parent(path)?;
+        if inner.get(path).is_ok_and(|meta| meta.file_type.is_dir()) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{path:?} is a directory"),
+            ));
+        }
+
+        Ok(Box::new(MemoryWriter {
+            vfs: self.clone(),
+            path: path.to_path_buf(),
+            buf: vec![],
+        }))
+    }
+
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let mut inner = self.lock();
+
+        inner.check_parent(path)?;
+        if inner.get(path).is_ok() {
+            return Err(already_exists(path));
+        }
+
+        // Claimed right away, the contents follow on flush
+        inner.nodes.insert(
+            path.to_path_buf(),
+            MemoryNode {
+                contents: Some(vec![]),
+                modified: SystemTime::now(),
+            },
+        );
+        inner.notify(path);
+
+        Ok(Box::new(MemoryWriter {
+            vfs: self.clone(),
+            path: path.to_path_buf(),
+            buf: vec![],
+        }))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut inner = self.lock();
+
+        inner.get(from)?;
+        inner.check_parent(to)?;
+        if to.starts_with(from) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("Can't move {from:?} into itself"),
+            ));
+        }
+        if inner.get(to).is_ok() {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{to:?} already exists"),
+            ));
+        }
+
+        let moved = inner
+            .nodes
+            .keys()
+            .filter(|path| path.starts_with(from))
+            .cloned()
+            .collect::<Vec<_>>();
+        moved.into_iter().for_each(|path| {
+            if let Some(node) = inner.nodes.remove(&path) {
+                // Joining an empty path would leave a trailing slash
+                let new_path = match path.strip_prefix(from) {
+                    Ok(rest) if !rest.as_os_str().is_empty() => to.join(rest),
+                    _ => to.to_path_buf(),
+                };
+                inner.nodes.insert(new_path, node);
+            }
+        });
+
+        inner.notify(from);
+        inner.notify(to);
+
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let mut inner = self.lock();
+
+        inner.get(path)?;
+        inner
+            .nodes
+            .retain(|node_path, _| !node_path.starts_with(path));
+        inner.notify(path);
+
+        Ok(())
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let mut inner = self.lock();
+
+        inner.check_parent(path)?;
+        if inner.get(path).is_ok() {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{path:?} already exists"),
+            ));
+        }
+
+        inner.nodes.insert(
+            path.to_path_buf(),
+            MemoryNode {
+                contents: None,
+                modified: SystemTime::now(),
+            },
+        );
+        inner.notify(path);
+
+        Ok(())
+    }
+
+    fn watch(&self, path: &Path, on_change: OnChange) -> io::Result<WatchHandle> {
+        let on_change = Arc::new(on_change);
+
+        self.lock()
+            .watchers
+            .push((path.to_path_buf(), Arc::downgrade(&on_change)));
+
+        Ok(WatchHandle::new(on_change))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn write(vfs: &MemoryVfs, path: &str, contents: &str) {
+        vfs.write(Path::new(path))
+            .unwrap()
+            .write_all(contents.as_bytes())
+            .unwrap();
+    }
+
+    fn read(vfs: &MemoryVfs, path: &str) -> String {
+        let mut contents = String::new();
+        vfs.read(Path::new(path))
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    fn names(vfs: &MemoryVfs, path: &str) -> Vec<String> {
+        vfs.list(Path::new(path))
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn writes_and_lists() {
+        let vfs = MemoryVfs::default();
+        vfs.create_dir(Path::new("/dir")).unwrap();
+        write(&vfs, "/dir/a.txt", "hello");
+        write(&vfs, "/b.txt", "world");
+
+        assert_eq!(read(&vfs, "/dir/a.txt"), "hello");
+        assert_eq!(names(&vfs, "/"), ["/b.txt", "/dir"]);
+        assert_eq!(names(&vfs, "/dir"), ["/dir/a.txt"]);
+
+        let meta = vfs.stat(Path::new("/b.txt")).unwrap();
+        assert!(meta.file_type.is_file());
+        assert_eq!(meta.size, 5);
+        assert!(vfs.stat(Path::new("/dir")).unwrap().file_type.is_dir());
+    }
+
+    #[test]
+    fn rejects_bad_paths() {
+        let vfs = MemoryVfs::default();
+        vfs.create_dir(Path::new("/dir")).unwrap();
+        write(&vfs, "/file", "");
+
+        let kind = |res: io::Result<()>| res.unwrap_err().kind();
+        assert_eq!(
+            kind(vfs.create_dir(Path::new("/dir"))),
+            ErrorKind::AlreadyExists
+        );
+        assert_eq!(
+            kind(vfs.create_dir(Path::new("/missing/dir"))),
+            ErrorKind::NotFound
+        );
+        assert_eq!(
+            kind(vfs.create_dir(Path::new("/file/dir"))),
+            ErrorKind::InvalidInput
+        );
+        assert!(vfs.write(Path::new("/dir")).is_err());
+        assert!(vfs.read(Path::new("/dir")).is_err());
+        assert!(vfs.list(Path::new("/file")).is_err());
+    }
+
+    #[test]
+    fn renames_whole_directories() {
+        let vfs = MemoryVfs::default();
+        vfs.create_dir(Path::new("/from")).unwrap();
+        vfs.create_dir(Path::new("/from/sub")).unwrap();
+        write(&vfs, "/from/sub/file", "contents");
+
+        vfs.rename(Path::new("/from"), Path::new("/to")).unwrap();
+
+        assert!(vfs.stat(Path::new("/from")).is_err());
+        assert_eq!(read(&vfs, "/to/sub/file"), "contents");
+        assert_eq!(names(&vfs, "/"), ["/to"]);
+    }
+
+    #[test]
+    fn rename_never_overwrites() {
+        let vfs = MemoryVfs::default();
+        write(&vfs, "/a", "a");
+        write(&vfs, "/b", "b");
+        vfs.create_dir(Path::new("/dir")).unwrap();
+        write(&vfs, "/dir/a", "inner");
+
+        let err = vfs.rename(Path::new("/a"), Path::new("/b")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        let err = vfs.rename(Path::new("/a"), Path::new("/dir")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        let err = vfs
+            .rename(Path::new("/dir"), Path::new("/dir/nested"))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        assert_eq!(read(&vfs, "/a"), "a");
+        assert_eq!(read(&vfs, "/b"), "b");
+        assert_eq!(read(&vfs, "/dir/a"), "inner");
+    }
+
+    #[test]
+    fn removes_recursively() {
+        let vfs = MemoryVfs::default();
+        vfs.create_dir(Path::new("/dir")).unwrap();
+        write(&vfs, "/dir/file", "");
+        write(&vfs, "/dirty", "");
+
+        vfs.remove(Path::new("/dir")).unwrap();
+
+        assert_eq!(names(&vfs, "/"), ["/dirty"]);
+        assert_eq!(
+            vfs.remove(Path::new("/dir")).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn notifies_watchers_until_dropped() {
+        let vfs = MemoryVfs::default();
+        vfs.create_dir(Path::new("/dir")).unwrap();
+
+        let changes = Arc::new(AtomicUsize::new(0));
+        let counter = changes.clone();
+        let handle = vfs
+            .watch(
+                Path::new("/dir"),
+                Box::new(move || {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }),
+            )
+            .unwrap();
+
+        write(&vfs, "/dir/file", "");
+        write(&vfs, "/elsewhere", "");
+        assert_eq!(changes.load(Ordering::Relaxed), 1);
+
+        drop(handle);
+        vfs.remove(Path::new("/dir/file")).unwrap();
+        assert_eq!(changes.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,444 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    thread,
+};
+
+use s3::{creds::Credentials, error::S3Error, Bucket, Region};
+
+use crate::config::{ConnectionProfile, SecretRef};
+
+use super::{
+    already_exists, pipe, OnChange, UploadWriter, Vfs, VfsEntry, VfsFileType, VfsMetadata,
+    WatchHandle,
+};
+
+/// Keys asked for per listing request, the most S3 returns anyway
+const PAGE_SIZE: usize = 1000;
+
+fn s3_err(err: S3Error) -> io::Error {
+    match err {
+        S3Error::HttpFailWithBody(404, body) => io::Error::new(ErrorKind::NotFound, body),
+        S3Error::HttpFailWithBody(403, body) => io::Error::new(ErrorKind::PermissionDenied, body),
+        err => io::Error::other(err),
+    }
+}
+
+fn dir_meta() -> VfsMetadata {
+    VfsMetadata {
+        file_type: VfsFileType::Dir,
+        size: 0,
+        modified: None,
+        read_only: false,
+        link_target: None,
+        mode: None,
+    }
+}
+
+/// Objects in an S3-compatible bucket, e.g. on AWS or MinIO.
+/// Directories are key prefixes ending with `/`, empty ones are kept by a zero-sized marker object
+pub struct S3Vfs {
+    bucket: Box<Bucket>,
+}
+
+impl S3Vfs {
+    pub fn connect(profile: &ConnectionProfile) -> io::Result<Self> {
+        let bucket_name = profile.bucket.as_deref().ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} doesn't name a bucket", profile.name),
+            )
+        })?;
+
+        let region = Region::Custom {
+            region: profile
+                .region
+                .clone()
+                .unwrap_or_else(|| "us-east-1".to_string()),
+            endpoint: profile.host.trim_end_matches('/').to_string(),
+        };
+
+        let secret = profile
+            .secret
+            .as_ref()
+            .map(SecretRef::resolve)
+            .transpose()?;
+        let credentials = Credentials::new(
+            Some(&profile.user)
+                .filter(|user| !user.is_empty())
+                .map(String::as_str),
+            secret.as_deref(),
+            None,
+            None,
+            None,
+        )
+        .map_err(io::Error::other)?;
+
+        // MinIO and most self-hosted servers don't do virtual hosted buckets
+        let bucket = Bucket::new(bucket_name, region, credentials)
+            .map_err(s3_err)?
+            .with_path_style();
+
+        let res = Self {
+            bucket: Box::new(bucket),
+        };
+        // Fail right away on a wrong endpoint or credentials rather than on the first listing
+        res.list_page(Path::new("/"), None, Some(1))?;
+
+        Ok(res)
+    }
+
+    /// Keys have no leading slash, `/` itself is the empty key
+    fn key(path: &Path) -> String {
+        path.to_string_lossy().trim_matches('/').to_string()
+    }
+
+    /// Prefix of every key inside the directory
+    fn prefix(path: &Path) -> String {
+        match Self::key(path) {
+            key if key.is_empty() => key,
+            key => format!("{key}/"),
+        }
+    }
+
+    fn path(key: &str) -> PathBuf {
+        PathBuf::from(format!("/{}", key.trim_end_matches('/')))
+    }
+
+    /// One page of the directory's direct children and the token for the next one
+    fn list_page(
+        &self,
+        path: &Path,
+        continuation_token: Option<String>,
+        max_keys: Option<usize>,
+    ) -> io::Result<(Vec<VfsEntry>, Option<String>)> {
+        let prefix = Self::prefix(path);
+        let (page, _) = self
+            .bucket
+            .list_page(
+                prefix.clone(),
+                Some("/".to_string()),
+                continuation_token,
+                None,
+                max_keys,
+            )
+            .map_err(s3_err)?;
+
+        let dirs = page
+            .common_prefixes
+            .unwrap_or_default()
+            .into_iter()
+            .map(|common_prefix| VfsEntry {
+                path: Self::path(&common_prefix.prefix),
+                meta: dir_meta(),
+            });
+        let files = page
+            .contents
+            .into_iter()
+            // The directory's own marker
+            .filter(|object| object.key != prefix)
+            .map(|object| VfsEntry {
+                path: Self::path(&object.key),
+                meta: VfsMetadata {
+                    file_type: VfsFileType::File,
+                    size: object.size,
+                    modified: humantime::parse_rfc3339(&object.last_modified).ok(),
+                    read_only: false,
+                    link_target: None,
+                    mode: None,
+                },
+            });
+
+        let next = page
+            .is_truncated
+            .then_some(page.next_continuation_token)
+            .flatten();
+
+        Ok((dirs.chain(files).collect(), next))
+    }
+
+    /// Every key below the directory, however deep
+    fn keys_under(&self, path: &Path) -> io::Result<Vec<String>> {
+        let results = self.bucket.list(Self::prefix(path), None).map_err(s3_err)?;
+
+        Ok(results
+            .into_iter()
+            .flat_map(|result| result.contents)
+            .map(|object| object.key)
+            .collect())
+    }
+
+    /// Puts replace objects without asking, so targets are checked first
+    fn check_free(&self, path: &Path) -> io::Result<()> {
+        match self.stat(path) {
+            Ok(_) => Err(already_exists(path)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn copy_key(&self, from: &str, to: &str) -> io::Result<()> {
+        self.bucket
+            .copy_object_internal(from, to)
+            .map_err(s3_err)
+            .map(|_| ())
+    }
+}
+
+impl Vfs for S3Vfs {
+    fn list(&self, path: &Path) -> io::Result<Vec<VfsEntry>> {
+        let mut entries = vec![];
+        self.list_pages(path, &mut |page| {
+            entries.extend(page);
+            true
+        })?;
+
+        Ok(entries)
+    }
+
+    fn list_pages(
+        &self,
+        path: &Path,
+        on_page: &mut dyn FnMut(Vec<VfsEntry>) -> bool,
+    ) -> io::Result<()> {
+        let mut token = None;
+
+        loop {
+            let (page, next) = self.list_page(path, token, Some(PAGE_SIZE))?;
+            if !on_page(page) {
+                return Ok(());
+            }
+
+            match next {
+                Some(next) => token = Some(next),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<VfsMetadata> {
+        let key = Self::key(path);
+        if key.is_empty() {
+            return Ok(dir_meta());
+        }
+
+        if let Ok((head, 200)) = self.bucket.head_object(&key) {
+            return Ok(VfsMetadata {
+                file_type: VfsFileType::File,
+                size: head.content_length.unwrap_or_default().max(0) as u64,
+                modified: head
+                    .last_modified
+                    .and_then(|modified| httpdate::parse_http_date(&modified).ok()),
+                read_only: false,
+                link_target: None,
+                mode: None,
+            });
+        }
+
+        match self.list_page(path, None, Some(1))? {
+            (entries, _) if !entries.is_empty() => Ok(dir_meta()),
+            // An empty directory only has its marker
+            _ if self.bucket.head_object(format!("{key}/")).is_ok() => Ok(dir_meta()),
+            _ => Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("{path:?} doesn't exist"),
+            )),
+        }
+    }
+
+    /// Streams the object through a pipe as it downloads on its own thread
+    fn read(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        let key = Self::key(path);
+        // Fails before anything is streamed, so an error response never reads as the contents
+        if self.stat(path)?.file_type == VfsFileType::Dir {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is a directory", path.display()),
+            ));
+        }
+
+        let bucket = self.bucket.clone();
+        let (mut writer, reader) = pipe();
+        thread::spawn(move || {
+            let result = match bucket.get_object_to_writer(&key, &mut writer) {
+                Ok(200) => Ok(()),
+                Ok(status) => Err(io::Error::other(format!(
+                    "Downloading {key} failed with status {status}"
+                ))),
+                Err(err) => Err(s3_err(err)),
+            };
+            writer.close(result);
+        });
+
+        Ok(Box::new(reader))
+    }
+
+    fn write(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let bucket = self.bucket.clone();
+        let key = Self::key(path);
+
+        Ok(Box::new(UploadWriter::new(move |mut reader| match bucket
+            .put_object_stream(&mut reader, &key)
+            .map_err(s3_err)?
+        {
+            200 => Ok(()),
+            status => Err(io::Error::other(format!(
+                "Uploading {key} failed with status {status}"
+            ))),
+        })))
+    }
+
+    /// S3 can't rename, every object is copied to its new key and removed. The target is only
+    /// checked to be free beforehand. Stops at the first failure, whose error lists the objects
+    /// that were already moved
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.check_free(to)?;
+
+        let (from_key, to_key) = (Self::key(from), Self::key(to));
+        let keys = match self.stat(from)?.file_type {
+            VfsFileType::Dir => self.keys_under(from)?,
+            _ => vec![from_key.clone()],
+        };
+
+        let mut moved = vec![];
+        for key in keys {
+            let target = format!("{to_key}{}", &key[from_key.len()..]);
+            let result = self
+                .copy_key(&key, &target)
+                .and_then(|()| self.bucket.delete_object(&key).map_err(s3_err).map(|_| ()));
+
+            match result {
+                Ok(()) => moved.push(key),
+                Err(err) if moved.is_empty() => return Err(err),
+                Err(err) => {
+                    return Err(io::Error::new(
+                        err.kind(),
+                        format!(
+                            "Failed to move {key}: {err}. Already moved to {to_key}: {}",
+                            moved.join(", ")
+                        ),
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        match self.stat(path)?.file_type {
+            VfsFileType::Dir => self
+                .keys_under(path)?
+                .into_iter()
+                .try_for_each(|key| self.bucket.delete_object(&key).map_err(s3_err).map(|_| ())),
+            _ => self
+                .bucket
+                .delete_object(Self::key(path))
+                .map_err(s3_err)
+                .map(|_| ()),
+        }
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        // The marker object would be put over an existing directory without complaint
+        self.check_free(path)?;
+
+        self.bucket
+            .put_object(Self::prefix(path), &[])
+            .map_err(s3_err)
+            .map(|_| ())
+    }
+
+    fn watch(&self, _path: &Path, _on_change: OnChange) -> io::Result<WatchHandle> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "S3 can't watch for changes",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_paths_to_keys() {
+        assert_eq!(S3Vfs::key(Path::new("/")), "");
+        assert_eq!(
+            S3Vfs::key(Path::new("/photos/2024/a.jpg")),
+            "photos/2024/a.jpg"
+        );
+        assert_eq!(S3Vfs::prefix(Path::new("/")), "");
+        assert_eq!(S3Vfs::prefix(Path::new("/photos/2024")), "photos/2024/");
+        assert_eq!(S3Vfs::path("photos/2024/"), PathBuf::from("/photos/2024"));
+        assert_eq!(S3Vfs::path("photos/a.jpg"), PathBuf::from("/photos/a.jpg"));
+    }
+
+    #[test]
+    fn maps_http_errors() {
+        let kind = |status| s3_err(S3Error::HttpFailWithBody(status, String::new())).kind();
+        assert_eq!(kind(404), ErrorKind::NotFound);
+        assert_eq!(kind(403), ErrorKind::PermissionDenied);
+        assert_eq!(kind(500), ErrorKind::Other);
+    }
+
+    /// Runs against a local MinIO, e.g.
+    /// `docker run -p 9000:9000 minio/minio server /data` with a bucket made in its console and
+    /// `RSFM_TEST_S3=http://127.0.0.1:9000 RSFM_TEST_S3_BUCKET=rsfm RSFM_TEST_S3_USER=minioadmin
+    /// RSFM_TEST_S3_SECRET=minioadmin cargo test -- --ignored`
+    #[test]
+    #[ignore = "needs an S3 server, see RSFM_TEST_S3"]
+    fn round_trips_through_minio() {
+        let var = |name| std::env::var(name).unwrap_or_else(|_| panic!("${name} isn't set"));
+        let profile = ConnectionProfile {
+            name: "minio".to_string(),
+            kind: crate::config::ConnectionKind::S3,
+            host: var("RSFM_TEST_S3"),
+            port: 9000,
+            user: var("RSFM_TEST_S3_USER"),
+            key: None,
+            passphrase: None,
+            secret: Some(SecretRef::Env("RSFM_TEST_S3_SECRET".to_string())),
+            path: None,
+            bucket: Some(var("RSFM_TEST_S3_BUCKET")),
+            region: None,
+        };
+        let vfs = S3Vfs::connect(&profile).unwrap();
+
+        let dir = PathBuf::from(format!("/rsfm-test-{}", std::process::id()));
+        vfs.create_dir(&dir).unwrap();
+        assert_eq!(
+            vfs.create_dir(&dir).unwrap_err().kind(),
+            ErrorKind::AlreadyExists
+        );
+        assert!(vfs.list(&dir).unwrap().is_empty());
+
+        let file = dir.join("a.txt");
+        let mut writer = vfs.create_new(&file).unwrap();
+        writer.write_all(b"contents").unwrap();
+        writer.flush().unwrap();
+        assert!(vfs.create_new(&file).is_err());
+
+        let mut read = String::new();
+        vfs.read(&file).unwrap().read_to_string(&mut read).unwrap();
+        assert_eq!(read, "contents");
+        assert_eq!(vfs.stat(&file).unwrap().size, 8);
+
+        let renamed = dir.join("sub").join("b.txt");
+        vfs.rename(&file, &renamed).unwrap();
+        let listed = vfs
+            .list(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.path, entry.meta.file_type.is_dir()))
+            .collect::<Vec<_>>();
+        assert_eq!(listed, [(dir.join("sub"), true)]);
+        assert_eq!(
+            vfs.stat(&file).err().map(|err| err.kind()),
+            Some(ErrorKind::NotFound)
+        );
+
+        vfs.remove(&dir).unwrap();
+        assert!(vfs.stat(&dir).is_err());
+    }
+}
@@ -0,0 +1,516 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use quick_xml::{events::Event, Reader};
+use reqwest::{
+    blocking::{Body, Client, RequestBuilder, Response},
+    header, Method, StatusCode, Url,
+};
+
+use crate::config::{ConnectionProfile, SecretRef};
+
+use super::{OnChange, UploadWriter, Vfs, VfsEntry, VfsFileType, VfsMetadata, WatchHandle};
+
+/// Characters escaped inside a path segment
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop><d:resourcetype/><d:getcontentlength/><d:getlastmodified/></d:prop>
+</d:propfind>"#;
+
+fn other_err(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::other(err)
+}
+
+/// Files on a WebDAV share, e.g. Nextcloud or Apache's mod_dav
+pub struct WebDavVfs {
+    client: Client,
+    /// Share URL without the trailing slash
+    base: String,
+    /// Decoded path part of `base`, hrefs in responses start with it
+    base_path: String,
+    user: String,
+    password: Option<String>,
+}
+
+impl WebDavVfs {
+    pub fn connect(profile: &ConnectionProfile) -> io::Result<Self> {
+        let base = profile.host.trim_end_matches('/').to_string();
+        let url = Url::parse(&base)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidInput, format!("{base:?}: {err}")))?;
+
+        let res = Self {
+            client: Client::builder().build().map_err(other_err)?,
+            base_path: percent_decode_str(url.path().trim_end_matches('/'))
+                .decode_utf8_lossy()
+                .to_string(),
+            base,
+            user: profile.user.clone(),
+            password: profile
+                .secret
+                .as_ref()
+                .map(SecretRef::resolve)
+                .transpose()?,
+        };
+        // Fail right away on a wrong URL or credentials rather than on the first listing
+        res.stat(Path::new("/"))?;
+
+        Ok(res)
+    }
+
+    fn url(&self, path: &Path) -> String {
+        let segments = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(segment) => {
+                    Some(utf8_percent_encode(&segment.to_string_lossy(), SEGMENT).to_string())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        format!("{}/{}", self.base, segments.join("/"))
+    }
+
+    fn request(&self, method: Method, path: &Path) -> RequestBuilder {
+        with_auth(
+            self.client.request(method, self.url(path)),
+            &self.user,
+            self.password.as_ref(),
+        )
+    }
+
+    fn propfind(&self, path: &Path, depth: &str) -> io::Result<Vec<VfsEntry>> {
+        let method = Method::from_bytes(b"PROPFIND").expect("PROPFIND is a valid method");
+
+        let body = send(
+            self.request(method, path)
+                .header("Depth", depth)
+                .header(header::CONTENT_TYPE, "application/xml")
+                .body(PROPFIND_BODY),
+        )?
+        .text()
+        .map_err(other_err)?;
+
+        self.parse_multistatus(&body)
+    }
+
+    /// Entries of a `207 Multi-Status` response, namespace prefixes are ignored
+    fn parse_multistatus(&self, xml: &str) -> io::Result<Vec<VfsEntry>> {
+        let mut reader = Reader::from_str(xml);
+        let mut entries = vec![];
+        let mut current: Option<(String, VfsMetadata)> = None;
+        // Element whose text is being read
+        let mut element = vec![];
+
+        loop {
+            match reader.read_event().map_err(other_err)? {
+                Event::Start(start) | Event::Empty(start) => match start.local_name().as_ref() {
+                    b"response" => {
+                        current = Some((
+                            String::new(),
+                            VfsMetadata {
+                                file_type: VfsFileType::File,
+                                size: 0,
+                                modified: None,
+                                read_only: false,
+                                link_target: None,
+                                mode: None,
+                            },
+                        ))
+                    }
+                    b"collection" => {
+                        if let Some((_, meta)) = &mut current {
+                            meta.file_type = VfsFileType::Dir;
+                        }
+                    }
+                    name => element = name.to_vec(),
+                },
+                Event::Text(text) => {
+                    let Some((href, meta)) = &mut current else {
+                        continue;
+                    };
+                    let text = text.unescape().map_err(other_err)?;
+
+                    match element.as_slice() {
+                        b"href" => *href = text.trim().to_string(),
+                        b"getcontentlength" => meta.size = text.trim().parse().unwrap_or_default(),
+                        b"getlastmodified" => meta.modified = httpdate::parse_http_date(&text).ok(),
+                        _ => {}
+                    }
+                }
+                Event::End(end) => {
+                    if end.local_name().as_ref() == b"response" {
+                        if let Some((href, meta)) = current.take() {
+                            entries.push(VfsEntry {
+                                path: self.href_path(&href),
+                                meta,
+                            });
+                        }
+                    }
+                    element.clear();
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Hrefs are either absolute URLs or absolute paths on the server
+    fn href_path(&self, href: &str) -> PathBuf {
+        let path = match Url::parse(href) {
+            Ok(url) => url.path().to_string(),
+            Err(_) => href.to_string(),
+        };
+        let path = percent_decode_str(&path).decode_utf8_lossy();
+        let path = path.strip_prefix(&self.base_path).unwrap_or(path.as_ref());
+
+        PathBuf::from(format!("/{}", path.trim_matches('/')))
+    }
+}
+
+fn with_auth(builder: RequestBuilder, user: &str, password: Option<&String>) -> RequestBuilder {
+    match user.is_empty() {
+        true => builder,
+        false => builder.basic_auth(user, password),
+    }
+}
+
+fn send(builder: RequestBuilder) -> io::Result<Response> {
+    check(builder.send().map_err(other_err)?)
+}
+
+fn check(response: Response) -> io::Result<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let url = response.url();
+    let kind = match status {
+        StatusCode::NOT_FOUND => ErrorKind::NotFound,
+        // Read-only shares answer writes with 405
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::METHOD_NOT_ALLOWED => {
+            ErrorKind::PermissionDenied
+        }
+        // MOVE with `Overwrite: F` or PUT with `If-None-Match: *` onto an existing target
+        StatusCode::PRECONDITION_FAILED => ErrorKind::AlreadyExists,
+        _ => ErrorKind::Other,
+    };
+    Err(io::Error::new(kind, format!("{url}: {status}")))
+}
+
+impl Vfs for WebDavVfs {
+    fn list(&self, path: &Path) -> io::Result<Vec<VfsEntry>> {
+        Ok(self
+            .propfind(path, "1")?
+            .into_iter()
+            .filter(|entry| entry.path != path)
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<VfsMetadata> {
+        self.propfind(path, "0")?
+            .into_iter()
+            .next()
+            .map(|entry| entry.meta)
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, format!("{path:?} doesn't exist")))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(send(self.request(Method::GET, path))?))
+    }
+
+    fn write(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let client = self.client.clone();
+        let url = self.url(path);
+        let (user, password) = (self.user.clone(), self.password.clone());
+
+        Ok(Box::new(UploadWriter::new(move |reader| {
+            let request = client.put(&url).body(Body::new(reader));
+            send(with_auth(request, &user, password.as_ref())).map(|_| ())
+        })))
+    }
+
+    /// `If-None-Match: *` makes the server refuse the upload when the file exists
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let client = self.client.clone();
+        let url = self.url(path);
+        let (user, password) = (self.user.clone(), self.password.clone());
+
+        Ok(Box::new(UploadWriter::new(move |reader| {
+            let request = client
+                .put(&url)
+                .header(header::IF_NONE_MATCH, "*")
+                .body(Body::new(reader));
+            send(with_auth(request, &user, password.as_ref())).map(|_| ())
+        })))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let method = Method::from_bytes(b"MOVE").expect("MOVE is a valid method");
+
+        send(
+            self.request(method, from)
+                .header("Destination", self.url(to))
+                .header("Overwrite", "F"),
+        )
+        .map(|_| ())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        send(self.request(Method::DELETE, path)).map(|_| ())
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        let method = Method::from_bytes(b"MKCOL").expect("MKCOL is a valid method");
+        let response = self.request(method, path).send().map_err(other_err)?;
+
+        // MKCOL is only allowed where nothing is mapped yet
+        if response.status() == StatusCode::METHOD_NOT_ALLOWED {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{path:?} already exists"),
+            ));
+        }
+        check(response).map(|_| ())
+    }
+
+    fn watch(&self, _path: &Path, _on_change: OnChange) -> io::Result<WatchHandle> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "WebDAV can't watch for changes",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        thread,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::*;
+
+    fn share(base: &str) -> WebDavVfs {
+        let url = Url::parse(base).unwrap();
+
+        WebDavVfs {
+            client: Client::new(),
+            base: base.trim_end_matches('/').to_string(),
+            base_path: url.path().trim_end_matches('/').to_string(),
+            user: String::new(),
+            password: None,
+        }
+    }
+
+    /// A share on a local server that answers every request with the status `respond` picks
+    /// for its method
+    fn serve(respond: impl Fn(&str) -> u16 + Send + 'static) -> WebDavVfs {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}/dav", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut head = vec![];
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    head.push(line.trim().to_lowercase());
+                }
+
+                // Read the body so the client never sees its upload cut off
+                let length = head
+                    .iter()
+                    .find_map(|line| line.strip_prefix("content-length: "))
+                    .map_or(0, |length| length.parse().unwrap());
+                stream.read_exact(&mut vec![0; length]).unwrap();
+                if head.iter().any(|line| line == "transfer-encoding: chunked") {
+                    let mut body = vec![];
+                    while !body.ends_with(b"0\r\n\r\n") {
+                        stream.read_until(b'\n', &mut body).unwrap();
+                    }
+                }
+
+                let method = head[0].split(' ').next().unwrap().to_uppercase();
+                let status = respond(&method);
+                write!(
+                    stream.get_mut(),
+                    "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap();
+            }
+        });
+
+        share(&base)
+    }
+
+    #[test]
+    fn parses_multistatus_responses() {
+        let vfs = share("https://cloud.example.com/remote.php/dav/files/alice");
+        let xml = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response>
+    <d:href>/remote.php/dav/files/alice/Documents/</d:href>
+    <d:propstat><d:prop>
+      <d:resourcetype><d:collection/></d:resourcetype>
+      <d:getlastmodified>Tue, 01 Oct 2024 10:00:00 GMT</d:getlastmodified>
+    </d:prop></d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/remote.php/dav/files/alice/Documents/My%20Notes.txt</d:href>
+    <d:propstat><d:prop>
+      <d:resourcetype/>
+      <d:getcontentlength>1234</d:getcontentlength>
+      <d:getlastmodified>Tue, 01 Oct 2024 10:00:00 GMT</d:getlastmodified>
+    </d:prop></d:propstat>
+  </d:response>
+  <d:response>
+    <d:href>/remote.php/dav/files/alice/Documents/Photos/</d:href>
+    <d:propstat><d:prop>
+      <d:resourcetype><d:collection/></d:resourcetype>
+    </d:prop></d:propstat>
+  </d:response>
+</d:multistatus>"#;
+
+        let entries = vfs.parse_multistatus(xml).unwrap();
+        let summary = entries
+            .iter()
+            .map(|entry| (entry.path.clone(), entry.meta.file_type, entry.meta.size))
+            .collect::<Vec<_>>();
+        assert!(
+            summary
+                == [
+                    (PathBuf::from("/Documents"), VfsFileType::Dir, 0),
+                    (
+                        PathBuf::from("/Documents/My Notes.txt"),
+                        VfsFileType::File,
+                        1234
+                    ),
+                    (PathBuf::from("/Documents/Photos"), VfsFileType::Dir, 0),
+                ]
+        );
+        assert_eq!(
+            entries[1].meta.modified,
+            Some(UNIX_EPOCH + Duration::from_secs(1_727_776_800))
+        );
+        assert_eq!(entries[2].meta.modified, None);
+    }
+
+    #[test]
+    fn ignores_namespace_prefixes() {
+        // Apache's mod_dav names its own namespace and answers with full URLs
+        let vfs = share("http://files.example.com/dav");
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:multistatus xmlns:D="DAV:" xmlns:lp1="DAV:">
+  <D:response>
+    <D:href>http://files.example.com/dav/notes.md</D:href>
+    <D:propstat><D:prop>
+      <lp1:resourcetype/>
+      <lp1:getcontentlength>42</lp1:getcontentlength>
+    </D:prop></D:propstat>
+  </D:response>
+</D:multistatus>"#;
+
+        let entries = vfs.parse_multistatus(xml).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, PathBuf::from("/notes.md"));
+        assert!(entries[0].meta.file_type == VfsFileType::File);
+        assert_eq!(entries[0].meta.size, 42);
+
+        assert!(vfs.parse_multistatus("<d:multistatus").is_err());
+    }
+
+    #[test]
+    fn maps_hrefs_to_paths() {
+        let vfs = share("https://cloud.example.com/remote.php/dav/files/alice");
+        assert_eq!(
+            vfs.href_path("/remote.php/dav/files/alice/"),
+            PathBuf::from("/")
+        );
+        assert_eq!(
+            vfs.href_path("/remote.php/dav/files/alice/a%20b/c%23d.txt"),
+            PathBuf::from("/a b/c#d.txt")
+        );
+        assert_eq!(
+            vfs.href_path("https://cloud.example.com/remote.php/dav/files/alice/dir/"),
+            PathBuf::from("/dir")
+        );
+
+        let vfs = share("http://files.example.com");
+        assert_eq!(vfs.href_path("/dir/file"), PathBuf::from("/dir/file"));
+        assert_eq!(
+            vfs.url(Path::new("/a b/c#d")),
+            "http://files.example.com/a%20b/c%23d"
+        );
+    }
+
+    #[test]
+    fn reads_405_as_existing_only_for_mkcol() {
+        // A read-only share refuses every write
+        let vfs = serve(|_| 405);
+
+        let err = vfs.create_dir(Path::new("/dir")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        let err = vfs.remove(Path::new("/file")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        let err = vfs
+            .rename(Path::new("/file"), Path::new("/other"))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+
+        let mut writer = vfs.create_new(Path::new("/file")).unwrap();
+        let err = writer.flush().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn maps_statuses_to_error_kinds() {
+        let vfs = serve(|method| match method {
+            "MOVE" | "PUT" => 412,
+            "DELETE" => 404,
+            _ => 500,
+        });
+
+        let err = vfs
+            .rename(Path::new("/file"), Path::new("/other"))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        let mut writer = vfs.create_new(Path::new("/file")).unwrap();
+        writer.write_all(b"contents").unwrap();
+        let err = writer.flush().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+
+        let err = vfs.remove(Path::new("/file")).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        let Err(err) = vfs.stat(Path::new("/file")) else {
+            panic!("A server error isn't metadata");
+        };
+        assert_eq!(err.kind(), ErrorKind::Other);
+    }
+}